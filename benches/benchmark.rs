@@ -25,6 +25,15 @@ fn get<D: Database, H: Hasher>(
     });
 }
 
+fn gets<D: Database, H: Hasher>(
+    tree: &mut Monotree<D, H>,
+    root: Option<Hash>,
+    keys: &mut [Hash],
+    _leaves: &[Hash],
+) {
+    tree.gets(root.as_ref(), keys).expect("gets");
+}
+
 fn remove<D: Database, H: Hasher>(
     tree: &mut Monotree<D, H>,
     root: Option<Hash>,
@@ -34,6 +43,54 @@ fn remove<D: Database, H: Hasher>(
     tree.removes(root.as_ref(), keys).expect("remove");
 }
 
+fn bench_verify_proofs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify_proofs_10000");
+    let hasher = Blake3::new();
+    let mut tree = Monotree::<MemoryDB, Blake3>::new("verify");
+    let keys = random_hashes(10000);
+    let root = tree.inserts(None, &keys, &keys).expect("inserts");
+    let proofs: Vec<Proof> = keys
+        .iter()
+        .map(|key| {
+            tree.get_merkle_proof(root.as_ref(), key)
+                .expect("get_merkle_proof")
+                .expect("proof")
+        })
+        .collect();
+    let items: Vec<(Hash, &Proof)> = keys.iter().cloned().zip(proofs.iter()).collect();
+
+    group.bench_function("verify_proof", |b| {
+        b.iter(|| {
+            items
+                .iter()
+                .map(|(leaf, proof)| {
+                    verify_proof(black_box(&hasher), root.as_ref(), leaf, Some(proof))
+                })
+                .collect::<Vec<_>>()
+        })
+    });
+    group.bench_function("verify_proofs", |b| {
+        b.iter(|| verify_proofs(black_box(&hasher), root.as_ref(), black_box(&items)))
+    });
+    group.finish();
+}
+
+fn bench_reinserts(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reinserts_10000");
+    let mut tree = Monotree::<MemoryDB, Blake3>::new("reinserts");
+    let keys = random_hashes(10000);
+    let root = tree.inserts(None, &keys, &keys).expect("inserts");
+
+    group.bench_function("reinsert_unchanged", |b| {
+        b.iter(|| {
+            tree.inserts(black_box(root.as_ref()), black_box(&keys), black_box(&keys))
+                .expect("inserts")
+        })
+    });
+    assert_eq!(tree.last_op_writes(), 0);
+    group.finish();
+}
+
 macro_rules! impl_bench_group {
     ($n:expr) => {
         paste::item_with_macros! {
@@ -52,7 +109,7 @@ macro_rules! impl_bench_group {
                         ("sha2", Sha2),
                         ("sha3", Sha3)
                     ],
-                    [insert, get, remove]
+                    [insert, get, gets, remove]
                 );
                 group.finish();
             }
@@ -96,7 +153,7 @@ macro_rules! impl_bench_fn {
             let mut root: Option<Hash> = None;
             let mut keys = $k.clone();
             root = match stringify!($fn) {
-                "get" | "remove" => insert(
+                "get" | "gets" | "remove" => insert(
                     black_box(&mut tree),
                     black_box(root),
                     black_box(&mut keys),
@@ -126,6 +183,8 @@ criterion_group!(
     bench_group_10,
     bench_group_100,
     bench_group_1000,
-    bench_group_10000
+    bench_group_10000,
+    bench_verify_proofs,
+    bench_reinserts
 );
 criterion_main!(benches);
@@ -8,7 +8,7 @@ fn main() -> Result<()> {
     // manually select a db and a hasher as your preference
     // Monotree::<DATABASE, HASHER>::new(DB_PATH)
     // where DATABASE = {MemoryDB, RocksDB, Sled}
-    //         HASHER = {Blake3, Blake2s, Blake2b, Sha2, Sha3}
+    //         HASHER = {Blake3, Blake2s, Blake2b, Sha2, Sha512_256, Sha3, Keccak256}
     let mut tree = Monotree::<RocksDB, Blake2b>::new("/tmp/monotree");
 
     // It is natural the tree root initially has 'None'
@@ -0,0 +1,34 @@
+use monotree::database::MemoryDB;
+use monotree::hasher::*;
+use monotree::utils::*;
+use monotree::*;
+
+fn main() -> Result<()> {
+    // Compare the size of proofs encoded by 'encode_proof_bytes()' and
+    // 'encode_compact_proof_bytes()', averaged over sampled keys of a tree of 1M entries.
+    // Run in release mode: cargo run --release --example proof_size
+    let n = 1_000_000;
+    let samples = 10_000;
+    let mut tree = Monotree::<MemoryDB, Blake3>::new("monotree");
+    let keys = random_hashes(n);
+    let root = tree.inserts(None, &keys, &keys)?;
+
+    let (mut plain, mut compact) = (0, 0);
+    for key in keys.iter().take(samples) {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?.expect("proof");
+        plain += encode_proof_bytes(&proof).len();
+        let proof = tree
+            .get_compact_proof(root.as_ref(), key)?
+            .expect("compact proof");
+        compact += encode_compact_proof_bytes(&proof).len();
+    }
+    let (plain, compact) = (plain / samples, compact / samples);
+    println!("entries: {}, proofs sampled: {}", n, samples);
+    println!("Proof:        {} bytes on average", plain);
+    println!("CompactProof: {} bytes on average", compact);
+    println!(
+        "reduced by {:.1}%",
+        100.0 * (plain - compact) as f64 / plain as f64
+    );
+    Ok(())
+}
@@ -1,6 +1,7 @@
 //! A module for representing `BitVec` in terms of bytes slice.
 use crate::utils::*;
 use crate::*;
+use std::fmt;
 use std::ops::Range;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -30,13 +31,49 @@ impl<'a> Bits<'a> {
     }
 
     /// Serialize `Bits` into bytes.
+    ///
+    /// The bits out of range in `path` are masked with zeros,
+    /// so that the same bits in the same range are always serialized into the same bytes.
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
-        Ok([
-            &self.range.start.to_be_bytes(),
-            &self.range.end.to_be_bytes(),
-            &self.path[..],
-        ]
-        .concat())
+        let (start, end) = (self.range.start, self.range.end);
+        let mut path = self.path[..nbytes_across(start, end) as usize].to_vec();
+        if let Some(first) = path.first_mut() {
+            *first &= 0xff >> (start % 8);
+        }
+        if let Some(last) = path.last_mut() {
+            if end % 8 != 0 {
+                *last &= 0xff << (8 - end % 8);
+            }
+        }
+        Ok([&start.to_be_bytes(), &end.to_be_bytes(), &path[..]].concat())
+    }
+
+    /// Serialize `Bits` into bytes in compact form: the length followed by
+    /// the bits in range only, which are packed from the very first bit.
+    pub fn to_compact_bytes(&self) -> Result<Vec<u8>> {
+        let packed = slicebit_to_bytes(&self.to_bits(), 0);
+        Ok([&self.len().to_be_bytes()[..], &packed].concat())
+    }
+
+    /// Get the bits in range as a Vec of `bool`.
+    pub fn to_bits(&self) -> Vec<bool> {
+        bytes_to_slicebit(self.path, &self.range)
+    }
+
+    /// Get the bits in range as a string of binary digits, e.g., `"0b1011"`.
+    pub fn to_bit_string(&self) -> String {
+        let digits: String = self
+            .to_bits()
+            .iter()
+            .map(|&bit| if bit { '1' } else { '0' })
+            .collect();
+        format!("0b{}", digits)
+    }
+
+    /// Get the bits in range followed by the ones of `other`, as a Vec of `bool`.
+    /// Useful to rebuild a key from segments of path along with `slicebit_to_bytes()`.
+    pub fn concat(&self, other: &Bits) -> Vec<bool> {
+        [self.to_bits(), other.to_bits()].concat()
     }
 
     /// Get the very first bit.
@@ -44,6 +81,20 @@ impl<'a> Bits<'a> {
         bit(&self.path, self.range.start)
     }
 
+    /// Get the `i`-th bit in range, or `None` if out of range. Index `i` starts from 0.
+    pub fn get(&self, i: BitsLen) -> Option<bool> {
+        if i < self.len() {
+            Some(bit(self.path, self.range.start + i))
+        } else {
+            None
+        }
+    }
+
+    /// Get an iterator over the bits in range.
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        self.range.clone().map(move |i| bit(self.path, i))
+    }
+
     pub fn len(&self) -> BitsLen {
         self.range.end - self.range.start
     }
@@ -73,3 +124,73 @@ impl<'a> Bits<'a> {
         len_lcp(&a.path, &a.range, &b.path, &b.range)
     }
 }
+
+impl fmt::Display for Bits<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_bit_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_to_bit_string() {
+        let bytes = [0x33, 0x33];
+        let bits = Bits::new(&bytes);
+        assert_eq!(bits.to_bit_string(), "0b0011001100110011");
+        assert_eq!(bits.shift(3, false).to_bit_string(), "0b1001100110011");
+        assert_eq!(bits.shift(10, true).to_string(), "0b0011001100");
+        assert_eq!(bits.shift(16, false).to_bit_string(), "0b");
+    }
+
+    #[test]
+    fn test_get_and_iter() {
+        let bytes = [0xa5, 0x0f];
+        let bits = Bits::new(&bytes).shift(4, false);
+        let expected = [0, 1, 0, 1, 0, 0, 0, 0, 1, 1, 1, 1];
+        let expected: Vec<bool> = expected.iter().map(|&b| b == 1).collect();
+        assert_eq!(bits.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), bits.to_bits());
+        for (i, &b) in expected.iter().enumerate() {
+            assert_eq!(bits.get(i as BitsLen), Some(b));
+        }
+        assert_eq!(bits.get(12), None);
+        let bits = bits.shift(6, true);
+        assert_eq!(bits.iter().collect::<Vec<_>>(), expected[..6].to_vec());
+        assert_eq!(bits.get(6), None);
+    }
+
+    #[test]
+    fn test_bytes_roundtrip() {
+        let bytes = random_bytes(1024);
+        let bits = Bits::new(&bytes).shift(13, false).shift(8000, true);
+        let serialized = bits.to_bytes().unwrap();
+        assert_eq!(Bits::from_bytes(&serialized).to_bits(), bits.to_bits());
+        assert_eq!(Bits::from_bytes(&serialized).range, bits.range);
+    }
+
+    #[cfg(feature = "bitslen_u32")]
+    #[test]
+    fn test_bytes_roundtrip_beyond_u16() {
+        let bytes = random_bytes(10 * 1024);
+        let bits = Bits::new(&bytes).shift(3, false).shift(70_000, true);
+        assert!(bits.range.end > u16::MAX as BitsLen);
+        let serialized = bits.to_bytes().unwrap();
+        assert_eq!(Bits::from_bytes(&serialized).range, bits.range);
+        assert_eq!(Bits::from_bytes(&serialized).to_bits(), bits.to_bits());
+    }
+
+    #[test]
+    fn test_concat() {
+        let bytes = [0x33, 0x33];
+        let bits = Bits::new(&bytes);
+        let (head, tail) = (bits.shift(5, true), bits.shift(5, false));
+        assert_eq!(head.concat(&tail), bits.to_bits());
+        assert_eq!(slicebit_to_bytes(&head.concat(&tail), 0), bytes);
+        assert_eq!(
+            tail.concat(&head),
+            bytes_to_slicebit(&[0x66, 0x66], &(0..16))
+        );
+    }
+}
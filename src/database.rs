@@ -1,148 +1,788 @@
 //! A module for implementing database supporting `monotree`.
 use crate::*;
 use hashbrown::HashMap;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::sync::{Arc, RwLock};
 
-use utils::*;
-
-#[cfg(any(feature = "db_rocksdb", feature = "db_sled"))]
+#[cfg(any(
+    feature = "db_rocksdb",
+    feature = "db_sled",
+    feature = "db_redb",
+    feature = "db_lmdb"
+))]
 mod cache {
     use super::*;
-    use std::collections::HashSet;
+    use std::collections::{BTreeMap, HashSet};
+    use std::sync::{Arc, Mutex};
+
+    /// The number of node blobs read kept in a cache unless set by `set_capacity()`.
+    pub(crate) const DEFAULT_CACHE_CAPACITY: usize = 1 << 16;
+
+    /// A map evicting the least recently used entries once it holds more than its capacity.
+    struct Lru {
+        capacity: usize,
+        tick: u64,
+        map: HashMap<Vec<u8>, (Vec<u8>, u64)>,
+        order: BTreeMap<u64, Vec<u8>>,
+    }
+
+    impl Lru {
+        fn new(capacity: usize) -> Self {
+            Lru {
+                capacity,
+                tick: 0,
+                map: HashMap::new(),
+                order: BTreeMap::new(),
+            }
+        }
 
+        fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+            self.tick += 1;
+            let (value, used) = self.map.get_mut(key)?;
+            let key = self.order.remove(used).expect("get(): lru");
+            self.order.insert(self.tick, key);
+            *used = self.tick;
+            Some(value.to_owned())
+        }
+
+        fn insert(&mut self, key: &[u8], value: Vec<u8>) {
+            self.remove(key);
+            if self.capacity == 0 {
+                return;
+            }
+            self.tick += 1;
+            self.map.insert(key.to_vec(), (value, self.tick));
+            self.order.insert(self.tick, key.to_vec());
+            self.evict();
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            if let Some((_, used)) = self.map.remove(key) {
+                self.order.remove(&used);
+            }
+        }
+
+        fn evict(&mut self) {
+            while self.map.len() > self.capacity {
+                let (&used, _) = self.order.iter().next().expect("evict(): lru");
+                let key = self.order.remove(&used).expect("evict(): lru");
+                self.map.remove(&key);
+            }
+        }
+
+        fn clear(&mut self) {
+            self.map.clear();
+            self.order.clear();
+        }
+    }
+
+    /// A read cache of node blobs.
+    ///
+    /// Since nodes are content-addressed, the blobs read (`lru`) can be safely shared
+    /// among cursors on the same database, while deletions (`set`) stay per-cursor.
+    /// The blobs read are bounded, evicting the least recently used ones.
+    /// Blobs written in batch mode (`map`) are kept per-cursor until the batch is finished,
+    /// since they are not readable from the database until then.
     pub(crate) struct MemCache {
-        set: HashSet<Hash>,
-        map: HashMap<Hash, Vec<u8>>,
+        set: HashSet<Vec<u8>>,
+        map: HashMap<Vec<u8>, Vec<u8>>,
+        lru: Arc<Mutex<Lru>>,
+        batch_on: bool,
     }
 
     impl MemCache {
         pub(crate) fn new() -> Self {
             MemCache {
                 set: HashSet::new(),
-                map: HashMap::with_capacity(1 << 12),
+                map: HashMap::new(),
+                lru: Arc::new(Mutex::new(Lru::new(DEFAULT_CACHE_CAPACITY))),
+                batch_on: false,
             }
         }
 
+        /// Get a new cache sharing the blobs read with this one.
+        pub(crate) fn share(&self) -> Self {
+            MemCache {
+                set: HashSet::new(),
+                map: HashMap::new(),
+                lru: Arc::clone(&self.lru),
+                batch_on: false,
+            }
+        }
+
+        /// Set the maximum number of blobs read kept, evicting the ones beyond it at once.
+        pub(crate) fn set_capacity(&mut self, capacity: usize) {
+            let mut lru = self.lru.lock().expect("set_capacity(): cache");
+            lru.capacity = capacity;
+            lru.evict();
+        }
+
+        /// Clear the cache, then keep the blobs written from now on until `finish_batch()`.
+        pub(crate) fn init_batch(&mut self) {
+            self.set.clear();
+            self.map.clear();
+            if Arc::strong_count(&self.lru) == 1 {
+                self.lru.lock().expect("init_batch(): cache").clear();
+            }
+            self.batch_on = true;
+        }
+
+        /// Hand the blobs written in batch mode over to the ones read, once the batch is written
+        /// to the database. Deleted keys are then absent from the database, so their tombstones
+        /// are dropped. If the batch failed to be written, `discard_batch()` instead.
+        pub(crate) fn finish_batch(&mut self) {
+            let mut lru = self.lru.lock().expect("finish_batch(): cache");
+            for (key, value) in self.map.drain() {
+                lru.insert(&key, value);
+            }
+            self.set.clear();
+            self.batch_on = false;
+        }
+
+        /// Drop the blobs written and the tombstones left in batch mode, as the batch is dropped.
+        pub(crate) fn discard_batch(&mut self) {
+            self.set.clear();
+            self.map.clear();
+            self.batch_on = false;
+        }
+
+        /// Drop all the blobs and tombstones, including the blobs read shared with other cursors,
+        /// e.g., when the database is cleared.
         pub(crate) fn clear(&mut self) {
             self.set.clear();
             self.map.clear();
+            self.lru.lock().expect("clear(): cache").clear();
         }
 
+        /// Tell if the given key is looked up without the database, e.g., to skip reads ahead.
+        #[cfg(any(test, feature = "db_rocksdb"))]
         pub(crate) fn contains(&self, key: &[u8]) -> bool {
-            self.set.contains(key) || self.map.contains_key(key)
+            self.set.contains(key)
+                || self.map.contains_key(key)
+                || self
+                    .lru
+                    .lock()
+                    .expect("contains(): cache")
+                    .map
+                    .contains_key(key)
         }
 
-        pub(crate) fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-            match self.map.get(key) {
-                Some(v) => Ok(Some(v.to_owned())),
-                None => Ok(None),
+        /// Look up a blob under a single lock: `Some(Some(_))` on a hit, `Some(None)` on a tombstone
+        /// and `None` on a miss, where the database is to be read.
+        pub(crate) fn lookup(&mut self, key: &[u8]) -> Option<Option<Vec<u8>>> {
+            if self.set.contains(key) {
+                return Some(None);
+            }
+            if let Some(value) = self.map.get(key) {
+                return Some(Some(value.to_owned()));
             }
+            self.lru.lock().expect("lookup(): cache").get(key).map(Some)
         }
 
+        /// Keep a blob read from the database.
+        pub(crate) fn fill(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+            self.lru.lock().expect("fill(): cache").insert(key, value);
+            Ok(())
+        }
+
+        /// Keep a blob written to the database, which is never evicted in batch mode.
         pub(crate) fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-            self.map.insert(slice_to_hash(key), value);
-            if self.set.contains(key) {
-                self.set.remove(key);
+            if self.batch_on {
+                self.map.insert(key.to_vec(), value);
+            } else {
+                self.fill(key, value)?;
             }
+            self.set.remove(key);
             Ok(())
         }
 
+        /// Forget a blob, leaving a tombstone over the database until the batch is finished.
         pub(crate) fn delete(&mut self, key: &[u8]) -> Result<()> {
             self.map.remove(key);
-            self.set.insert(slice_to_hash(key));
+            self.lru.lock().expect("delete(): cache").remove(key);
+            if self.batch_on {
+                self.set.insert(key.to_vec());
+            }
             Ok(())
         }
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        #[test]
+        fn test_cache_eviction() {
+            let mut cache = MemCache::new();
+            cache.set_capacity(2);
+            cache.fill(b"a", b"1".to_vec()).unwrap();
+            cache.fill(b"b", b"2".to_vec()).unwrap();
+            assert_eq!(cache.lookup(b"a"), Some(Some(b"1".to_vec())));
+
+            // the least recently used one is evicted, while the one read recently hits
+            cache.fill(b"c", b"3".to_vec()).unwrap();
+            assert!(!cache.contains(b"b"));
+            assert_eq!(cache.lookup(b"a"), Some(Some(b"1".to_vec())));
+            assert_eq!(cache.lookup(b"c"), Some(Some(b"3".to_vec())));
+            cache.set_capacity(1);
+            assert!(!cache.contains(b"a"));
+            assert!(cache.contains(b"c"));
+        }
+
+        #[test]
+        fn test_cache_bypass() {
+            let mut cache = MemCache::new();
+            cache.set_capacity(0);
+            cache.fill(b"a", b"1".to_vec()).unwrap();
+            assert!(!cache.contains(b"a"));
+            assert_eq!(cache.lookup(b"a"), None);
+
+            // while the blobs written in batch mode are kept until it is finished
+            cache.init_batch();
+            cache.put(b"b", b"2".to_vec()).unwrap();
+            assert_eq!(cache.lookup(b"b"), Some(Some(b"2".to_vec())));
+            cache.finish_batch();
+            assert!(!cache.contains(b"b"));
+        }
+
+        #[test]
+        fn test_cache_keeps_writes_in_batch() {
+            let mut cache = MemCache::new();
+            cache.set_capacity(1);
+            cache.init_batch();
+            for i in 0..100u8 {
+                cache.put(&[i], vec![i]).unwrap();
+            }
+            for i in 0..100u8 {
+                assert_eq!(cache.lookup(&[i]), Some(Some(vec![i])));
+            }
+            cache.delete(&[0]).unwrap();
+            assert!(cache.contains(&[0]));
+            assert_eq!(cache.lookup(&[0]), Some(None));
+
+            // then bounded once the batch is finished
+            cache.finish_batch();
+            let kept: Vec<u8> = (1..100u8).filter(|&i| cache.contains(&[i])).collect();
+            assert_eq!(kept.len(), 1);
+            assert_eq!(cache.lookup(&kept), Some(Some(kept)));
+        }
+
+        #[test]
+        fn test_cache_discards_batch() {
+            let mut cache = MemCache::new();
+            let other = cache.share();
+            cache.fill(b"a", b"1".to_vec()).unwrap();
+            cache.init_batch();
+            cache.put(b"b", b"2".to_vec()).unwrap();
+            cache.delete(b"a").unwrap();
+            assert_eq!(cache.lookup(b"a"), Some(None));
+
+            // blobs of a batch failed to be written are never served to other cursors
+            cache.discard_batch();
+            assert_eq!(cache.lookup(b"a"), None);
+            assert_eq!(cache.lookup(b"b"), None);
+            assert!(!other.contains(b"b"));
+        }
+
+        #[test]
+        fn test_cache_deletes_and_puts_in_batch() {
+            let mut cache = MemCache::new();
+            cache.init_batch();
+            // put -> delete -> get
+            cache.put(b"a", b"1".to_vec()).unwrap();
+            cache.delete(b"a").unwrap();
+            assert_eq!(cache.lookup(b"a"), Some(None));
+            // delete -> put -> get
+            cache.delete(b"b").unwrap();
+            cache.put(b"b", b"2".to_vec()).unwrap();
+            assert_eq!(cache.lookup(b"b"), Some(Some(b"2".to_vec())));
+            // put -> delete -> put -> get
+            cache.put(b"c", b"3".to_vec()).unwrap();
+            cache.delete(b"c").unwrap();
+            cache.put(b"c", b"4".to_vec()).unwrap();
+            assert_eq!(cache.lookup(b"c"), Some(Some(b"4".to_vec())));
+            cache.finish_batch();
+
+            // the same as the database holds once the batch is committed
+            assert!(!cache.contains(b"a"));
+            assert_eq!(cache.lookup(b"b"), Some(Some(b"2".to_vec())));
+            assert_eq!(cache.lookup(b"c"), Some(Some(b"4".to_vec())));
+
+            // and no tombstone is left to hide a blob written by another cursor
+            let mut other = cache.share();
+            other.put(b"a", b"5".to_vec()).unwrap();
+            assert_eq!(cache.lookup(b"a"), Some(Some(b"5".to_vec())));
+        }
+    }
 }
 
 /// A trait defining databases used for `monotree`.
+///
+/// The trait is object-safe, so that databases can be chosen at runtime by `DynDatabase`.
 pub trait Database {
-    fn new(dbpath: &str) -> Self;
+    fn new(dbpath: &str) -> Self
+    where
+        Self: Sized;
+
+    /// Open the database in read-only mode, where writes fail. Unsupported by default.
+    fn open_readonly(_dbpath: &str) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        Err(Errors::new(
+            "open_readonly(): not supported by this database",
+        ))
+    }
+
     fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>>;
     fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()>;
     fn delete(&mut self, key: &[u8]) -> Result<()>;
     fn init_batch(&mut self) -> Result<()>;
     fn finish_batch(&mut self) -> Result<()>;
+
+    /// Drop the writes pending in a batch instead of applying them, e.g., when an operation
+    /// turns out to fail after writing. The database is out of batch mode afterwards.
+    /// Unsupported by default.
+    fn discard_batch(&mut self) -> Result<()> {
+        Err(Errors::new(
+            "discard_batch(): not supported by this database",
+        ))
+    }
+
+    /// Get the values of the given keys at once, aligned to the keys.
+    /// Loops over `get()` by default, while backends may read them in a single call.
+    fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Hint that the given keys are likely to be read soon, so that reads of them
+    /// can run ahead and overlap each other. Does nothing by default.
+    fn prefetch(&mut self, _keys: &[&[u8]]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Set the maximum number of nodes read kept in the cache of the database, if any,
+    /// beyond which the least recently used ones are evicted. Does nothing by default.
+    ///
+    /// With `0`, no nodes read are kept, so every read goes to the database.
+    /// Nodes written in batch mode are still kept until the batch is finished.
+    fn set_cache_capacity(&mut self, _capacity: usize) {}
+
+    /// Open the database with the given capacity of its cache. Refer to `set_cache_capacity()`.
+    fn with_cache_capacity(dbpath: &str, capacity: usize) -> Self
+    where
+        Self: Sized,
+    {
+        let mut db = Self::new(dbpath);
+        db.set_cache_capacity(capacity);
+        db
+    }
+
+    /// Make the data written so far durable. Does nothing by default.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Get all keys stored starting with the given prefix.
+    ///
+    /// Note that writes pending in a batch are not visible until the batch is finished.
+    fn scan(&mut self, _prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Err(Errors::new("scan(): not supported by this database"))
+    }
+
+    /// Delete all entries of the database.
+    /// Deletes every key found by `scan()` in a batch by default.
+    ///
+    /// Writes pending in a batch left open, e.g., by an operation failed in the middle,
+    /// are discarded, and the database is out of batch mode afterwards.
+    fn clear(&mut self) -> Result<()> {
+        let keys = self.scan(&[])?;
+        self.init_batch()?;
+        for key in keys.iter() {
+            self.delete(key)?;
+        }
+        self.finish_batch()
+    }
+}
+
+/// A trait defining databases opening other cursors on themselves, used for `SharedMonotree`.
+pub trait Cursor: Database + Sized {
+    /// Open another cursor sharing the DB handle and the cached node blobs, with its own batch.
+    fn cursor(&self) -> Self;
+}
+
+/// A trait defining databases of asynchronous I/O used for `AsyncMonotree`, mirroring `Database`.
+#[cfg(feature = "async")]
+pub trait AsyncDatabase {
+    fn get(&mut self, key: &[u8]) -> impl Future<Output = Result<Option<Vec<u8>>>> + Send;
+    fn put(&mut self, key: &[u8], value: Vec<u8>) -> impl Future<Output = Result<()>> + Send;
+    fn delete(&mut self, key: &[u8]) -> impl Future<Output = Result<()>> + Send;
+    fn init_batch(&mut self) -> impl Future<Output = Result<()>> + Send;
+    fn finish_batch(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    /// Drop the writes pending in a batch, as `Database::discard_batch()`.
+    /// Unsupported by default.
+    fn discard_batch(&mut self) -> impl Future<Output = Result<()>> + Send {
+        async {
+            Err(Errors::new(
+                "discard_batch(): not supported by this database",
+            ))
+        }
+    }
 }
 
 /// A database using `HashMap`.
+///
+/// Writes in batch mode are kept apart until `finish_batch()`, so that they can be discarded.
+/// Cursors share the map, while a clone copies it, so that a tree can be forked in tests.
 pub struct MemoryDB {
-    db: HashMap<Hash, Vec<u8>>,
+    db: Arc<RwLock<HashMap<Vec<u8>, Vec<u8>>>>,
+    batch: Option<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl Clone for MemoryDB {
+    fn clone(&self) -> Self {
+        let db = self.db.read().expect("clone(): memorydb").clone();
+        MemoryDB {
+            db: Arc::new(RwLock::new(db)),
+            batch: self.batch.clone(),
+        }
+    }
+}
+
+impl Cursor for MemoryDB {
+    fn cursor(&self) -> Self {
+        MemoryDB {
+            db: Arc::clone(&self.db),
+            batch: None,
+        }
+    }
 }
 
 impl Database for MemoryDB {
     fn new(_dbname: &str) -> Self {
-        MemoryDB { db: HashMap::new() }
+        MemoryDB {
+            db: Arc::new(RwLock::new(HashMap::new())),
+            batch: None,
+        }
     }
 
     fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        match self.db.get(key) {
-            Some(v) => Ok(Some(v.to_owned())),
-            None => Ok(None),
+        match self.batch.as_ref().and_then(|batch| batch.get(key)) {
+            Some(value) => Ok(value.clone()),
+            None => Ok(self.db.read().expect("get(): memorydb").get(key).cloned()),
         }
     }
 
     fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-        self.db.insert(slice_to_hash(key), value);
+        match self.batch.as_mut() {
+            Some(batch) => {
+                batch.insert(key.to_vec(), Some(value));
+            }
+            None => {
+                let mut db = self.db.write().expect("put(): memorydb");
+                db.insert(key.to_vec(), value);
+            }
+        }
         Ok(())
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<()> {
-        self.db.remove(key);
+        match self.batch.as_mut() {
+            Some(batch) => {
+                batch.insert(key.to_vec(), None);
+            }
+            None => {
+                self.db.write().expect("delete(): memorydb").remove(key);
+            }
+        }
         Ok(())
     }
 
     fn init_batch(&mut self) -> Result<()> {
+        self.batch = Some(HashMap::new());
         Ok(())
     }
 
     fn finish_batch(&mut self) -> Result<()> {
+        let mut db = self.db.write().expect("finish_batch(): memorydb");
+        for (key, value) in self.batch.take().into_iter().flatten() {
+            match value {
+                Some(value) => db.insert(key, value),
+                None => db.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    fn discard_batch(&mut self) -> Result<()> {
+        self.batch = None;
+        Ok(())
+    }
+
+    fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .db
+            .read()
+            .expect("scan(): memorydb")
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.db.write().expect("clear(): memorydb").clear();
+        self.batch = None;
         Ok(())
     }
 }
 
+/// `MemoryDB` never blocks, so its futures are ready at once.
+#[cfg(feature = "async")]
+impl AsyncDatabase for MemoryDB {
+    async fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Database::get(self, key)
+    }
+
+    async fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        Database::put(self, key, value)
+    }
+
+    async fn delete(&mut self, key: &[u8]) -> Result<()> {
+        Database::delete(self, key)
+    }
+
+    async fn init_batch(&mut self) -> Result<()> {
+        Database::init_batch(self)
+    }
+
+    async fn finish_batch(&mut self) -> Result<()> {
+        Database::finish_batch(self)
+    }
+
+    async fn discard_batch(&mut self) -> Result<()> {
+        Database::discard_batch(self)
+    }
+}
+
+/// A database of the backend chosen at runtime, e.g., by a name from a config file.
+///
+/// Trees with different backends can be held together as `Monotree<DynDatabase, H>`.
+/// Constructed by `Database::new()`, it falls back to `MemoryDB`. Refer to `DynDatabase::open()`.
+pub struct DynDatabase(Box<dyn Database>);
+
+impl DynDatabase {
+    /// Wrap the given database.
+    pub fn with_db<D: Database + 'static>(db: D) -> Self {
+        DynDatabase(Box::new(db))
+    }
+
+    /// Open a database of the given kind at `dbpath`. The kind is one of
+    /// `"memory"`, `"rocksdb"`, `"sled"`, `"redb"` and `"lmdb"`, where the ones
+    /// other than `"memory"` are available only with their features enabled.
+    pub fn open(kind: &str, dbpath: &str) -> Result<Self> {
+        match kind {
+            "memory" => Ok(Self::with_db(MemoryDB::new(dbpath))),
+            #[cfg(feature = "db_rocksdb")]
+            "rocksdb" => Ok(Self::with_db(rocksdb::RocksDB::new(dbpath))),
+            #[cfg(feature = "db_sled")]
+            "sled" => Ok(Self::with_db(sled::Sled::new(dbpath))),
+            #[cfg(feature = "db_redb")]
+            "redb" => Ok(Self::with_db(redb::Redb::new(dbpath))),
+            #[cfg(feature = "db_lmdb")]
+            "lmdb" => Ok(Self::with_db(lmdb::Lmdb::new(dbpath))),
+            _ => Err(Errors::new(&format!("open(): unknown database: {}", kind))),
+        }
+    }
+}
+
+impl Database for DynDatabase {
+    fn new(dbpath: &str) -> Self {
+        Self::with_db(MemoryDB::new(dbpath))
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.0.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.0.put(key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.0.delete(key)
+    }
+
+    fn init_batch(&mut self) -> Result<()> {
+        self.0.init_batch()
+    }
+
+    fn finish_batch(&mut self) -> Result<()> {
+        self.0.finish_batch()
+    }
+
+    fn discard_batch(&mut self) -> Result<()> {
+        self.0.discard_batch()
+    }
+
+    fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.0.multi_get(keys)
+    }
+
+    fn prefetch(&mut self, keys: &[&[u8]]) -> Result<()> {
+        self.0.prefetch(keys)
+    }
+
+    fn set_cache_capacity(&mut self, capacity: usize) {
+        self.0.set_cache_capacity(capacity)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+
+    fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        self.0.scan(prefix)
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.0.clear()
+    }
+}
+
 #[cfg(feature = "db_rocksdb")]
 pub mod rocksdb {
-    use crate::{Database, Errors, Result};
     use super::cache::MemCache;
-    use rocksdb::{WriteBatch, DB};
+    use super::Cursor;
+    use crate::{Database, MonotreeError, Result};
+    use rocksdb::checkpoint::Checkpoint;
+    use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+    use std::collections::{HashMap, HashSet};
     use std::path::Path;
-    use std::sync::{Arc, Mutex};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+
+    /// A read of nodes running ahead on its own thread. Refer to `Database::prefetch()`.
+    type Pending = JoinHandle<std::result::Result<Vec<Vec<u8>>, rocksdb::Error>>;
+
+    /// The maximum number of threads reading nodes ahead at a time.
+    const PREFETCH_THREADS: usize = 4;
 
     /// A database using rust wrapper for `RocksDB`.
     pub struct RocksDB {
-        db: Arc<Mutex<DB>>,
+        db: Arc<DB>,
         batch: WriteBatch,
         cache: MemCache,
         batch_on: bool,
+        pending: HashSet<Vec<u8>>,
+        reads: Vec<(Vec<Vec<u8>>, Pending)>,
+        readonly: bool,
     }
 
-    impl From<rocksdb::Error> for Errors {
+    impl From<rocksdb::Error> for MonotreeError {
         fn from(err: rocksdb::Error) -> Self {
-            Errors::new(&err.to_string())
+            MonotreeError::Db(err.to_string())
         }
     }
 
-    impl Database for RocksDB {
-        fn new(dbpath: &str) -> Self {
-            let db = Arc::new(Mutex::new(
-                DB::open_default(Path::new(dbpath)).expect("new(): rocksdb"),
-            ));
+    impl RocksDB {
+        fn with_db(db: DB, readonly: bool) -> Self {
             RocksDB {
-                db,
+                db: Arc::new(db),
                 batch: WriteBatch::default(),
                 cache: MemCache::new(),
                 batch_on: false,
+                pending: HashSet::new(),
+                reads: Vec::new(),
+                readonly,
+            }
+        }
+
+        /// Create a checkpoint of this database at the given path, which is a consistent
+        /// snapshot to be opened by `open_readonly()` while writes continue on this one.
+        pub fn checkpoint(&self, path: &str) -> Result<()> {
+            Checkpoint::new(&self.db)?.create_checkpoint(Path::new(path))?;
+            Ok(())
+        }
+
+        /// Wait for the read running ahead, keeping the nodes read in the cache.
+        /// Nodes written or deleted since the read started are no longer pending, thus skipped.
+        fn join_read(&mut self, i: usize) -> Result<()> {
+            let (keys, read) = self.reads.swap_remove(i);
+            let pending: Vec<bool> = keys.iter().map(|key| self.pending.remove(key)).collect();
+            let values = read.join().map_err(|_| {
+                MonotreeError::Db("rocksdb: prefetch thread panicked".to_string())
+            })??;
+            for ((key, value), pending) in keys.iter().zip(values).zip(pending) {
+                // a missing key comes as an empty value, while node blobs are never empty
+                if pending && !value.is_empty() {
+                    self.cache.fill(key, value)?;
+                }
+            }
+            Ok(())
+        }
+
+        fn check_writable(&self) -> Result<()> {
+            if self.readonly {
+                return Err(MonotreeError::Db("rocksdb: read-only database".to_string()));
+            }
+            Ok(())
+        }
+
+        /// Open another cursor on this database.
+        ///
+        /// The cursor shares both the DB handle and the cached node blobs with this one,
+        /// but has its own batch. Useful when many `Monotree`s work on a single DB.
+        pub fn cursor(&self) -> Self {
+            RocksDB {
+                db: Arc::clone(&self.db),
+                batch: WriteBatch::default(),
+                cache: self.cache.share(),
+                batch_on: false,
+                pending: HashSet::new(),
+                reads: Vec::new(),
+                readonly: self.readonly,
             }
         }
+    }
+
+    impl Cursor for RocksDB {
+        fn cursor(&self) -> Self {
+            RocksDB::cursor(self)
+        }
+    }
+
+    impl Database for RocksDB {
+        fn new(dbpath: &str) -> Self {
+            let db = DB::open_default(Path::new(dbpath)).expect("new(): rocksdb");
+            Self::with_db(db, false)
+        }
+
+        fn open_readonly(dbpath: &str) -> Result<Self> {
+            let db = DB::open_for_read_only(&Options::default(), Path::new(dbpath), false)?;
+            Ok(Self::with_db(db, true))
+        }
 
         fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-            if self.cache.contains(key) {
-                return self.cache.get(key);
+            if let Some(value) = self.cache.lookup(key) {
+                return Ok(value);
             }
-            let db = self.db.lock().expect("get(): rocksdb");
-            match db.get(key)? {
+            if self.pending.contains(key) {
+                let i = self
+                    .reads
+                    .iter()
+                    .position(|(keys, _)| keys.iter().any(|k| k == key));
+                if let Some(i) = i {
+                    self.join_read(i)?;
+                    if let Some(value) = self.cache.lookup(key) {
+                        return Ok(value);
+                    }
+                }
+            }
+            match self.db.get(key)? {
                 Some(value) => {
-                    self.cache.put(key, value.to_owned())?;
+                    self.cache.fill(key, value.to_owned())?;
                     Ok(Some(value))
                 }
                 None => Ok(None),
@@ -150,39 +790,133 @@ pub mod rocksdb {
         }
 
         fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-            self.cache.put(key, value.to_owned())?;
+            self.check_writable()?;
+            self.pending.remove(key);
             if self.batch_on {
-                Ok(self.batch.put(key, value)?)
+                self.batch.put(key, &value);
             } else {
-                let db = self.db.lock().expect("put(): rocksdb");
-                Ok(db.put(key, value)?)
+                self.db.put(key, &value)?;
             }
+            self.cache.put(key, value)
         }
 
         fn delete(&mut self, key: &[u8]) -> Result<()> {
+            self.check_writable()?;
+            self.pending.remove(key);
             self.cache.delete(key)?;
             if self.batch_on {
-                Ok(self.batch.delete(key)?)
+                self.batch.delete(key);
             } else {
-                let db = self.db.lock().expect("remove(): rocksdb");
-                Ok(db.delete(key)?)
+                self.db.delete(key)?;
             }
+            Ok(())
         }
 
         fn init_batch(&mut self) -> Result<()> {
             self.batch = WriteBatch::default();
-            self.cache.clear();
+            self.cache.init_batch();
             self.batch_on = true;
             Ok(())
         }
 
         fn finish_batch(&mut self) -> Result<()> {
             self.batch_on = false;
-            if !self.batch.is_empty() {
-                let batch = std::mem::take(&mut self.batch);
-                let db = self.db.lock().expect("write_batch(): rocksdb");
-                db.write(batch)?;
+            let batch = std::mem::take(&mut self.batch);
+            if !batch.is_empty() {
+                if let Err(err) = self.db.write(batch) {
+                    self.cache.discard_batch();
+                    return Err(err.into());
+                }
             }
+            self.cache.finish_batch();
+            Ok(())
+        }
+
+        fn discard_batch(&mut self) -> Result<()> {
+            self.batch = WriteBatch::default();
+            self.cache.discard_batch();
+            self.batch_on = false;
+            Ok(())
+        }
+
+        fn set_cache_capacity(&mut self, capacity: usize) {
+            self.cache.set_capacity(capacity);
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(self.db.flush()?)
+        }
+
+        fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+            Ok(self
+                .db
+                .iterator(IteratorMode::From(prefix, Direction::Forward))
+                .map(|(key, _)| key)
+                .take_while(|key| key.starts_with(prefix))
+                .map(|key| key.to_vec())
+                .collect())
+        }
+
+        /// Delete all the keys in a single batch, discarding the writes pending in a batch.
+        fn clear(&mut self) -> Result<()> {
+            self.check_writable()?;
+            self.batch = WriteBatch::default();
+            self.batch_on = false;
+            self.pending.clear();
+            self.cache.clear();
+            let mut batch = WriteBatch::default();
+            for key in self.scan(&[])? {
+                batch.delete(key);
+            }
+            Ok(self.db.write(batch)?)
+        }
+
+        /// Read the keys missing in the cache by a single `multi_get()` of `RocksDB`.
+        fn multi_get(&mut self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+            let misses: Vec<&[u8]> = keys
+                .iter()
+                .filter(|key| !self.cache.contains(key) && !self.pending.contains(**key))
+                .cloned()
+                .collect();
+            let mut fetched: HashMap<&[u8], Option<Vec<u8>>> = HashMap::new();
+            for (key, value) in misses.iter().zip(self.db.multi_get(misses.iter())?) {
+                // a missing key comes as an empty value, while node blobs are never empty
+                let value = Some(value).filter(|value| !value.is_empty());
+                if let Some(value) = value.as_ref() {
+                    self.cache.fill(key, value.to_owned())?;
+                }
+                fetched.insert(key, value);
+            }
+            keys.iter()
+                .map(|key| match fetched.remove(key) {
+                    Some(value) => Ok(value),
+                    None => self.get(key),
+                })
+                .collect()
+        }
+
+        /// Read the nodes by a single `multi_get()` on a thread of its own,
+        /// which are then taken by `get()`. Reads having run ahead but never taken
+        /// are kept in the cache. While `PREFETCH_THREADS` reads are running, hints are ignored.
+        fn prefetch(&mut self, keys: &[&[u8]]) -> Result<()> {
+            while let Some(i) = self.reads.iter().position(|(_, read)| read.is_finished()) {
+                self.join_read(i)?;
+            }
+            if self.reads.len() >= PREFETCH_THREADS {
+                return Ok(());
+            }
+            let mut misses: Vec<Vec<u8>> = Vec::new();
+            for key in keys.iter() {
+                if !self.cache.contains(key) && self.pending.insert(key.to_vec()) {
+                    misses.push(key.to_vec());
+                }
+            }
+            if misses.is_empty() {
+                return Ok(());
+            }
+            let (db, owned) = (Arc::clone(&self.db), misses.clone());
+            let read = thread::spawn(move || db.multi_get(owned));
+            self.reads.push((misses, read));
             Ok(())
         }
     }
@@ -190,8 +924,9 @@ pub mod rocksdb {
 
 #[cfg(feature = "db_sled")]
 pub mod sled {
-    use crate::{Database, Errors, Result};
     use super::cache::MemCache;
+    use super::Cursor;
+    use crate::{Database, MonotreeError, Result};
 
     /// A database using `Sled`, a pure-rust-implmented DB.
     pub struct Sled {
@@ -201,16 +936,30 @@ pub mod sled {
         batch_on: bool,
     }
 
-    impl From<sled::Error> for Errors {
+    impl From<sled::Error> for MonotreeError {
         fn from(err: sled::Error) -> Self {
-            Errors::new(&err.to_string())
+            MonotreeError::Db(err.to_string())
         }
     }
 
     impl Sled {
-        pub fn flush(&self) -> Result<()> {
-            self.db.flush()?;
-            Ok(())
+        /// Open another cursor on this database.
+        ///
+        /// The cursor shares both the DB handle and the cached node blobs with this one,
+        /// but has its own batch. Useful when many `Monotree`s work on a single DB.
+        pub fn cursor(&self) -> Self {
+            Sled {
+                db: self.db.clone(),
+                batch: sled::Batch::default(),
+                cache: self.cache.share(),
+                batch_on: false,
+            }
+        }
+    }
+
+    impl Cursor for Sled {
+        fn cursor(&self) -> Self {
+            Sled::cursor(self)
         }
     }
 
@@ -226,12 +975,12 @@ pub mod sled {
         }
 
         fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-            if self.cache.contains(key) {
-                return self.cache.get(key);
+            if let Some(value) = self.cache.lookup(key) {
+                return Ok(value);
             }
             match self.db.get(key)? {
                 Some(value) => {
-                    self.cache.put(key, value.to_vec())?;
+                    self.cache.fill(key, value.to_vec())?;
                     Ok(Some(value.to_vec()))
                 }
                 None => Ok(None),
@@ -239,13 +988,12 @@ pub mod sled {
         }
 
         fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-            self.cache.put(key, value.to_owned())?;
             if self.batch_on {
-                self.batch.insert(key, value);
+                self.batch.insert(key, value.to_owned());
             } else {
-                self.db.insert(key, value)?;
+                self.db.insert(key, value.to_owned())?;
             }
-            Ok(())
+            self.cache.put(key, value)
         }
 
         fn delete(&mut self, key: &[u8]) -> Result<()> {
@@ -260,7 +1008,390 @@ pub mod sled {
 
         fn init_batch(&mut self) -> Result<()> {
             self.batch = sled::Batch::default();
+            self.cache.init_batch();
+            self.batch_on = true;
+            Ok(())
+        }
+
+        fn finish_batch(&mut self) -> Result<()> {
+            self.batch_on = false;
+            let batch = std::mem::take(&mut self.batch);
+            if let Err(err) = self.db.apply_batch(batch) {
+                self.cache.discard_batch();
+                return Err(err.into());
+            }
+            self.cache.finish_batch();
+            Ok(())
+        }
+
+        fn discard_batch(&mut self) -> Result<()> {
+            self.batch = sled::Batch::default();
+            self.cache.discard_batch();
+            self.batch_on = false;
+            Ok(())
+        }
+
+        fn set_cache_capacity(&mut self, capacity: usize) {
+            self.cache.set_capacity(capacity);
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.db.flush()?;
+            Ok(())
+        }
+
+        fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+            self.db
+                .scan_prefix(prefix)
+                .map(|item| Ok(item?.0.to_vec()))
+                .collect()
+        }
+
+        /// Clear the tree of `Sled` at once, discarding the writes pending in a batch.
+        fn clear(&mut self) -> Result<()> {
+            self.batch = sled::Batch::default();
+            self.batch_on = false;
+            self.cache.clear();
+            self.db.clear()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "db_redb")]
+pub mod redb {
+    use super::cache::MemCache;
+    use super::Cursor;
+    use crate::{Database, MonotreeError, Result};
+    use redb::TableDefinition;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// The single table where all the entries of `monotree` are stored.
+    const NODES: TableDefinition<&[u8], &[u8]> = TableDefinition::new("monotree");
+
+    /// A database using `redb`, a pure-rust embedded key-value store.
+    ///
+    /// As with the other databases, `dbpath` is a directory, where a file of `redb` is created.
+    /// Writes in batch mode are applied in a single write transaction on `finish_batch()`.
+    pub struct Redb {
+        db: Arc<redb::Database>,
+        batch: HashMap<Vec<u8>, Option<Vec<u8>>>,
+        cache: MemCache,
+        batch_on: bool,
+    }
+
+    impl From<redb::Error> for MonotreeError {
+        fn from(err: redb::Error) -> Self {
+            MonotreeError::Db(err.to_string())
+        }
+    }
+
+    /// Take any error of `redb` into `MonotreeError`, by way of `redb::Error`.
+    fn db_err<E: Into<redb::Error>>(err: E) -> MonotreeError {
+        err.into().into()
+    }
+
+    impl Redb {
+        /// Open another cursor on this database.
+        ///
+        /// The cursor shares both the DB handle and the cached node blobs with this one,
+        /// but has its own batch. Useful when many `Monotree`s work on a single DB.
+        pub fn cursor(&self) -> Self {
+            Redb {
+                db: Arc::clone(&self.db),
+                batch: HashMap::new(),
+                cache: self.cache.share(),
+                batch_on: false,
+            }
+        }
+
+        fn read(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            let txn = self.db.begin_read().map_err(db_err)?;
+            let table = txn.open_table(NODES).map_err(db_err)?;
+            let value = table
+                .get(key)
+                .map_err(db_err)?
+                .map(|value| value.value().to_vec());
+            Ok(value)
+        }
+
+        /// Apply the given writes in a single write transaction, where `None` means deletion.
+        fn write<'a, I>(&self, writes: I) -> Result<()>
+        where
+            I: IntoIterator<Item = (&'a [u8], Option<&'a [u8]>)>,
+        {
+            let txn = self.db.begin_write().map_err(db_err)?;
+            {
+                let mut table = txn.open_table(NODES).map_err(db_err)?;
+                for (key, value) in writes {
+                    match value {
+                        Some(value) => table.insert(key, value).map_err(db_err)?,
+                        None => table.remove(key).map_err(db_err)?,
+                    };
+                }
+            }
+            txn.commit().map_err(db_err)?;
+            Ok(())
+        }
+
+        /// Remove all the entries in a single write transaction.
+        fn truncate(&self) -> Result<()> {
+            let txn = self.db.begin_write().map_err(db_err)?;
+            txn.open_table(NODES)
+                .map_err(db_err)?
+                .retain(|_, _| false)
+                .map_err(db_err)?;
+            txn.commit().map_err(db_err)?;
+            Ok(())
+        }
+
+        fn scan_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+            let txn = self.db.begin_read().map_err(db_err)?;
+            let table = txn.open_table(NODES).map_err(db_err)?;
+            let mut keys = Vec::new();
+            for item in table.range(prefix..).map_err(db_err)? {
+                let key = item.map_err(db_err)?.0.value().to_vec();
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                keys.push(key);
+            }
+            Ok(keys)
+        }
+    }
+
+    impl Cursor for Redb {
+        fn cursor(&self) -> Self {
+            Redb::cursor(self)
+        }
+    }
+
+    impl Database for Redb {
+        fn new(dbpath: &str) -> Self {
+            fs::create_dir_all(dbpath).expect("new(): redb dir");
+            let db = redb::Database::create(Path::new(dbpath).join("monotree.redb"))
+                .expect("new(): redb");
+            let redb = Redb {
+                db: Arc::new(db),
+                batch: HashMap::new(),
+                cache: MemCache::new(),
+                batch_on: false,
+            };
+            // create the table ahead, so that reads on an empty database do not fail
+            redb.write(None).expect("new(): redb table");
+            redb
+        }
+
+        fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            if let Some(value) = self.cache.lookup(key) {
+                return Ok(value);
+            }
+            match self.read(key)? {
+                Some(value) => {
+                    self.cache.fill(key, value.to_owned())?;
+                    Ok(Some(value))
+                }
+                None => Ok(None),
+            }
+        }
+
+        fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+            if self.batch_on {
+                self.batch.insert(key.to_vec(), Some(value.to_owned()));
+            } else {
+                self.write(Some((key, Some(&value[..]))))?;
+            }
+            self.cache.put(key, value)
+        }
+
+        fn delete(&mut self, key: &[u8]) -> Result<()> {
+            self.cache.delete(key)?;
+            if self.batch_on {
+                self.batch.insert(key.to_vec(), None);
+            } else {
+                self.write(Some((key, None)))?;
+            }
+            Ok(())
+        }
+
+        fn init_batch(&mut self) -> Result<()> {
+            self.batch.clear();
+            self.cache.init_batch();
+            self.batch_on = true;
+            Ok(())
+        }
+
+        fn finish_batch(&mut self) -> Result<()> {
+            self.batch_on = false;
+            let batch = std::mem::take(&mut self.batch);
+            let written = self.write(
+                batch
+                    .iter()
+                    .map(|(key, value)| (&key[..], value.as_deref())),
+            );
+            if let Err(err) = written {
+                self.cache.discard_batch();
+                return Err(err);
+            }
+            self.cache.finish_batch();
+            Ok(())
+        }
+
+        fn discard_batch(&mut self) -> Result<()> {
+            self.batch.clear();
+            self.cache.discard_batch();
+            self.batch_on = false;
+            Ok(())
+        }
+
+        fn set_cache_capacity(&mut self, capacity: usize) {
+            self.cache.set_capacity(capacity);
+        }
+
+        fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+            self.scan_prefix(prefix)
+        }
+
+        /// Empty the table of `redb` at once, discarding the writes pending in a batch.
+        fn clear(&mut self) -> Result<()> {
+            self.batch.clear();
+            self.batch_on = false;
             self.cache.clear();
+            self.truncate()
+        }
+    }
+}
+
+#[cfg(feature = "db_lmdb")]
+pub mod lmdb {
+    use super::cache::MemCache;
+    use crate::{Database, MonotreeError, Result};
+    use lmdb::{Cursor as _, DatabaseFlags, Environment, Transaction, WriteFlags};
+    use lmdb_sys::{MDB_FIRST, MDB_NEXT, MDB_SET_RANGE};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// The maximum size of the memory map, which bounds the size of the database.
+    const MAP_SIZE: usize = 1 << 36;
+
+    /// A database using `LMDB`, a memory-mapped key-value store of predictable latency on reads.
+    ///
+    /// Writes in batch mode are applied in a single write transaction on `finish_batch()`.
+    pub struct Lmdb {
+        env: Arc<Environment>,
+        db: lmdb::Database,
+        batch: HashMap<Vec<u8>, Option<Vec<u8>>>,
+        cache: MemCache,
+        batch_on: bool,
+    }
+
+    impl From<lmdb::Error> for MonotreeError {
+        fn from(err: lmdb::Error) -> Self {
+            MonotreeError::Db(err.to_string())
+        }
+    }
+
+    impl Lmdb {
+        /// Open another cursor on this database.
+        ///
+        /// The cursor shares both the DB handle and the cached node blobs with this one,
+        /// but has its own batch. Useful when many `Monotree`s work on a single DB.
+        pub fn cursor(&self) -> Self {
+            Lmdb {
+                env: Arc::clone(&self.env),
+                db: self.db,
+                batch: HashMap::new(),
+                cache: self.cache.share(),
+                batch_on: false,
+            }
+        }
+
+        /// Apply the given writes in a single write transaction, where `None` means deletion.
+        fn write<'a, I>(&self, writes: I) -> Result<()>
+        where
+            I: IntoIterator<Item = (&'a [u8], Option<&'a [u8]>)>,
+        {
+            let mut txn = self.env.begin_rw_txn()?;
+            for (key, value) in writes {
+                match value {
+                    Some(value) => txn.put(self.db, &key, &value, WriteFlags::empty())?,
+                    None => match txn.del(self.db, &key, None) {
+                        Ok(()) | Err(lmdb::Error::NotFound) => (),
+                        Err(err) => return Err(err.into()),
+                    },
+                }
+            }
+            txn.commit()?;
+            Ok(())
+        }
+    }
+
+    impl super::Cursor for Lmdb {
+        fn cursor(&self) -> Self {
+            Lmdb::cursor(self)
+        }
+    }
+
+    impl Database for Lmdb {
+        fn new(dbpath: &str) -> Self {
+            fs::create_dir_all(dbpath).expect("new(): lmdb dir");
+            let env = Environment::new()
+                .set_map_size(MAP_SIZE)
+                .open(Path::new(dbpath))
+                .expect("new(): lmdb");
+            let db = env
+                .create_db(None, DatabaseFlags::empty())
+                .expect("new(): lmdb database");
+            Lmdb {
+                env: Arc::new(env),
+                db,
+                batch: HashMap::new(),
+                cache: MemCache::new(),
+                batch_on: false,
+            }
+        }
+
+        fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            if let Some(value) = self.cache.lookup(key) {
+                return Ok(value);
+            }
+            let txn = self.env.begin_ro_txn()?;
+            let value = match txn.get(self.db, &key) {
+                Ok(value) => value.to_vec(),
+                Err(lmdb::Error::NotFound) => return Ok(None),
+                Err(err) => return Err(err.into()),
+            };
+            self.cache.fill(key, value.to_owned())?;
+            Ok(Some(value))
+        }
+
+        fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+            if self.batch_on {
+                self.batch.insert(key.to_vec(), Some(value.to_owned()));
+            } else {
+                self.write(Some((key, Some(&value[..]))))?;
+            }
+            self.cache.put(key, value)
+        }
+
+        fn delete(&mut self, key: &[u8]) -> Result<()> {
+            self.cache.delete(key)?;
+            if self.batch_on {
+                self.batch.insert(key.to_vec(), None);
+            } else {
+                self.write(Some((key, None)))?;
+            }
+            Ok(())
+        }
+
+        fn init_batch(&mut self) -> Result<()> {
+            self.batch.clear();
+            self.cache.init_batch();
             self.batch_on = true;
             Ok(())
         }
@@ -268,7 +1399,69 @@ pub mod sled {
         fn finish_batch(&mut self) -> Result<()> {
             self.batch_on = false;
             let batch = std::mem::take(&mut self.batch);
-            self.db.apply_batch(batch)?;
+            let written = self.write(
+                batch
+                    .iter()
+                    .map(|(key, value)| (&key[..], value.as_deref())),
+            );
+            if written.is_err() {
+                self.cache.discard_batch();
+                return written;
+            }
+            self.cache.finish_batch();
+            Ok(())
+        }
+
+        fn discard_batch(&mut self) -> Result<()> {
+            self.batch.clear();
+            self.cache.discard_batch();
+            self.batch_on = false;
+            Ok(())
+        }
+
+        fn set_cache_capacity(&mut self, capacity: usize) {
+            self.cache.set_capacity(capacity);
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.env.sync(true)?;
+            Ok(())
+        }
+
+        fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+            let txn = self.env.begin_ro_txn()?;
+            let cursor = txn.open_ro_cursor(self.db)?;
+            let mut keys = Vec::new();
+            // `iter_from()` of `lmdb` panics on no entry found, so the cursor is moved by hand
+            let (mut from, mut op) = if prefix.is_empty() {
+                (None, MDB_FIRST)
+            } else {
+                (Some(prefix), MDB_SET_RANGE)
+            };
+            loop {
+                let key = match cursor.get(from, None, op) {
+                    Ok((Some(key), _)) => key,
+                    Ok((None, _)) | Err(lmdb::Error::NotFound) => break,
+                    Err(err) => return Err(err.into()),
+                };
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                keys.push(key.to_vec());
+                from = None;
+                op = MDB_NEXT;
+            }
+            Ok(keys)
+        }
+
+        /// Empty the database of `LMDB` at once, discarding the writes pending in a batch.
+        fn clear(&mut self) -> Result<()> {
+            self.batch.clear();
+            self.batch_on = false;
+            self.cache.clear();
+            let mut txn = self.env.begin_rw_txn()?;
+            txn.clear_db(self.db)?;
+            txn.commit()?;
             Ok(())
         }
     }
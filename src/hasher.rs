@@ -4,16 +4,60 @@ use crate::*;
 use digest::Digest;
 
 /// A trait defining hashers used for `monotree`
-pub trait Hasher {
-    fn new() -> Self;
-    fn digest(&self, bytes: &[u8]) -> Hash;
+///
+/// `N` is the length of digest in bytes, which is also the length of keys of the tree.
+/// Hashers of other than the default length plug into a tree of the same length:
+/// ```
+/// use monotree::database::MemoryDB;
+/// use monotree::hasher::Blake3;
+/// use monotree::utils::{random_bytes, slice_to_array};
+/// use monotree::{Hash, Hasher, Monotree, Result};
+///
+/// struct Blake3Truncated20;
+/// impl Hasher<20> for Blake3Truncated20 {
+///     type Config = ();
+///
+///     fn with_config(_: ()) -> Self {
+///         Blake3Truncated20
+///     }
+///
+///     fn digest(&self, bytes: &[u8]) -> Hash<20> {
+///         slice_to_array(&Blake3::new().digest(bytes)[..20])
+///     }
+/// }
+///
+/// fn example() -> Result<()> {
+///     let mut tree = Monotree::<MemoryDB, Blake3Truncated20, 20>::new("hash20");
+///     let key: Hash<20> = slice_to_array(&random_bytes(20));
+///     let root = tree.insert(None, &key, &key)?;
+///     assert_eq!(tree.get(root.as_ref(), &key)?, Some(key));
+///     Ok(())
+/// }
+/// # example().unwrap();
+/// ```
+pub trait Hasher<const N: usize = HASH_LEN> {
+    /// A state the hasher is constructed with, e.g., a key. `()` for hashers of no state.
+    type Config: Default;
+
+    fn with_config(config: Self::Config) -> Self;
+
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self::with_config(Default::default())
+    }
+
+    fn digest(&self, bytes: &[u8]) -> Hash<N>;
 }
 
 #[derive(Clone, Debug)]
-/// A hasher using `Blake2s` hash function
+/// A hasher using `Blake2s` hash function of 32-byte output, i.e., `BLAKE2s-256`, unkeyed.
 pub struct Blake2s;
 impl Hasher for Blake2s {
-    fn new() -> Self {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
         Blake2s
     }
 
@@ -26,10 +70,15 @@ impl Hasher for Blake2s {
 }
 
 #[derive(Clone, Debug)]
-/// A hasher using `Blake2b` hash function
+/// A hasher using `Blake2b` hash function of 32-byte output, i.e., `BLAKE2b-256`, unkeyed.
+///
+/// The output length is a parameter of `BLAKE2b` digested along with the input,
+/// so it differs from the 64-byte `BLAKE2b-512` truncated to 32 bytes.
 pub struct Blake2b;
 impl Hasher for Blake2b {
-    fn new() -> Self {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
         Blake2b
     }
 
@@ -42,10 +91,12 @@ impl Hasher for Blake2b {
 }
 
 #[derive(Clone, Debug)]
-/// A hasher using `Blake3` hash function
+/// A hasher using `Blake3` hash function of the default 32-byte output, unkeyed.
 pub struct Blake3;
 impl Hasher for Blake3 {
-    fn new() -> Self {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
         Blake3
     }
 
@@ -59,10 +110,12 @@ impl Hasher for Blake3 {
 }
 
 #[derive(Clone, Debug)]
-/// A hasher using `SHA2` hash function
+/// A hasher using `SHA-256` hash function of the `SHA-2` family, of 32-byte output.
 pub struct Sha2;
 impl Hasher for Sha2 {
-    fn new() -> Self {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
         Sha2
     }
 
@@ -76,10 +129,35 @@ impl Hasher for Sha2 {
 }
 
 #[derive(Clone, Debug)]
-/// A hasher using `SHA3` or `Keccak` hash function
+/// A hasher using `SHA-512/256` hash function of the `SHA-2` family, of 32-byte output.
+///
+/// It runs `SHA-512` from its own initial values and truncates the output to 32 bytes,
+/// so it differs from both `SHA-256` and the output of `SHA-512` truncated.
+pub struct Sha512_256;
+impl Hasher for Sha512_256 {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
+        Sha512_256
+    }
+
+    /// Currently supports 256-bit or 32-byte only.
+    fn digest(&self, bytes: &[u8]) -> Hash {
+        let mut hasher = sha2::Sha512Trunc256::new();
+        hasher.input(bytes);
+        let hash = hasher.result();
+        slice_to_hash(hash.as_slice())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// A hasher using `SHA3-256` hash function of 32-byte output, as standardized by NIST.
+/// Refer to `Keccak256` for the legacy one before standardization.
 pub struct Sha3;
 impl Hasher for Sha3 {
-    fn new() -> Self {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
         Sha3
     }
 
@@ -91,3 +169,144 @@ impl Hasher for Sha3 {
         slice_to_hash(hash.as_slice())
     }
 }
+
+#[derive(Clone, Debug)]
+/// A hasher using legacy `Keccak-256` hash function of 32-byte output, as used in Ethereum.
+///
+/// It differs from `Sha3` only in padding: `Keccak-256` pads with `0x01`
+/// as originally submitted, while the NIST-standardized `SHA3-256` pads with `0x06`.
+/// Hence they digest the same bytes into different hashes.
+pub struct Keccak256;
+impl Hasher for Keccak256 {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
+        Keccak256
+    }
+
+    /// Currently supports 256-bit or 32-byte only.
+    fn digest(&self, bytes: &[u8]) -> Hash {
+        let mut hasher = sha3::Keccak256::new();
+        hasher.input(bytes);
+        let hash = hasher.result();
+        slice_to_hash(hash.as_slice())
+    }
+}
+
+#[derive(Clone)]
+/// A hasher using `Blake3` hash function in keyed mode, i.e., a MAC of the 32-byte key.
+///
+/// Only holders of the key can compute nodes, hence roots and proofs of the tree.
+/// Use `Monotree::new_with_hasher()` to plug it in.
+pub struct Blake3Keyed {
+    key: [u8; 32],
+}
+
+impl Blake3Keyed {
+    /// Construct a hasher keyed with the given key.
+    pub fn with_key(key: &[u8; 32]) -> Self {
+        Self::with_config(*key)
+    }
+}
+
+impl Hasher for Blake3Keyed {
+    /// The key, all zeros by default.
+    type Config = [u8; 32];
+
+    fn with_config(key: [u8; 32]) -> Self {
+        Blake3Keyed { key }
+    }
+
+    fn digest(&self, bytes: &[u8]) -> Hash {
+        slice_to_hash(blake3::keyed_hash(&self.key, bytes).as_bytes())
+    }
+}
+
+#[derive(Clone, Debug)]
+/// A hasher wrapping another one to digest under a domain tag, for trees sharing a database.
+///
+/// Every input is prefixed with the tag, preceded by its length so that no tag is
+/// a prefix of another. Hence nodes of trees in different domains never collide,
+/// nor can be spliced between the trees. Use `Monotree::with_hasher()` to plug it in,
+/// and verify proofs with a `DomainHasher` of the same tag.
+pub struct DomainHasher<H> {
+    hasher: H,
+    tag: Vec<u8>,
+}
+
+impl<H> DomainHasher<H> {
+    /// Construct a hasher digesting under the given domain tag.
+    pub fn with_tag<const N: usize>(tag: &[u8]) -> Self
+    where
+        H: Hasher<N>,
+    {
+        DomainHasher {
+            hasher: H::new(),
+            tag: [&(tag.len() as u64).to_be_bytes()[..], tag].concat(),
+        }
+    }
+}
+
+impl<H: Hasher<N>, const N: usize> Hasher<N> for DomainHasher<H> {
+    /// The domain tag, empty by default.
+    type Config = Vec<u8>;
+
+    fn with_config(tag: Vec<u8>) -> Self {
+        Self::with_tag::<N>(&tag)
+    }
+
+    fn digest(&self, bytes: &[u8]) -> Hash<N> {
+        self.hasher.digest(&[&self.tag[..], bytes].concat())
+    }
+}
+
+#[cfg(feature = "hasher_poseidon")]
+#[derive(Clone, Debug)]
+/// A hasher using `Poseidon` hash function over the scalar field of `BN254`,
+/// with the same parameters as the one of `circomlib`.
+///
+/// Bytes are split into 31-byte chunks so that each of them fits in a field element,
+/// preceded by an element of the number of bytes. The elements are absorbed by 12 at most
+/// at a time, where the digest of the former ones comes first to the next absorption.
+/// A circuit hashing the same elements in the same way gets the very same digest.
+pub struct Poseidon;
+
+#[cfg(feature = "hasher_poseidon")]
+impl Poseidon {
+    /// The number of bytes packed into a field element.
+    const CHUNK_LEN: usize = 31;
+    /// The maximum number of elements absorbed at a time.
+    const MAX_INPUTS: usize = 12;
+}
+
+#[cfg(feature = "hasher_poseidon")]
+impl Hasher for Poseidon {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
+        Poseidon
+    }
+
+    fn digest(&self, bytes: &[u8]) -> Hash {
+        use light_poseidon::PoseidonBytesHasher;
+        let len = (bytes.len() as u64).to_be_bytes();
+        let elements: Vec<&[u8]> = std::iter::once(&len[..])
+            .chain(bytes.chunks(Self::CHUNK_LEN))
+            .collect();
+        let mut hash: Option<Hash> = None;
+        let mut rest = &elements[..];
+        while !rest.is_empty() {
+            let n = rest.len().min(Self::MAX_INPUTS - hash.is_some() as usize);
+            let inputs: Vec<&[u8]> = hash
+                .iter()
+                .map(|x| &x[..])
+                .chain(rest[..n].iter().cloned())
+                .collect();
+            let mut poseidon = light_poseidon::Poseidon::<ark_bn254::Fr>::new_circom(inputs.len())
+                .expect("digest(): poseidon");
+            hash = Some(poseidon.hash_bytes_be(&inputs).expect("digest(): poseidon"));
+            rest = &rest[n..];
+        }
+        hash.expect("digest(): poseidon")
+    }
+}
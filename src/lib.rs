@@ -17,12 +17,19 @@
 //! - [`HashMap`](https://lib.rs/crates/hashbrown)
 //! - [`RocksDB`](https://lib.rs/crates/rocksdb)
 //! - [`Sled`](https://lib.rs/crates/sled)
+//! - [`Redb`](https://lib.rs/crates/redb), with `db_redb` feature
+//! - [`LMDB`](https://lib.rs/crates/lmdb), with `db_lmdb` feature
+//! - Databases of async I/O implementing `AsyncDatabase`, used by `AsyncMonotree`, with `async` feature
 //!
 //! _Hashers include_:
-//! - [`Blake3`](https://lib.rs/crates/blake3)
+//! - [`Blake3`](https://lib.rs/crates/blake3), also keyed as `Blake3Keyed`
 //! - [`Blake2s`](https://lib.rs/crates/blake2-rfc) and [`Blake2b`](https://lib.rs/crates/blake2-rfc)
 //! - [`SHA-2`](https://lib.rs/crates/sha2)
-//! - [`SHA-3 (Keccak)`](https://lib.rs/crates/sha3)
+//! - [`SHA-3`](https://lib.rs/crates/sha3)
+//! - [`Keccak-256`](https://lib.rs/crates/sha3)
+//! - [`Poseidon`](https://lib.rs/crates/light-poseidon), with `hasher_poseidon` feature
+//!
+//! Keys are limited to 8KB by default. Enable `bitslen_u32` feature for longer keys.
 //!
 //! # Quick start
 //! ```
@@ -107,14 +114,38 @@
 /// Size of fixed length byte-array from a `Hasher`. Equivalent to `key` length of `monotree`.
 pub const HASH_LEN: usize = 32;
 
+/// A prefix byte put before leaf values on digest when domain separation is on.
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// A prefix byte put before serialized nodes on digest when domain separation is on.
+pub const NODE_PREFIX: u8 = 0x01;
+
+/// A key of the database under which the latest root (or _headroot_) of `monotree` is stored.
+pub const ROOT_KEY: &[u8] = b"_______monotree::headroot_______";
+
+/// A key of the database under which the recent roots pushed by `push_root()` are stored.
+pub const HISTORY_KEY: &[u8] = b"_______monotree::history________";
+
+/// A key of the database under which the names of trees given to `set_headroot_ns()` are stored.
+pub const NAMESPACES_KEY: &[u8] = b"______monotree::namespaces______";
+
+/// A prefix of the database keys under which raw values inserted by `insert_value()` are stored.
+pub const VALUE_PREFIX: &[u8] = b"val::";
+
 /// A type representing length of `Bits`.
+/// This limits the length of keys to `BitsLen::MAX` bits, i.e., 8KB with `u16`.
+#[cfg(not(feature = "bitslen_u32"))]
 pub type BitsLen = u16;
 
-/// A `Result` type redefined for error handling. The same as `std::result::Result<T, Errors>`.
-pub type Result<T> = std::result::Result<T, Errors>;
+/// A type representing length of `Bits`, widened by `bitslen_u32` feature for longer keys.
+#[cfg(feature = "bitslen_u32")]
+pub type BitsLen = u32;
 
-/// A type indicating fixed length byte-array. This has the length of `HASH_LEN`.
-pub type Hash = [u8; HASH_LEN];
+/// A `Result` type redefined for error handling. The same as `Result<T, MonotreeError>`.
+pub type Result<T> = std::result::Result<T, MonotreeError>;
+
+/// A type indicating fixed length byte-array. This has the length of `HASH_LEN` by default.
+pub type Hash<const N: usize = HASH_LEN> = [u8; N];
 
 /// A type representing _Merkle proof_.
 pub type Proof = Vec<(bool, Vec<u8>)>;
@@ -126,34 +157,88 @@ pub type DefaultDatabase = database::MemoryDB;
 pub type DefaultHasher = hasher::Blake3;
 
 pub use self::bits::Bits;
+#[cfg(feature = "async")]
+pub use self::database::AsyncDatabase;
+pub use self::database::Cursor;
 pub use self::database::Database;
 pub use self::hasher::Hasher;
 pub use self::node::{Cell, Node, Unit};
-pub use self::tree::{verify_proof, Monotree};
+#[cfg(feature = "async")]
+pub use self::tree::AsyncMonotree;
+pub use self::tree::{
+    apply_update, commit_roots, compute_root_from_proof, decode_compact_proof_bytes,
+    decode_multiproof_bytes, decode_proof_bytes, encode_compact_proof_bytes,
+    encode_multiproof_bytes, encode_proof_bytes, proof_of_root, verify_compact_proof,
+    verify_domain_separated_proof, verify_domain_separated_value_proof, verify_keyed_proof,
+    verify_multiproof, verify_non_inclusion, verify_proof, verify_proof_bundle,
+    verify_proof_detailed, verify_proof_of_root, verify_proof_with, verify_proofs,
+    verify_self_contained, verify_value_proof, CompactProof, CompactStep, DiffKind, KeyedProof,
+    Monotree, MultiProof, NonInclusionProof, Op, SelfContainedProof, SharedMonotree, StorageStats,
+    UpdateProof,
+};
 
-#[derive(Debug)]
-/// An `Error` type defiend for handling general errors.
-pub struct Errors {
-    details: String,
+/// An `Error` type defined for handling errors, to be matched on by the kind of failure.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MonotreeError {
+    /// A key or hash of a length other than the expected one is given.
+    KeyLength { expected: usize, got: usize },
+    /// A node referenced in the tree is not found in the database.
+    NodeNotFound(Vec<u8>),
+    /// Bytes of nodes or blobs are not well-formed.
+    Corruption(String),
+    /// The database failed.
+    Db(String),
+    /// Bytes of a proof are not well-formed.
+    MalformedProof,
+    /// The headroot is not the one expected by a compare-and-swap, e.g., `cas_insert()`.
+    CasFailed,
+    /// Any other failure, such as an invalid argument.
+    Other(String),
 }
 
-impl Errors {
-    pub fn new(msg: &str) -> Errors {
-        Errors {
-            details: msg.to_string(),
-        }
+/// The former name of `MonotreeError`, kept for compatibility.
+pub type Errors = MonotreeError;
+
+impl MonotreeError {
+    pub fn new(msg: &str) -> MonotreeError {
+        MonotreeError::Other(msg.to_string())
+    }
+
+    /// Get an error of bytes not well-formed, given the reason.
+    pub fn corruption(msg: &str) -> MonotreeError {
+        MonotreeError::Corruption(msg.to_string())
     }
 }
 
-impl std::fmt::Display for Errors {
+impl std::fmt::Display for MonotreeError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.details)
+        match self {
+            MonotreeError::KeyLength { expected, got } => {
+                write!(f, "invalid key length: expected {}, got {}", expected, got)
+            }
+            MonotreeError::NodeNotFound(hash) => {
+                write!(f, "dangling node reference: {}", hex::encode(hash))
+            }
+            MonotreeError::Corruption(msg) | MonotreeError::Db(msg) | MonotreeError::Other(msg) => {
+                write!(f, "{}", msg)
+            }
+            MonotreeError::MalformedProof => write!(f, "malformed proof"),
+            MonotreeError::CasFailed => write!(f, "headroot not as expected"),
+        }
+    }
+}
+
+impl std::error::Error for MonotreeError {}
+
+impl From<String> for MonotreeError {
+    fn from(msg: String) -> Self {
+        MonotreeError::Other(msg)
     }
 }
 
-impl std::error::Error for Errors {
-    fn description(&self) -> &str {
-        &self.details
+impl From<std::io::Error> for MonotreeError {
+    fn from(err: std::io::Error) -> Self {
+        MonotreeError::Other(err.to_string())
     }
 }
 
@@ -32,27 +32,32 @@ pub struct Unit<'a> {
 ///
 /// # Byte-Serialized View
 /// Numbers in parentheses refer to byte length.
-/// By default `HashLen = 32`, `BitsLen = 2`.
+/// `N` refers to the length of hash. By default `N = HASH_LEN = 32`, `BitsLen = 2` (`4` with `bitslen_u32` feature).
 ///
 /// _SoftNode_ = `Cell` + `0x00`(1), where    
-/// `Cell` = `hash`(`HASH_LEN`) + `path`(`< HASH_LEN`) + `range_start`(`BitsLen`) + `range_end`(`BitsLen`).   
+/// `Cell` = `hash`(`N`) + `path`(`< N`) + `range_start`(`BitsLen`) + `range_end`(`BitsLen`).   
 /// `0x00` is an indicator for soft node.  
 ///
 /// _HardNode_ = `Cell_L` + `Cell_R` + `0x01`(1), where    
-/// `Cell_L` = `hash_L`(`HASH_LEN`) + `path_L`(`< HASH_LEN`) + `range_L_start`(`BitsLen`) + `range_L_end`(`BitsLen`)   
-/// `Cell_R` = `path_R`(`< HASH_LEN`) _ `range_R_start`(`BitsLen`) + `range_R_end`(`BitsLen`) + `hash_R`(`HASH_LEN`).   
+/// `Cell_L` = `hash_L`(`N`) + `path_L`(`< N`) + `range_L_start`(`BitsLen`) + `range_L_end`(`BitsLen`)   
+/// `Cell_R` = `path_R`(`< N`) _ `range_R_start`(`BitsLen`) + `range_R_end`(`BitsLen`) + `hash_R`(`N`).   
 /// `0x01` is an indicator for hard node.
 ///
+/// _Compact_ form of nodes has `0x02` and `0x03` as an indicator instead, respectively.
+/// In compact form, `path` and its range are replaced with
+/// `len`(`BitsLen`) + `bits`(`< N`), where `bits` are only the bits in range
+/// packed from the very first bit. This saves some bytes per cell, dropping `range_start`.
+///
 /// To make ***Merkle proof*** easier, we purposely placed the _hashes_ on outskirts of the serialized form.
 /// With only 1-bit information of left or right, provers can easily guess
 /// which side the hash he holds should be appended for the next step.
 /// Refer to `verify_proof()` implementation regarding on this discussion.
-pub enum Node<'a> {
+pub enum Node<'a, const N: usize = HASH_LEN> {
     Soft(Cell<'a>),
     Hard(Cell<'a>, Cell<'a>),
 }
 
-impl<'a> Node<'a> {
+impl<'a, const N: usize> Node<'a, N> {
     pub fn new(lc: Cell<'a>, rc: Cell<'a>) -> Self {
         match (&lc, &rc) {
             (&Some(_), &None) => Node::Soft(lc),
@@ -64,7 +69,7 @@ impl<'a> Node<'a> {
 
     /// Construct `Cell`s by deserializing bytes slice.
     pub fn cells_from_bytes(bytes: &'a [u8], right: bool) -> Result<(Cell<'a>, Cell<'a>)> {
-        match Node::from_bytes(&bytes)? {
+        match Self::from_bytes(bytes)? {
             Node::Soft(cell) => Ok((cell, None)),
             Node::Hard(lc, rc) => {
                 if right {
@@ -76,61 +81,124 @@ impl<'a> Node<'a> {
         }
     }
 
-    fn parse_bytes(bytes: &'a [u8], right: bool) -> Result<(Cell<'a>, usize)> {
+    fn parse_bytes(bytes: &'a [u8], right: bool, compact: bool) -> Result<(Cell<'a>, usize)> {
         let len_bytes = bytes.len();
         let len_bits = std::mem::size_of::<BitsLen>();
-        let offset_hash = if right { 0usize } else { HASH_LEN };
+        let len_header = if compact { len_bits } else { 2 * len_bits };
+        if len_bytes < N + len_header {
+            return Err(MonotreeError::corruption("truncated node"));
+        }
+        let offset_hash = if right { 0usize } else { N };
         let range_hash = if right {
-            len_bytes - HASH_LEN..len_bytes
+            len_bytes - N..len_bytes
         } else {
-            0..HASH_LEN
+            0..N
         };
-        let start: BitsLen = bytes_to_int(&bytes[offset_hash..offset_hash + len_bits]);
-        let end: BitsLen = bytes_to_int(&bytes[offset_hash + len_bits..offset_hash + 2 * len_bits]);
-        let offset_bits = nbytes_across(start, end) as usize;
+        let (range, offset_path) = if compact {
+            let end: BitsLen = bytes_to_int(&bytes[offset_hash..offset_hash + len_bits]);
+            (0..end, offset_hash + len_bits)
+        } else {
+            let start: BitsLen = bytes_to_int(&bytes[offset_hash..offset_hash + len_bits]);
+            let end: BitsLen =
+                bytes_to_int(&bytes[offset_hash + len_bits..offset_hash + 2 * len_bits]);
+            (start..end, offset_hash + 2 * len_bits)
+        };
+        if range.start > range.end {
+            return Err(MonotreeError::corruption("invalid range of node"));
+        }
+        let offset_bits = nbytes_across(range.start, range.end) as usize;
+        let len_path = if right { len_bytes - N } else { len_bytes };
+        if offset_path + offset_bits > len_path {
+            return Err(MonotreeError::corruption("truncated node"));
+        }
         Ok((
             Some(Unit {
                 hash: &bytes[range_hash],
                 bits: Bits {
-                    path: &bytes
-                        [offset_hash + 2 * len_bits..offset_hash + 2 * len_bits + offset_bits],
-                    range: start..end,
+                    path: &bytes[offset_path..offset_path + offset_bits],
+                    range,
                 },
             }),
-            offset_hash + 2 * len_bits + offset_bits,
+            offset_path + offset_bits,
         ))
     }
 
     /// Construct `Node` by deserializing bytes slice.
     pub fn from_bytes(bytes: &'a [u8]) -> Result<Self> {
         match bytes.last() {
-            Some(&0x00) => {
-                let (cell, _) = Node::parse_bytes(&bytes[..bytes.len() - 1], false)?;
+            Some(&x) if x == 0x00 || x == 0x02 => {
+                let (cell, _) = Self::parse_bytes(&bytes[..bytes.len() - 1], false, x == 0x02)?;
                 Ok(Node::Soft(cell))
             }
-            Some(&0x01) => {
-                let (lc, size) = Node::parse_bytes(&bytes, false)?;
-                let (rc, _) = Node::parse_bytes(&bytes[size..bytes.len() - 1], true)?;
+            Some(&x) if x == 0x01 || x == 0x03 => {
+                let (lc, size) = Self::parse_bytes(bytes, false, x == 0x03)?;
+                if size > bytes.len() - 1 {
+                    return Err(MonotreeError::corruption("truncated node"));
+                }
+                let (rc, _) = Self::parse_bytes(&bytes[size..bytes.len() - 1], true, x == 0x03)?;
+                Self::check_sides(&lc, &rc, false)?;
                 Ok(Node::Hard(lc, rc))
             }
-            _ => unreachable!("Node::from_bytes()"),
+            _ => Err(MonotreeError::corruption("invalid node")),
+        }
+    }
+
+    /// Check if the cells of a hard node lie on the opposite sides of each other,
+    /// i.e., their first bits differ. Returns whether the cells are in the reverse order,
+    /// which is allowed only when `swappable`, otherwise the node is regarded as corrupt.
+    fn check_sides(lc: &Cell, rc: &Cell, swappable: bool) -> Result<bool> {
+        match (lc, rc) {
+            (Some(lu), Some(ru)) if !lu.bits.is_empty() && !ru.bits.is_empty() => {
+                match (lu.bits.first(), ru.bits.first()) {
+                    (false, true) => Ok(false),
+                    (true, false) if swappable => Ok(true),
+                    _ => Err(MonotreeError::corruption(
+                        "corrupt node: cells on the same side",
+                    )),
+                }
+            }
+            _ => Err(MonotreeError::corruption("corrupt node: empty cell")),
         }
     }
 
     /// Serialize `Node` into bytes.
     pub fn to_bytes(&self) -> Result<Vec<u8>> {
-        match self {
-            Node::Soft(Some(unit)) => {
-                Ok([&unit.hash[..], &unit.bits.to_bytes()?, &[0x00]].concat())
+        self.serialize(false)
+    }
+
+    /// Serialize `Node` into bytes in compact form.
+    pub fn to_compact_bytes(&self) -> Result<Vec<u8>> {
+        self.serialize(true)
+    }
+
+    fn serialize(&self, compact: bool) -> Result<Vec<u8>> {
+        let bits_to_bytes = |bits: &Bits| {
+            if compact {
+                bits.to_compact_bytes()
+            } else {
+                bits.to_bytes()
             }
-            Node::Hard(Some(lu), Some(ru)) => {
-                let (lu, ru) = if ru.bits.first() { (lu, ru) } else { (ru, lu) };
+        };
+        match self {
+            Node::Soft(Some(unit)) => Ok([
+                unit.hash,
+                &bits_to_bytes(&unit.bits)?,
+                &[if compact { 0x02 } else { 0x00 }],
+            ]
+            .concat()),
+            Node::Hard(lc, rc) => {
+                let swapped = Self::check_sides(lc, rc, true)?;
+                let (lu, ru) = match (lc, rc) {
+                    (Some(lu), Some(ru)) if swapped => (ru, lu),
+                    (Some(lu), Some(ru)) => (lu, ru),
+                    _ => unreachable!("node.to_bytes()"),
+                };
                 Ok([
-                    &lu.hash[..],
-                    &lu.bits.to_bytes()?,
-                    &ru.bits.to_bytes()?,
-                    &ru.hash[..],
-                    &[0x01],
+                    lu.hash,
+                    &bits_to_bytes(&lu.bits)?,
+                    &bits_to_bytes(&ru.bits)?,
+                    ru.hash,
+                    &[if compact { 0x03 } else { 0x01 }],
                 ]
                 .concat())
             }
@@ -1,12 +1,78 @@
 //! A module implementing `monotree`.
+use crate::database::DynDatabase;
+#[cfg(feature = "async")]
+use crate::database::{AsyncDatabase, MemoryDB};
 use crate::utils::*;
 use crate::*;
+use hashbrown::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::io::{Read, Write};
+use std::sync::Mutex;
 
-/// A structure for `monotree`.
-#[derive(Debug)]
-pub struct Monotree<D = DefaultDatabase, H = DefaultHasher> {
+/// An operation on `monotree` to be applied in batch mode by `apply()` or `apply_batch()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op<const N: usize = HASH_LEN> {
+    /// Insert an entry of key and leaf.
+    Insert(Hash<N>, Hash<N>),
+    /// Remove an entry of key.
+    Remove(Hash<N>),
+}
+
+/// How an entry differs from an old root to a new one, as listed by `Monotree::diff()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiffKind<const N: usize = HASH_LEN> {
+    /// The key is newly inserted with the leaf.
+    Added(Hash<N>),
+    /// The key is removed.
+    Removed,
+    /// The key is updated to the leaf.
+    Changed(Hash<N>),
+}
+
+impl<const N: usize> Op<N> {
+    /// Get the key the operation is applied to.
+    pub fn key(&self) -> &Hash<N> {
+        match self {
+            Op::Insert(key, _) | Op::Remove(key) => key,
+        }
+    }
+}
+
+/// What remains of a subtree after removing a key from it.
+enum Remains<const N: usize> {
+    /// The key is not found, so the subtree remains unchanged.
+    Unchanged,
+    /// Nothing remains.
+    Empty,
+    /// A node remains.
+    Node(Hash<N>),
+    /// Only a cell remains, of which bits are to be merged into the parent's cell.
+    Cell(Vec<bool>, Hash<N>),
+}
+
+/// A lookup memoized by `Monotree::get_with_side()`: root, key and the result.
+type Memo<const N: usize> = (Hash<N>, Hash<N>, Option<(Hash<N>, bool)>);
+
+/// A structure for `monotree`. Cloned when both the database and the hasher are,
+/// e.g., to fork a tree on `MemoryDB` into an independent copy.
+#[derive(Clone, Debug)]
+pub struct Monotree<D = DefaultDatabase, H = DefaultHasher, const N: usize = HASH_LEN> {
     db: D,
     hasher: H,
+    compact: bool,
+    domain: bool,
+    collision_check: bool,
+    auto_head: bool,
+    memoize: bool,
+    memo: Option<Memo<N>>,
+    published_len: usize,
+    prefetch: bool,
+    verify_on_read: bool,
+    history_len: usize,
+    ns: Vec<u8>,
+    writes: HashSet<Hash<N>>,
+    batch_open: bool,
+    cleared: bool,
 }
 
 impl Default for Monotree<DefaultDatabase, DefaultHasher> {
@@ -15,35 +81,513 @@ impl Default for Monotree<DefaultDatabase, DefaultHasher> {
     }
 }
 
-impl<D, H> Monotree<D, H>
+impl<H: Hasher<N>, const N: usize> Monotree<DynDatabase, H, N> {
+    /// Open a `monotree` on the database of the given kind chosen at runtime,
+    /// e.g., `"rocksdb"` from a config file. Refer to `DynDatabase::open()`.
+    pub fn open(kind: &str, dbpath: &str) -> Result<Self> {
+        Ok(Self::with_db(DynDatabase::open(kind, dbpath)?))
+    }
+}
+
+impl<D, H, const N: usize> Monotree<D, H, N>
 where
     D: Database,
-    H: Hasher,
+    H: Hasher<N>,
 {
     pub fn new(dbpath: &str) -> Self {
         let db = Database::new(dbpath);
-        let hasher = Hasher::new();
-        Monotree { db, hasher }
+        Self::with_db(db)
+    }
+
+    /// Construct a `monotree` scoping all its keys in the database under the namespace `ns`,
+    /// so that several independent trees live in a single database without reading
+    /// each other's nodes, values and headroots.
+    ///
+    /// Keys are prefixed with `ns` as they are, so namespaces must not be prefixes of one another.
+    /// A tree without namespace, the default, stores keys as they are as before.
+    pub fn with_namespace(dbpath: &str, ns: &[u8]) -> Self {
+        let mut tree = Self::new(dbpath);
+        tree.ns = ns.to_vec();
+        tree
+    }
+
+    /// Construct a `monotree` digesting with the given hasher instance,
+    /// e.g., a hasher constructed with its own state by `Hasher::with_config()`.
+    pub fn new_with_hasher(dbpath: &str, hasher: H) -> Self {
+        Self::new(dbpath).with_hasher(hasher)
+    }
+
+    /// Open a `monotree` on the database in read-only mode, e.g., on a checkpoint of RocksDB.
+    /// Lookups and proofs work as usual, while operations modifying the tree fail.
+    pub fn open_readonly(dbpath: &str) -> Result<Self> {
+        Ok(Self::with_db(D::open_readonly(dbpath)?))
+    }
+
+    /// Construct a `monotree` on top of the given database instance.
+    pub fn with_db(db: D) -> Self {
+        let hasher = H::new();
+        Monotree {
+            db,
+            hasher,
+            compact: false,
+            domain: false,
+            collision_check: false,
+            auto_head: false,
+            memoize: false,
+            memo: None,
+            published_len: N,
+            prefetch: false,
+            verify_on_read: false,
+            history_len: 16,
+            ns: Vec::new(),
+            writes: HashSet::new(),
+            batch_open: false,
+            cleared: false,
+        }
+    }
+
+    /// Use the given hasher instance rather than the one from `Hasher::new()`,
+    /// e.g., a hasher with its own state such as `DomainHasher`.
+    pub fn with_hasher(mut self, hasher: H) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Set whether to write nodes in compact form, which saves some bytes per node.
+    ///
+    /// Note that a tree in compact form has roots different from the one in default form,
+    /// even for the same entries. Nodes in both forms are readable regardless of this option.
+    pub fn with_compact_node_encoding(mut self, on: bool) -> Self {
+        self.compact = on;
+        self
+    }
+
+    /// Set whether to separate the domains of leaves and nodes on digest.
+    ///
+    /// When on, nodes are digested with `NODE_PREFIX` ahead of their bytes,
+    /// and values are digested into leaves with `LEAF_PREFIX` by `hash_leaf()`,
+    /// so that a leaf can never be taken for a node and vice versa.
+    /// Proofs of such a tree must be verified with `verify_domain_separated_proof()`.
+    pub fn with_domain_separation(mut self, on: bool) -> Self {
+        self.domain = on;
+        self
+    }
+
+    /// Set whether to check in `inserts()` that no node hash produced equals any of input keys.
+    ///
+    /// Since nodes are stored under their hashes, the same space as the one of keys,
+    /// such a collision may confuse callers sharing the database with their own entries.
+    /// It would never happen with a cryptographic hasher, so this is rather a paranoia check.
+    pub fn with_collision_check(mut self, on: bool) -> Self {
+        self.collision_check = on;
+        self
+    }
+
+    /// Set whether to write the new root to `ROOT_KEY` on every operation modifying the tree,
+    /// so that the headroot always tracks the latest state without `set_headroot()`.
+    ///
+    /// The headroot is written in the same batch as the nodes of the operation.
+    /// Off by default, where the headroot is up to callers.
+    pub fn with_auto_head(mut self, on: bool) -> Self {
+        self.auto_head = on;
+        self
+    }
+
+    /// Set whether to memoize the last lookup, so that looking up the same key
+    /// against the same root again, e.g., polling a key until it changes, skips walking the tree.
+    ///
+    /// Only a single lookup is memoized, which is replaced whenever the root or key differs.
+    pub fn with_memo(mut self, on: bool) -> Self {
+        self.memoize = on;
+        self.memo = None;
+        self
+    }
+
+    /// Set whether to have the database read ahead the child nodes of each node walked
+    /// in `get()` and `insert()`, by `Database::prefetch()`.
+    ///
+    /// Only one of the children is needed by the walk, while the other is likely
+    /// to be needed soon by walks of other keys, e.g., in `gets()` or `inserts()`
+    /// walking keys in order. Overlapping the reads may pay off on backends of high latency
+    /// such as network-attached storage, where reads in strict series add up.
+    pub fn with_prefetch(mut self, on: bool) -> Self {
+        self.prefetch = on;
+        self
+    }
+
+    /// Set whether to check every node read against the hash it is stored under,
+    /// failing with `MonotreeError::Corruption` on mismatch, e.g., on disk corruption
+    /// or a database of another tree opened by mistake.
+    ///
+    /// Each node read costs an extra hash fn call, so this is off by default.
+    pub fn with_verify_on_read(mut self, on: bool) -> Self {
+        self.verify_on_read = on;
+        self
+    }
+
+    /// Set the number of recent roots kept by `push_root()`, 16 by default.
+    pub fn with_history_capacity(mut self, capacity: usize) -> Self {
+        self.history_len = capacity;
+        self
+    }
+
+    /// Set the length in bytes of roots published by `published_root()`, `N` by default.
+    ///
+    /// Roots are truncated only when published for external commitment, e.g., on-chain,
+    /// while nodes are still addressed by full hashes inside the tree.
+    /// Note that a root truncated to `len` bytes resists collisions only as much as
+    /// a hash of `len` bytes does, i.e., about `2^(4 * len)` digests to find a collision.
+    ///
+    /// `len` out of range is clamped to `1..=N`, i.e., roots are never published empty
+    /// nor longer than the hash itself.
+    pub fn with_published_root_len(mut self, len: usize) -> Self {
+        self.published_len = len.clamp(1, N);
+        self
+    }
+
+    /// Get the given root truncated to the length set by `with_published_root_len()`.
+    ///
+    /// To verify a Merkle proof against a published root, compute the full root
+    /// by `compute_root_from_proof()` and compare its prefix with the published one.
+    pub fn published_root(&self, root: &Hash<N>) -> Vec<u8> {
+        root[..self.published_len].to_vec()
+    }
+
+    /// Digest the given value into a leaf, with `LEAF_PREFIX` if domain separation is on.
+    pub fn hash_leaf(&self, value: &[u8]) -> Hash<N> {
+        if self.domain {
+            self.hasher.digest(&[&[LEAF_PREFIX], value].concat())
+        } else {
+            self.hasher.digest(value)
+        }
+    }
+
+    /// Get the number of DB writes made by the last operation modifying the tree.
+    ///
+    /// Writes of the same node are counted only once, as the batch coalesces them.
+    /// Batch operations such as `inserts()` are regarded as a single operation.
+    pub fn last_op_writes(&self) -> usize {
+        self.writes.len()
+    }
+
+    /// Retrieve the latest state (root) of the `monotree` stored under `ROOT_KEY`.
+    pub fn get_headroot(&mut self) -> Result<Option<Hash<N>>> {
+        self.get_headroot_at(ROOT_KEY)
+    }
+
+    /// Set the latest state (root) of the `monotree` to the database under `ROOT_KEY`.
+    /// Setting `None` removes the headroot.
+    pub fn set_headroot(&mut self, headroot: Option<&Hash<N>>) -> Result<()> {
+        self.set_headroot_at(ROOT_KEY, headroot)
+    }
+
+    /// Retrieve the latest root of the tree named `ns`, among several trees in a database.
+    /// Refer to `set_headroot_ns()`.
+    pub fn get_headroot_ns(&mut self, ns: &str) -> Result<Option<Hash<N>>> {
+        let key = self.headroot_key_ns(ns);
+        self.get_headroot_at(&key)
+    }
+
+    /// Set the latest root of the tree named `ns`, so that several trees in a database
+    /// track their own headroots. It is stored under the digest of `ns` followed by `ROOT_KEY`.
+    ///
+    /// The name is kept under `NAMESPACES_KEY`, so that `prune()` keeps the tree alive.
+    pub fn set_headroot_ns(&mut self, ns: &str, headroot: Option<&Hash<N>>) -> Result<()> {
+        let mut names = self.namespaces()?;
+        if headroot.is_some() && !names.iter().any(|name| name == ns) {
+            names.push(ns.to_string());
+            let mut bytes: Vec<u8> = Vec::new();
+            for name in names.iter() {
+                bytes.extend_from_slice(&(name.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(name.as_bytes());
+            }
+            self.db_put(NAMESPACES_KEY, bytes)?;
+        }
+        let key = self.headroot_key_ns(ns);
+        self.set_headroot_at(&key, headroot)
+    }
+
+    /// Get the names of trees given to `set_headroot_ns()`, in order of first set.
+    pub fn namespaces(&mut self) -> Result<Vec<String>> {
+        let bytes = match self.db_get(NAMESPACES_KEY)? {
+            Some(bytes) => bytes,
+            None => return Ok(Vec::new()),
+        };
+        let malformed = || MonotreeError::corruption("namespaces(): malformed names");
+        let mut names: Vec<String> = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            if rest.len() < 4 {
+                return Err(malformed());
+            }
+            let len = u32::from_be_bytes(slice_to_array(&rest[..4])) as usize;
+            if rest.len() < 4 + len {
+                return Err(malformed());
+            }
+            let name = std::str::from_utf8(&rest[4..4 + len]).map_err(|_| malformed())?;
+            names.push(name.to_string());
+            rest = &rest[4 + len..];
+        }
+        Ok(names)
+    }
+
+    /// Push the given root to the history of recent roots stored under `HISTORY_KEY`,
+    /// e.g., on every commit, so that previous states can be looked up for rollback.
+    /// Once the history is full, the oldest root is dropped.
+    pub fn push_root(&mut self, root: &Hash<N>) -> Result<()> {
+        let mut roots = self.roots_history()?;
+        roots.insert(0, *root);
+        roots.truncate(self.history_len);
+        self.db_put(HISTORY_KEY, roots.concat())
+    }
+
+    /// Get the recent roots pushed by `push_root()`, newest first.
+    pub fn roots_history(&mut self) -> Result<Vec<Hash<N>>> {
+        match self.db_get(HISTORY_KEY)? {
+            Some(bytes) if bytes.len() % N == 0 => {
+                Ok(bytes.chunks_exact(N).map(slice_to_array).collect())
+            }
+            Some(_) => Err(MonotreeError::corruption(
+                "roots_history(): malformed history",
+            )),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn headroot_key_ns(&self, ns: &str) -> Hash<N> {
+        self.hasher.digest(&[ns.as_bytes(), ROOT_KEY].concat())
+    }
+
+    fn get_headroot_at(&mut self, key: &[u8]) -> Result<Option<Hash<N>>> {
+        match self.db_get(key)? {
+            Some(root) => Ok(Some(try_slice_to_array(&root)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_headroot_at(&mut self, key: &[u8], headroot: Option<&Hash<N>>) -> Result<()> {
+        match headroot {
+            Some(root) => self.db_put(key, root.to_vec()),
+            None => self.db_delete(key),
+        }
+    }
+
+    /// Get the key in the database of the given key, prefixed with the namespace if any.
+    fn ns_key<'a>(&self, key: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.ns.is_empty() {
+            Cow::Borrowed(key)
+        } else {
+            Cow::Owned([&self.ns, key].concat())
+        }
+    }
+
+    fn db_get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let key = self.ns_key(key);
+        self.db.get(&key)
+    }
+
+    fn db_put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        let key = self.ns_key(key);
+        self.db.put(&key, value)
+    }
+
+    fn db_delete(&mut self, key: &[u8]) -> Result<()> {
+        let key = self.ns_key(key);
+        self.db.delete(&key)
+    }
+
+    /// Scan keys beginning with the given prefix in the namespace, with the namespace stripped.
+    fn db_scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let prefix = self.ns_key(prefix);
+        let keys = self.db.scan(&prefix)?;
+        Ok(keys
+            .into_iter()
+            .map(|key| key[self.ns.len()..].to_vec())
+            .collect())
     }
 
     /// Insert key-leaf entry into the `monotree`. Returns a new root hash.
-    pub fn insert(&mut self, root: Option<&Hash>, key: &Hash, leaf: &Hash) -> Result<Option<Hash>> {
+    pub fn insert(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<Option<Hash<N>>> {
+        self.writes.clear();
+        self.atomic(|tree| tree.insert_entry(root, key, leaf))
+    }
+
+    /// Insert key-leaf entry into the tree, the same as `insert()`, returning the leaf
+    /// replaced along with a new root hash, e.g., to track the change of a value of a key.
+    /// The leaf replaced is `None` if the key is new to the tree.
+    pub fn insert_returning_old(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<(Option<Hash<N>>, Option<Hash<N>>)> {
+        let old = self.get(root, key)?;
+        let root = self.insert(root, key, leaf)?;
+        Ok((root, old))
+    }
+
+    /// Insert key-leaf entry into the tree of the headroot, only if the headroot is still
+    /// the expected one, e.g., for writers deriving new roots from a shared head.
+    ///
+    /// The headroot is checked and set to the new root in batch mode along with the insertion,
+    /// so that they are committed together. Returns a new root hash, or fails with
+    /// `MonotreeError::CasFailed` leaving the tree unchanged if the headroot has moved.
+    /// On any other failure, the batch is discarded and the tree is left unchanged as well.
+    ///
+    /// Note that the check and the set are atomic only among the writers of this `Monotree`,
+    /// e.g., sharing it by `SharedMonotree`. Writers of other instances on the same database
+    /// may move the headroot in between, so they must be serialized by the caller.
+    pub fn cas_insert(
+        &mut self,
+        expected_head: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<Option<Hash<N>>> {
+        self.writes.clear();
+        self.in_batch(|tree| {
+            if tree.get_headroot()?.as_ref() != expected_head {
+                return Err(MonotreeError::CasFailed);
+            }
+            let root = tree.insert_entry(expected_head, key, leaf)?;
+            tree.set_headroot(root.as_ref())?;
+            Ok(root)
+        })
+    }
+
+    /// Insert key-leaf entry into the `monotree`. Returns a new root hash
+    /// along with the serialized bytes of the new root node, to be shipped to peers
+    /// who can then request the nodes under it on demand.
+    pub fn insert_returning_root_node(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<Option<(Hash<N>, Vec<u8>)>> {
+        match self.insert(root, key, leaf)? {
+            Some(root) => Ok(Some((root, self.get_node(&root)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Insert a value of arbitrary length under the given key. Returns a new root hash.
+    ///
+    /// The value is digested into a leaf by `hash_leaf()`, which is then inserted into the tree
+    /// as the leaf of the key. Thus, the root commits to the value and Merkle proofs work as usual.
+    /// The raw value is stored under `VALUE_PREFIX` followed by the leaf, in the same batch
+    /// as the nodes, so that the tree and its values are committed atomically.
+    pub fn insert_value(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<Hash<N>>> {
+        self.writes.clear();
+        let leaf = self.hash_leaf(value);
+        self.in_batch(|tree| {
+            tree.db_put(&value_key(&leaf), value.to_vec())?;
+            tree.writes.insert(leaf);
+            let root = tree.insert_entry(root, key, &leaf)?;
+            tree.update_head(root.as_ref())?;
+            Ok(root)
+        })
+    }
+
+    /// Run a single operation modifying the tree. When auto head is on, the operation runs
+    /// in batch mode along with writing the resulting root to `ROOT_KEY`.
+    fn atomic<F>(&mut self, op: F) -> Result<Option<Hash<N>>>
+    where
+        F: FnOnce(&mut Self) -> Result<Option<Hash<N>>>,
+    {
+        if !self.auto_head {
+            return op(self);
+        }
+        self.in_batch(|tree| {
+            let root = op(tree)?;
+            tree.set_headroot(root.as_ref())?;
+            Ok(root)
+        })
+    }
+
+    /// Run the given operation in batch mode. The batch is finished if the operation succeeds,
+    /// otherwise discarded, so that no batch is left open by an operation failed in the middle.
+    fn in_batch<T, F>(&mut self, op: F) -> Result<T>
+    where
+        F: FnOnce(&mut Self) -> Result<T>,
+    {
+        self.db.init_batch()?;
+        self.batch_open = true;
+        match op(self) {
+            Ok(value) => {
+                self.db.finish_batch()?;
+                self.batch_open = false;
+                Ok(value)
+            }
+            Err(err) => {
+                self.db.discard_batch()?;
+                self.batch_open = false;
+                Err(err)
+            }
+        }
+    }
+
+    /// Write the given root to `ROOT_KEY` if auto head is on. Called in batch mode.
+    fn update_head(&mut self, root: Option<&Hash<N>>) -> Result<()> {
+        if self.auto_head {
+            self.set_headroot(root)?;
+        }
+        Ok(())
+    }
+
+    fn insert_entry(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<Option<Hash<N>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
         match root {
             None => {
-                let (hash, bits) = (leaf, Bits::new(key));
+                let (hash, bits) = (leaf, Bits::new(&key));
                 self.put_node(Node::new(Some(Unit { hash, bits }), None))
             }
-            Some(root) => self.put(root, Bits::new(key), leaf),
+            Some(root) => self.put(root, Bits::new(&key), leaf),
         }
     }
 
-    fn put_node(&mut self, node: Node) -> Result<Option<Hash>> {
-        let bytes = node.to_bytes()?;
-        let hash = self.hasher.digest(&bytes);
-        self.db.put(&hash, bytes)?;
+    fn put_node(&mut self, node: Node<N>) -> Result<Option<Hash<N>>> {
+        let (hash, bytes) = digest_node(&self.hasher, &node, self.compact, self.domain)?;
+        self.db_put(&hash, bytes)?;
+        self.writes.insert(hash);
         Ok(Some(hash))
     }
 
+    /// Get the bytes of node stored under the given hash, failing when it is not found.
+    fn get_node(&mut self, hash: &[u8]) -> Result<Vec<u8>> {
+        match self.db_get(hash)? {
+            Some(bytes) => {
+                self.check_node(hash, &bytes)?;
+                Ok(bytes)
+            }
+            None => Err(MonotreeError::NodeNotFound(hash.to_vec())),
+        }
+    }
+
+    /// Fail if the bytes of node do not digest into the given hash, when verify on read is on.
+    fn check_node(&self, hash: &[u8], bytes: &[u8]) -> Result<()> {
+        if self.verify_on_read && digest_node_bytes(&self.hasher, bytes, self.domain)[..] != *hash {
+            return Err(MonotreeError::Corruption(format!(
+                "node integrity check failed: {}",
+                hex!(hash)
+            )));
+        }
+        Ok(())
+    }
+
     /// Recursively insert a bytes (in forms of Bits) and a leaf into the tree.  
     ///
     /// Optimization in `monotree` is mainly to compress the path as much as possible
@@ -69,18 +613,28 @@ where
     /// * split-node (2)
     ///     immediately split node into two with the longest common prefix,
     ///     then wind the recursive stack from there returning resulting hashes.
-    fn put(&mut self, root: &[u8], bits: Bits, leaf: &[u8]) -> Result<Option<Hash>> {
-        let bytes = self.db.get(root)?.expect("bytes");
-        let (lc, rc) = Node::cells_from_bytes(&bytes, bits.first())?;
-        let unit = lc.as_ref().expect("put(): left-unit");
+    ///
+    /// When the leaf put is the same as the existing one, no node on the path changes.
+    /// Then the given root is returned as it is, without any hash fn call or DB write.
+    fn put(&mut self, root: &[u8], bits: Bits, leaf: &[u8]) -> Result<Option<Hash<N>>> {
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        self.prefetch_cells(&[&lc, &rc], bits.len())?;
+        let unit = lc
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("put(): left-unit"))?;
         let n = Bits::len_common_bits(&unit.bits, &bits);
         match n {
             n if n == 0 => self.put_node(Node::new(lc, Some(Unit { hash: leaf, bits }))),
+            n if n == bits.len() && unit.hash == leaf => Ok(Some(slice_to_array(root))),
             n if n == bits.len() => self.put_node(Node::new(Some(Unit { hash: leaf, bits }), rc)),
             n if n == unit.bits.len() => {
                 let hash = &self
                     .put(unit.hash, bits.shift(n, false), leaf)?
-                    .expect("put(): hash");
+                    .ok_or_else(|| MonotreeError::corruption("put(): hash"))?;
+                if unit.hash == hash {
+                    return Ok(Some(slice_to_array(root)));
+                }
                 let unit = unit.to_owned();
                 self.put_node(Node::new(Some(Unit { hash, ..unit }), rc))
             }
@@ -94,174 +648,2823 @@ where
 
                 let hash = &self
                     .put_node(Node::new(Some(lu), Some(ru)))?
-                    .expect("put(): hash");
+                    .ok_or_else(|| MonotreeError::corruption("put(): hash"))?;
                 let bits = cloned.shift(n, true);
                 self.put_node(Node::new(Some(Unit { hash, bits }), rc))
             }
         }
     }
 
+    /// Check if the given root is a node in the database, e.g., to reject an unknown root
+    /// given by a client before walking down the tree from it.
+    pub fn root_exists(&mut self, root: &Hash<N>) -> Result<bool> {
+        Ok(self.db_get(root)?.is_some())
+    }
+
     /// Get a leaf hash for the given root and key.
-    pub fn get(&mut self, root: Option<&Hash>, key: &Hash) -> Result<Option<Hash>> {
-        match root {
+    pub fn get(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        Ok(self.get_with_side(root, key)?.map(|(leaf, _)| leaf))
+    }
+
+    /// Get a leaf hash for the given root and key, along with its side at the parent node:
+    /// `true` if it is the right one, the same as the `right` flag of `Node::cells_from_bytes()`.
+    pub fn get_with_side(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<Option<(Hash<N>, bool)>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        let root = match root {
+            None => return Ok(None),
+            Some(root) => root,
+        };
+        match self.memo {
+            Some((r, k, found)) if self.memoize && r == *root && k == key => Ok(found),
+            _ => {
+                let found = match self.find_key(root, Bits::new(&key)) {
+                    Err(MonotreeError::NodeNotFound(hash)) if self.cleared && hash == root[..] => {
+                        None
+                    }
+                    found => found?,
+                };
+                if self.memoize {
+                    self.memo = Some((*root, key, found));
+                }
+                Ok(found)
+            }
+        }
+    }
+
+    /// Get a value inserted by `insert_value()` for the given root and key.
+    pub fn get_value(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.get(root, key)? {
             None => Ok(None),
-            Some(root) => self.find_key(root, Bits::new(key)),
+            Some(leaf) => match self.db_get(&value_key(&leaf))? {
+                Some(value) => Ok(Some(value)),
+                None => Err(MonotreeError::Corruption(format!(
+                    "value not found: {}",
+                    hex!(leaf)
+                ))),
+            },
         }
     }
 
-    fn find_key(&mut self, root: &[u8], bits: Bits) -> Result<Option<Hash>> {
-        let bytes = self.db.get(root)?.expect("bytes");
-        let (cell, _) = Node::cells_from_bytes(&bytes, bits.first())?;
-        let unit = cell.as_ref().expect("find_key(): left-unit");
+    fn find_key(&mut self, root: &[u8], bits: Bits) -> Result<Option<(Hash<N>, bool)>> {
+        let bytes = self.get_node(root)?;
+        let (cell, other) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        self.prefetch_cells(&[&cell, &other], bits.len())?;
+        let unit = cell
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("find_key(): left-unit"))?;
         let n = Bits::len_common_bits(&unit.bits, &bits);
         match n {
-            n if n == bits.len() => Ok(Some(slice_to_hash(unit.hash))),
+            n if n == bits.len() => Ok(Some((slice_to_array(unit.hash), bits.first()))),
             n if n == unit.bits.len() => self.find_key(&unit.hash, bits.shift(n, false)),
             _ => Ok(None),
         }
     }
 
-    /// Remove the given key and its corresponding leaf from the tree. Returns a new root hash.
-    pub fn remove(&mut self, root: Option<&Hash>, key: &[u8]) -> Result<Option<Hash>> {
+    /// Get the hash of the subtree holding the entries of keys beginning with the given prefix
+    /// of bits, e.g., to compare a shard of the keyspace between replicas by a single hash.
+    /// Returns `None` if no key in the tree begins with the prefix.
+    ///
+    /// When the prefix ends where the tree branches, this is the hash of the node there.
+    /// Since paths are compressed, the prefix may end in the middle of a path instead.
+    /// Then this is the hash of a soft node holding the rest of the path, as if the node were
+    /// stored there, so that the hash depends only on the entries under the prefix.
+    pub fn subtree_root(
+        &mut self,
+        root: Option<&Hash<N>>,
+        prefix: &Bits,
+    ) -> Result<Option<Hash<N>>> {
         match root {
             None => Ok(None),
-            Some(root) => self.delete_key(root, Bits::new(key)),
+            Some(root) if prefix.is_empty() => Ok(Some(*root)),
+            Some(root) => self.find_subtree(root, prefix.clone()),
         }
     }
 
-    fn delete_key(&mut self, root: &[u8], bits: Bits) -> Result<Option<Hash>> {
-        let bytes = self.db.get(root)?.expect("bytes");
-        let (lc, rc) = Node::cells_from_bytes(&bytes, bits.first())?;
-        let unit = lc.as_ref().expect("delete_key(): left-unit");
+    fn find_subtree(&mut self, root: &[u8], bits: Bits) -> Result<Option<Hash<N>>> {
+        let bytes = self.get_node(root)?;
+        let (cell, _) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        let unit = cell
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("find_subtree(): left-unit"))?;
         let n = Bits::len_common_bits(&unit.bits, &bits);
         match n {
-            n if n == bits.len() => match rc {
-                Some(_) => self.put_node(Node::new(None, rc)),
-                None => Ok(None),
-            },
-            n if n == unit.bits.len() => {
-                let hash = self.delete_key(&unit.hash, bits.shift(n, false))?;
-                match (hash, &rc) {
-                    (None, None) => Ok(None),
-                    (None, Some(_)) => self.put_node(Node::new(None, rc)),
-                    (Some(ref hash), _) => {
-                        let unit = unit.to_owned();
-                        let lc = Some(Unit { hash, ..unit });
-                        self.put_node(Node::new(lc, rc))
-                    }
-                }
+            n if n == unit.bits.len() && n == bits.len() => Ok(Some(slice_to_array(unit.hash))),
+            n if n == bits.len() => {
+                let (hash, bits) = (unit.hash, unit.bits.shift(n, false));
+                let node = Node::new(Some(Unit { hash, bits }), None);
+                let (hash, _) = digest_node(&self.hasher, &node, self.compact, self.domain)?;
+                Ok(Some(hash))
             }
+            n if n == unit.bits.len() => self.find_subtree(unit.hash, bits.shift(n, false)),
             _ => Ok(None),
         }
     }
 
-    /// This method is intended to use the `insert()` method in batch mode.
-    pub fn inserts(
-        &mut self,
-        root: Option<&Hash>,
-        keys: &[Hash],
-        leaves: &[Hash],
-    ) -> Result<Option<Hash>> {
-        let indices = get_sorted_indices(keys, false);
-        self.db.init_batch()?;
-        let mut root = root.cloned();
-        for i in indices.iter() {
-            root = self.insert(root.as_ref(), &keys[*i], &leaves[*i])?;
+    /// Have the database read ahead the nodes the given cells link to, if prefetch is on.
+    /// Cells as long as the bits of key remaining link to leaves rather than nodes.
+    fn prefetch_cells(&mut self, cells: &[&Cell], remaining: BitsLen) -> Result<()> {
+        if !self.prefetch {
+            return Ok(());
         }
-        self.db.finish_batch()?;
-        Ok(root)
+        let keys: Vec<Cow<[u8]>> = cells
+            .iter()
+            .filter_map(|cell| cell.as_ref())
+            .filter(|unit| unit.bits.len() < remaining)
+            .map(|unit| self.ns_key(unit.hash))
+            .collect();
+        let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_ref()).collect();
+        self.db.prefetch(&keys)
     }
 
-    /// This method is intended to use the `get()` method in batch mode.
-    pub fn gets(&mut self, root: Option<&Hash>, keys: &[Hash]) -> Result<Vec<Option<Hash>>> {
-        let mut leaves: Vec<Option<Hash>> = Vec::new();
-        for key in keys.iter() {
-            leaves.push(self.get(root, key)?);
+    /// Check if the given key exists in the tree, without fetching its leaf.
+    pub fn contains(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<bool> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        match root {
+            None => Ok(false),
+            Some(root) => self.has_key(root, Bits::new(&key)),
         }
-        Ok(leaves)
     }
 
-    /// This method is intended to use the `remove()` method in batch mode.
-    pub fn removes(&mut self, root: Option<&Hash>, keys: &[Hash]) -> Result<Option<Hash>> {
-        let indices = get_sorted_indices(keys, false);
-        let mut root = root.cloned();
-        self.db.init_batch()?;
-        for i in indices.iter() {
-            root = self.remove(root.as_ref(), &keys[*i])?;
+    fn has_key(&mut self, root: &[u8], bits: Bits) -> Result<bool> {
+        let bytes = self.get_node(root)?;
+        let (cell, _) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        let unit = cell
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("has_key(): left-unit"))?;
+        let n = Bits::len_common_bits(&unit.bits, &bits);
+        match n {
+            n if n == bits.len() => Ok(true),
+            n if n == unit.bits.len() => self.has_key(unit.hash, bits.shift(n, false)),
+            _ => Ok(false),
         }
-        self.db.finish_batch()?;
-        Ok(root)
     }
 
-    /// Generate a Merkle proof for the given root and key.
-    pub fn get_merkle_proof(&mut self, root: Option<&Hash>, key: &[u8]) -> Result<Option<Proof>> {
-        let mut proof: Proof = Vec::new();
+    /// Count the entries in the tree with the given root, or `0` for `None`.
+    ///
+    /// The whole tree is walked to count the cells reaching the full length of keys,
+    /// as nodes do not keep the size of subtrees under them.
+    pub fn len(&mut self, root: Option<&Hash<N>>) -> Result<usize> {
         match root {
-            None => Ok(None),
-            Some(root) => self.gen_proof(root, Bits::new(key), &mut proof),
+            None => Ok(0),
+            Some(root) => self.count_leaves(root, 0),
         }
     }
 
-    fn gen_proof(&mut self, root: &[u8], bits: Bits, proof: &mut Proof) -> Result<Option<Proof>> {
-        let bytes = self.db.get(root)?.expect("bytes");
-        let (cell, _) = Node::cells_from_bytes(&bytes, bits.first())?;
-        let unit = cell.as_ref().expect("gen_proof(): left-unit");
-        let n = Bits::len_common_bits(&unit.bits, &bits);
-        match n {
-            n if n == bits.len() => {
-                proof.push(self.encode_proof(&bytes, bits.first())?);
-                Ok(Some(proof.to_owned()))
+    /// Count the entries in the tree with the given root. Same as `len()`.
+    pub fn count(&mut self, root: Option<&Hash<N>>) -> Result<usize> {
+        self.len(root)
+    }
+
+    /// Recursively count leaves under the node, given the length of bits leading to it.
+    fn count_leaves(&mut self, root: &[u8], depth: usize) -> Result<usize> {
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        let mut count = 0;
+        for unit in [lc, rc].iter().flatten() {
+            let depth = depth + unit.bits.len() as usize;
+            count += if depth == N * 8 {
+                1
+            } else {
+                self.count_leaves(unit.hash, depth)?
+            };
+        }
+        Ok(count)
+    }
+
+    /// Get the depth of the tree with the given root, i.e., the maximum number of nodes
+    /// walked from the root to any leaf. This bounds the number of steps of Merkle proofs.
+    ///
+    /// Thanks to path compression, the depth depends on how keys diverge rather than
+    /// on how long they share prefixes, e.g., 2 for two keys differing only in the last bit.
+    pub fn depth(&mut self, root: Option<&Hash<N>>) -> Result<usize> {
+        match root {
+            None => Ok(0),
+            Some(root) => self.depth_under(root, 0),
+        }
+    }
+
+    /// Recursively get the depth under the node, given the length of bits leading to it.
+    fn depth_under(&mut self, root: &[u8], depth: usize) -> Result<usize> {
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        let mut max = 0;
+        for unit in [lc, rc].iter().flatten() {
+            let depth = depth + unit.bits.len() as usize;
+            if depth < N * 8 {
+                max = max.max(self.depth_under(unit.hash, depth)?);
             }
-            n if n == unit.bits.len() => {
-                proof.push(self.encode_proof(&bytes, bits.first())?);
-                self.gen_proof(unit.hash, bits.shift(n, false), proof)
+        }
+        Ok(max + 1)
+    }
+
+    /// Get statistics of the nodes stored for the tree with the given root,
+    /// e.g., to see how much a version of the tree takes in the database.
+    ///
+    /// Each distinct node reachable from the root counts once, even if shared by subtrees.
+    /// `total_bytes` sums the lengths of nodes as stored, i.e., in compact form if so written.
+    pub fn storage_stats(&mut self, root: Option<&Hash<N>>) -> Result<StorageStats> {
+        let mut stats = StorageStats::default();
+        if let Some(root) = root {
+            self.collect_stats(root, 0, &mut HashSet::new(), &mut stats)?;
+        }
+        Ok(stats)
+    }
+
+    /// Recursively add up the nodes under the node not yet visited into `stats`,
+    /// given the length of bits leading to it.
+    fn collect_stats(
+        &mut self,
+        root: &[u8],
+        depth: usize,
+        visited: &mut HashSet<Vec<u8>>,
+        stats: &mut StorageStats,
+    ) -> Result<()> {
+        if !visited.insert(root.to_vec()) {
+            return Ok(());
+        }
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        stats.node_count += 1;
+        stats.total_bytes += bytes.len();
+        match (&lc, &rc) {
+            (Some(_), Some(_)) => stats.hard_nodes += 1,
+            _ => stats.soft_nodes += 1,
+        }
+        for unit in [lc, rc].iter().flatten() {
+            let depth = depth + unit.bits.len() as usize;
+            if depth < N * 8 {
+                self.collect_stats(unit.hash, depth, visited, stats)?;
             }
-            _ => Ok(None),
         }
+        Ok(())
     }
 
-    fn encode_proof(&self, bytes: &[u8], right: bool) -> Result<(bool, Vec<u8>)> {
-        match Node::from_bytes(bytes)? {
-            Node::Soft(_) => Ok((false, bytes[HASH_LEN..].to_vec())),
-            Node::Hard(_, _) => {
-                if right {
-                    Ok((
-                        true,
-                        [&bytes[..bytes.len() - HASH_LEN - 1], &[0x01]].concat(),
-                    ))
-                } else {
-                    Ok((false, bytes[HASH_LEN..].to_vec()))
-                }
+    /// Render the tree with the given root as ASCII text, e.g., to see why a root is
+    /// not the expected one. Each node is shown as `Soft` or `Hard` with its hash,
+    /// followed by its cells of path bits and child hashes, where hashes are cut to 8 hex digits.
+    /// For instance, with the long paths to leaves elided:
+    ///
+    /// ```text
+    /// Hard 1f3a9c04
+    /// +- 0b0 -> 77e1b2d8
+    /// |  Soft 77e1b2d8
+    /// |  +- 0b1011... -> 0c5d2e9a (leaf)
+    /// +- 0b1... -> a41f6b3e (leaf)
+    /// ```
+    ///
+    /// A node referring back to one of its ancestors, as in a corrupted database,
+    /// is marked as `(cycle)` rather than walked again.
+    pub fn debug_tree(&mut self, root: Option<&Hash<N>>) -> Result<String> {
+        let mut out = String::new();
+        match root {
+            None => out.push_str("(empty)\n"),
+            Some(root) => self.render_node(root, 0, "", &mut HashSet::new(), &mut out)?,
+        }
+        Ok(out)
+    }
+
+    /// Recursively render the node and its cells into `out`, given the length of bits
+    /// leading to it, the indent of its lines, and the hashes of its ancestors.
+    fn render_node(
+        &mut self,
+        root: &[u8],
+        depth: usize,
+        indent: &str,
+        ancestors: &mut HashSet<Vec<u8>>,
+        out: &mut String,
+    ) -> Result<()> {
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        let kind = if lc.is_some() && rc.is_some() {
+            "Hard"
+        } else {
+            "Soft"
+        };
+        out.push_str(&format!("{}{} {}\n", indent, kind, short_hex(root)));
+        ancestors.insert(root.to_vec());
+        let units: Vec<&Unit> = [&lc, &rc].iter().filter_map(|cell| cell.as_ref()).collect();
+        for (i, unit) in units.iter().enumerate() {
+            let line = format!("{}+- {} -> {}", indent, unit.bits, short_hex(unit.hash));
+            let depth = depth + unit.bits.len() as usize;
+            if depth >= N * 8 {
+                out.push_str(&format!("{} (leaf)\n", line));
+            } else if ancestors.contains(unit.hash) {
+                out.push_str(&format!("{} (cycle)\n", line));
+            } else {
+                out.push_str(&format!("{}\n", line));
+                let last = i == units.len() - 1;
+                let indent = format!("{}{}", indent, if last { "   " } else { "|  " });
+                self.render_node(unit.hash, depth, &indent, ancestors, out)?;
             }
         }
+        ancestors.remove(root);
+        Ok(())
     }
-}
 
-/// Verify a Merkle proof with the given root, leaf and hasher if the proof is valid or not.
-///
-/// Be aware of that it fails if not provided a suitable hasher used in the tree
-/// This generic fn must be independantly called upon request, not a member of Monotree.
-pub fn verify_proof<H: Hasher>(
-    hasher: &H,
-    root: Option<&Hash>,
-    leaf: &Hash,
-    proof: Option<&Proof>,
-) -> bool {
-    match proof {
-        None => false,
-        Some(proof) => {
-            let mut hash = leaf.to_owned();
-            proof.iter().rev().for_each(|(right, cut)| {
-                if *right {
-                    let l = cut.len();
-                    let o = [&cut[..l - 1], &hash[..], &cut[l - 1..]].concat();
-                    hash = hasher.digest(&o);
-                } else {
-                    let o = [&hash[..], &cut[..]].concat();
-                    hash = hasher.digest(&o);
+    /// List keys changed from an old root to a new one, in order of keys.
+    ///
+    /// Both trees are walked together level by level. Subtrees at the same bits of key
+    /// with the same hash are identical, so they are never descended into.
+    pub fn diff(
+        &mut self,
+        old_root: Option<&Hash<N>>,
+        new_root: Option<&Hash<N>>,
+    ) -> Result<Vec<(Hash<N>, DiffKind<N>)>> {
+        let mut old: HashMap<Vec<bool>, Hash<N>> =
+            old_root.map(|r| (vec![], *r)).into_iter().collect();
+        let mut new: HashMap<Vec<bool>, Hash<N>> =
+            new_root.map(|r| (vec![], *r)).into_iter().collect();
+        loop {
+            old.retain(|path, hash| match new.get(path) {
+                Some(h) if h == hash => {
+                    new.remove(path);
+                    false
                 }
+                _ => true,
             });
-            root.expect("verify_proof(): root") == &hash
+            let shortest = old
+                .keys()
+                .chain(new.keys())
+                .map(|path| path.len())
+                .filter(|&n| n < N * 8)
+                .min();
+            match shortest {
+                None => break,
+                Some(n) => {
+                    old = self.expand_cells(old, n)?;
+                    new = self.expand_cells(new, n)?;
+                }
+            }
+        }
+        let mut diff: Vec<_> = new
+            .iter()
+            .map(|(path, leaf)| match old.get(path) {
+                Some(_) => (path, DiffKind::Changed(*leaf)),
+                None => (path, DiffKind::Added(*leaf)),
+            })
+            .chain(
+                old.keys()
+                    .filter(|path| !new.contains_key(*path))
+                    .map(|path| (path, DiffKind::Removed)),
+            )
+            .map(|(path, kind)| (slice_to_array(&slicebit_to_bytes(path, 0)), kind))
+            .collect();
+        diff.sort_by_key(|entry| entry.0);
+        Ok(diff)
+    }
+
+    /// Replace nodes reached by `depth` bits of key with their cells, keyed by the bits leading to them.
+    fn expand_cells(
+        &mut self,
+        cells: HashMap<Vec<bool>, Hash<N>>,
+        depth: usize,
+    ) -> Result<HashMap<Vec<bool>, Hash<N>>> {
+        let mut expanded = HashMap::new();
+        for (path, hash) in cells {
+            if path.len() != depth {
+                expanded.insert(path, hash);
+                continue;
+            }
+            let bytes = self.get_node(&hash)?;
+            let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+            for unit in [lc, rc].iter().flatten() {
+                let bits = [&path[..], &unit.bits.to_bits()].concat();
+                expanded.insert(bits, slice_to_array(unit.hash));
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Iterate over all entries of key and leaf in the tree with the given root, in order of keys.
+    ///
+    /// The tree is walked depth-first, reassembling each key from the bits of cells on its path.
+    pub fn iter<'a>(
+        &'a mut self,
+        root: Option<&Hash<N>>,
+    ) -> impl Iterator<Item = Result<(Hash<N>, Hash<N>)>> + 'a {
+        self.iter_under(root, &[])
+    }
+
+    /// Get all entries of key and leaf of which keys start with the given prefix, in order of keys.
+    pub fn leaves_under(
+        &mut self,
+        root: Option<&Hash<N>>,
+        prefix: &[u8],
+    ) -> Result<Vec<(Hash<N>, Hash<N>)>> {
+        self.iter_under(root, prefix).collect()
+    }
+
+    /// Iterate over entries of key and leaf of which keys start with the given prefix,
+    /// in order of keys. Unlike `leaves_under()`, the subtree is walked lazily,
+    /// holding only the nodes pending on the way.
+    pub fn iter_under<'a>(
+        &'a mut self,
+        root: Option<&Hash<N>>,
+        prefix: &[u8],
+    ) -> impl Iterator<Item = Result<(Hash<N>, Hash<N>)>> + 'a {
+        self.iter_under_bits(root, bytes_to_bits(prefix))
+    }
+
+    /// Get all entries of key and leaf of which keys start with the first `prefix_bits` bits
+    /// of `prefix`, in order of keys. Unlike `leaves_under()`, the prefix may end in the middle
+    /// of a byte, e.g., to list the entries of a shard of `subtree_root()`.
+    /// An empty prefix gets all entries of the tree.
+    pub fn scan_prefix(
+        &mut self,
+        root: Option<&Hash<N>>,
+        prefix: &[u8],
+        prefix_bits: BitsLen,
+    ) -> Result<Vec<(Hash<N>, Hash<N>)>> {
+        if prefix_bits as usize > prefix.len() * 8 {
+            return Err(Errors::new("scan_prefix(): prefix_bits beyond prefix"));
+        }
+        let prefix = bytes_to_slicebit(prefix, &(0..prefix_bits));
+        self.iter_under_bits(root, prefix).collect()
+    }
+
+    fn iter_under_bits<'a>(
+        &'a mut self,
+        root: Option<&Hash<N>>,
+        prefix: Vec<bool>,
+    ) -> impl Iterator<Item = Result<(Hash<N>, Hash<N>)>> + 'a {
+        IterUnder {
+            tree: self,
+            prefix,
+            stack: root.map(|root| (*root, Vec::new())).into_iter().collect(),
+        }
+    }
+
+    /// Remove the given key and its corresponding leaf from the tree. Returns a new root hash.
+    pub fn remove(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        self.writes.clear();
+        self.atomic(|tree| tree.remove_entry(root, key))
+    }
+
+    /// Remove the given key and its corresponding leaf from the tree.
+    /// Returns a new root hash along with whether the key existed and was removed.
+    pub fn remove_checked(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<(Option<Hash<N>>, bool)> {
+        self.writes.clear();
+        let mut removed = false;
+        let root = self.atomic(|tree| {
+            let (root, found) = tree.remove_entry_checked(root, key)?;
+            removed = found;
+            Ok(root)
+        })?;
+        Ok((root, removed))
+    }
+
+    fn remove_entry(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        Ok(self.remove_entry_checked(root, key)?.0)
+    }
+
+    fn remove_entry_checked(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<(Option<Hash<N>>, bool)> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        let root = match root {
+            None => return Ok((None, false)),
+            Some(root) => root,
+        };
+        match self.delete_key(root, Bits::new(&key))? {
+            Remains::Unchanged => Ok((Some(*root), false)),
+            Remains::Empty => Ok((None, true)),
+            Remains::Node(hash) => Ok((Some(hash), true)),
+            Remains::Cell(bits, hash) => {
+                let path = slicebit_to_bytes(&bits, 0);
+                let range = 0..bits.len() as BitsLen;
+                let bits = Bits { path: &path, range };
+                let root = self.put_node(Node::new(Some(Unit { hash: &hash, bits }), None))?;
+                Ok((root, true))
+            }
+        }
+    }
+
+    /// Recursively remove a bytes (in forms of Bits) and its leaf from the tree.
+    ///
+    /// When a node is left with only a cell, the cell is not written as a node on its own
+    /// but merged into the parent's cell. This keeps the tree in the very same shape as
+    /// built only by insertions, so that the root depends only on the entries it has.
+    fn delete_key(&mut self, root: &[u8], bits: Bits) -> Result<Remains<N>> {
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        let unit = lc
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("delete_key(): left-unit"))?;
+        let n = Bits::len_common_bits(&unit.bits, &bits);
+        match n {
+            n if n == bits.len() => match rc {
+                Some(rc) => Ok(Remains::Cell(rc.bits.to_bits(), slice_to_array(rc.hash))),
+                None => Ok(Remains::Empty),
+            },
+            n if n == unit.bits.len() => {
+                match (self.delete_key(unit.hash, bits.shift(n, false))?, rc) {
+                    (Remains::Unchanged, _) => Ok(Remains::Unchanged),
+                    (Remains::Empty, None) => Ok(Remains::Empty),
+                    (Remains::Empty, Some(rc)) => {
+                        Ok(Remains::Cell(rc.bits.to_bits(), slice_to_array(rc.hash)))
+                    }
+                    (Remains::Node(hash), None) => Ok(Remains::Cell(unit.bits.to_bits(), hash)),
+                    (Remains::Node(ref hash), rc) => {
+                        let unit = unit.to_owned();
+                        let lc = Some(Unit { hash, ..unit });
+                        let hash = self
+                            .put_node(Node::new(lc, rc))?
+                            .ok_or_else(|| MonotreeError::corruption("delete_key(): hash"))?;
+                        Ok(Remains::Node(hash))
+                    }
+                    (Remains::Cell(suffix, hash), None) => {
+                        Ok(Remains::Cell([unit.bits.to_bits(), suffix].concat(), hash))
+                    }
+                    (Remains::Cell(suffix, ref hash), rc) => {
+                        let merged = [unit.bits.to_bits(), suffix].concat();
+                        let start = unit.bits.range.start;
+                        let path = slicebit_to_bytes(&merged, start as usize);
+                        let range = start..start + merged.len() as BitsLen;
+                        let lc = Some(Unit {
+                            hash,
+                            bits: Bits { path: &path, range },
+                        });
+                        let hash = self
+                            .put_node(Node::new(lc, rc))?
+                            .ok_or_else(|| MonotreeError::corruption("delete_key(): hash"))?;
+                        Ok(Remains::Node(hash))
+                    }
+                }
+            }
+            _ => Ok(Remains::Unchanged),
+        }
+    }
+
+    /// Get the hashes of stored nodes beginning with the given prefix, in order of hashes.
+    ///
+    /// Intended for tools resolving a root from a truncated hash, as git does for short hashes.
+    /// Every node matches, not only roots, since nodes do not know whether they are roots.
+    /// The database must support `Database::scan()`.
+    pub fn resolve_root_prefix(&mut self, prefix: &[u8]) -> Result<Vec<Hash<N>>> {
+        let mut hashes: Vec<Hash<N>> = Vec::new();
+        for key in self.db_scan(prefix)? {
+            if key.len() != N || key == ROOT_KEY || key == HISTORY_KEY || key == NAMESPACES_KEY {
+                continue;
+            }
+            match self.db_get(&key)? {
+                Some(bytes) if Node::<N>::from_bytes(&bytes).is_ok() => {
+                    hashes.push(slice_to_array(&key))
+                }
+                _ => continue,
+            }
+        }
+        hashes.sort_unstable();
+        Ok(hashes)
+    }
+
+    /// Flush the database, making the nodes written so far durable.
+    pub fn flush(&mut self) -> Result<()> {
+        self.db.flush()
+    }
+
+    /// Delete all entries of the database unreachable from the given live roots.
+    /// Returns the number of entries deleted.
+    ///
+    /// Every insertion writes new nodes while the old ones stay, so the database grows
+    /// with versions of the tree. Nodes reachable from the headroot, the headroots set by
+    /// `set_headroot_ns()` and the roots in history are kept as well as the headroots
+    /// and the history themselves, and so are the values stored under reachable leaves.
+    /// Note that anything else in the database, such as entries of other trees sharing it
+    /// without their headroots set in it, is regarded as unreachable. With a namespace,
+    /// only keys under it are subject to pruning. The database must support `Database::scan()`.
+    pub fn prune(&mut self, live_roots: &[Hash<N>]) -> Result<usize> {
+        self.writes.clear();
+        self.memo = None;
+        let mut live: HashSet<Vec<u8>> = HashSet::new();
+        live.insert(ROOT_KEY.to_vec());
+        live.insert(HISTORY_KEY.to_vec());
+        live.insert(NAMESPACES_KEY.to_vec());
+        let mut headroots: Vec<Hash<N>> = self.get_headroot()?.into_iter().collect();
+        for ns in self.namespaces()? {
+            let key = self.headroot_key_ns(&ns);
+            live.insert(key.to_vec());
+            headroots.extend(self.get_headroot_at(&key)?);
+        }
+        let history = self.roots_history()?;
+        for root in live_roots
+            .iter()
+            .chain(headroots.iter())
+            .chain(history.iter())
+        {
+            self.mark_live(root, 0, &mut live)?;
+        }
+        let keys = self.db_scan(&[])?;
+        self.in_batch(|tree| {
+            let mut count = 0;
+            for key in keys.iter().filter(|key| !live.contains(*key)) {
+                tree.db_delete(key)?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    /// Delete everything of the tree in the database: nodes, values, the headroot
+    /// and the history of roots. Note that this destroys every version of the tree,
+    /// not just the one of a root. Refer to `prune()` to keep the live ones.
+    ///
+    /// Without a namespace, the whole database is cleared by `Database::clear()`, including
+    /// entries of other trees sharing it. With a namespace, only keys under it are deleted.
+    pub fn clear_all(&mut self) -> Result<()> {
+        self.memo = None;
+        if self.ns.is_empty() {
+            return self.db.clear();
+        }
+        let keys = self.db_scan(&[])?;
+        self.in_batch(|tree| {
+            for key in keys.iter() {
+                tree.db_delete(key)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Wipe the tree in the database as `clear_all()`, then take any root cleared away as empty.
+    ///
+    /// Afterwards, `get_headroot()` returns `None` and `get()` from any previous root returns
+    /// `None` rather than failing with `MonotreeError::NodeNotFound`. Fails without clearing
+    /// if a batch is left open, e.g., by a database failed to discard it, so that its writes
+    /// pending are never dropped silently.
+    pub fn clear(&mut self) -> Result<()> {
+        if self.batch_open {
+            return Err(Errors::new("clear(): a batch is left open"));
+        }
+        self.clear_all()?;
+        self.cleared = true;
+        Ok(())
+    }
+
+    /// Collect garbage of the database, the same as `prune()`.
+    pub fn gc(&mut self, live_roots: &[Hash<N>]) -> Result<usize> {
+        self.prune(live_roots)
+    }
+
+    /// Recursively collect the hashes of nodes and leaves under the node,
+    /// given the length of bits leading to it. Subtrees already collected are skipped.
+    fn mark_live(&mut self, root: &[u8], depth: usize, live: &mut HashSet<Vec<u8>>) -> Result<()> {
+        if !live.insert(root.to_vec()) {
+            return Ok(());
+        }
+        if depth == N * 8 {
+            live.insert(value_key(root));
+            return Ok(());
+        }
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        for unit in [lc, rc].iter().flatten() {
+            self.mark_live(unit.hash, depth + unit.bits.len() as usize, live)?;
+        }
+        Ok(())
+    }
+
+    /// Get the nodes reachable from `new_root` but not from `old_root`, along with `new_root`,
+    /// so that a light client holding the nodes of `old_root` can catch up by `apply_update()`.
+    ///
+    /// The whole tree of `old_root` is walked to tell the nodes it has.
+    /// Nodes come in the order of children first, then their parent.
+    pub fn update_proof(
+        &mut self,
+        old_root: Option<&Hash<N>>,
+        new_root: Option<&Hash<N>>,
+    ) -> Result<UpdateProof<N>> {
+        let mut known: HashSet<Vec<u8>> = HashSet::new();
+        if let Some(root) = old_root {
+            self.mark_live(root, 0, &mut known)?;
+        }
+        let mut nodes: Vec<Vec<u8>> = Vec::new();
+        if let Some(root) = new_root {
+            self.collect_nodes(root, 0, &mut known, &mut nodes)?;
+        }
+        Ok(UpdateProof {
+            root: new_root.cloned(),
+            nodes,
+        })
+    }
+
+    /// Export every node reachable from the given root into a single blob,
+    /// e.g., for backups or for shipping a verifiable state to another node.
+    ///
+    /// The blob consists of `SUBTREE_MAGIC`, the length of hash as a varint, the root,
+    /// the number of nodes as a varint, and then each node as its hash, the length of
+    /// its bytes as a varint and the bytes. Nodes come in the order of children first.
+    pub fn export_subtree(&mut self, root: &Hash<N>) -> Result<Vec<u8>> {
+        let mut nodes: Vec<Vec<u8>> = Vec::new();
+        self.collect_nodes(root, 0, &mut HashSet::new(), &mut nodes)?;
+        let mut blob = SUBTREE_MAGIC.to_vec();
+        blob.extend(encode_varint(N as u64));
+        blob.extend(root);
+        blob.extend(encode_varint(nodes.len() as u64));
+        for bytes in nodes.iter() {
+            blob.extend(&digest_node_bytes(&self.hasher, bytes, self.domain));
+            blob.extend(encode_varint(bytes.len() as u64));
+            blob.extend(bytes);
+        }
+        Ok(blob)
+    }
+
+    /// Import the nodes of a blob from `export_subtree()` into the database. Returns the root.
+    ///
+    /// Every node is digested again with the hasher of this tree before anything is written,
+    /// which fails on a node of a hash other than the one stated, so that a corrupted blob
+    /// never poisons the database. So it does when any node under the root is missing.
+    pub fn import_subtree(&mut self, blob: &[u8]) -> Result<Hash<N>> {
+        let mut rest = match blob.strip_prefix(SUBTREE_MAGIC) {
+            Some(rest) => rest,
+            None => {
+                return Err(MonotreeError::corruption(
+                    "import_subtree(): not a subtree blob",
+                ))
+            }
+        };
+        let next_varint = |rest: &mut &[u8]| -> Result<usize> {
+            let (x, n) = decode_varint(rest)?;
+            *rest = &rest[n..];
+            Ok(x as usize)
+        };
+        let next_bytes = |rest: &mut &[u8], len: usize| -> Result<Vec<u8>> {
+            if rest.len() < len {
+                return Err(MonotreeError::corruption("import_subtree(): truncated"));
+            }
+            let bytes = rest[..len].to_vec();
+            *rest = &rest[len..];
+            Ok(bytes)
+        };
+        if next_varint(&mut rest)? != N {
+            return Err(MonotreeError::corruption(
+                "import_subtree(): length of hash mismatched",
+            ));
+        }
+        let root: Hash<N> = slice_to_array(&next_bytes(&mut rest, N)?);
+        let mut nodes: HashMap<Hash<N>, Vec<u8>> = HashMap::new();
+        for i in 0..next_varint(&mut rest)? {
+            let hash: Hash<N> = slice_to_array(&next_bytes(&mut rest, N)?);
+            let len = next_varint(&mut rest)?;
+            let bytes = next_bytes(&mut rest, len)?;
+            Node::<N>::from_bytes(&bytes)?;
+            if digest_node_bytes(&self.hasher, &bytes, self.domain) != hash {
+                return Err(MonotreeError::Corruption(format!(
+                    "import_subtree(): hash mismatched at node {}",
+                    i
+                )));
+            }
+            nodes.insert(hash, bytes);
+        }
+        if !rest.is_empty() {
+            return Err(MonotreeError::corruption(
+                "import_subtree(): trailing bytes",
+            ));
+        }
+
+        // every node under the root must be in the blob
+        let mut stack: Vec<(Hash<N>, usize)> = vec![(root, 0)];
+        while let Some((hash, depth)) = stack.pop() {
+            if depth == N * 8 {
+                continue;
+            }
+            let bytes = nodes
+                .get(&hash)
+                .ok_or_else(|| MonotreeError::corruption("import_subtree(): missing node"))?;
+            let (lc, rc) = Node::<N>::cells_from_bytes(bytes, false)?;
+            for unit in [lc, rc].iter().flatten() {
+                stack.push((slice_to_array(unit.hash), depth + unit.bits.len() as usize));
+            }
+        }
+
+        self.writes.clear();
+        self.in_batch(|tree| {
+            for (hash, bytes) in nodes {
+                tree.db_put(&hash, bytes)?;
+                tree.writes.insert(hash);
+            }
+            Ok(root)
+        })
+    }
+
+    /// Recursively collect the bytes of nodes under the node not known yet,
+    /// given the length of bits leading to it.
+    fn collect_nodes(
+        &mut self,
+        root: &[u8],
+        depth: usize,
+        known: &mut HashSet<Vec<u8>>,
+        nodes: &mut Vec<Vec<u8>>,
+    ) -> Result<()> {
+        if depth == N * 8 || !known.insert(root.to_vec()) {
+            return Ok(());
+        }
+        let bytes = self.get_node(root)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        for unit in [lc, rc].iter().flatten() {
+            self.collect_nodes(unit.hash, depth + unit.bits.len() as usize, known, nodes)?;
+        }
+        nodes.push(bytes);
+        Ok(())
+    }
+
+    /// Dump every entry of the database under the namespace of this tree into the writer,
+    /// i.e., nodes along with values and the history of roots, e.g., for backups or
+    /// for moving a tree to another machine. Fails on databases unable to `scan()`.
+    ///
+    /// The dump consists of `DUMP_MAGIC`, the length of hash as a varint, the headroot
+    /// as a byte of whether it exists followed by the root, the number of entries as a varint,
+    /// and then each entry as its key and value, both prefixed with their length as a varint.
+    pub fn dump<W: Write>(&mut self, mut out: W) -> Result<()> {
+        let mut keys: Vec<Vec<u8>> = self
+            .db_scan(&[])?
+            .into_iter()
+            .filter(|key| key != ROOT_KEY)
+            .collect();
+        keys.sort();
+        let mut bytes = DUMP_MAGIC.to_vec();
+        bytes.extend(encode_varint(N as u64));
+        match self.get_headroot()? {
+            Some(root) => {
+                bytes.push(0x01);
+                bytes.extend(&root);
+            }
+            None => bytes.push(0x00),
+        }
+        bytes.extend(encode_varint(keys.len() as u64));
+        out.write_all(&bytes)?;
+        for key in keys.iter() {
+            let value = self
+                .db_get(key)?
+                .ok_or_else(|| Errors::new("dump(): entry removed while dumping"))?;
+            out.write_all(&encode_varint(key.len() as u64))?;
+            out.write_all(key)?;
+            out.write_all(&encode_varint(value.len() as u64))?;
+            out.write_all(&value)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Load a dump from `dump()` into the database, in batch mode, along with its headroot.
+    ///
+    /// The whole dump is parsed before anything is written, so that a truncated
+    /// or corrupted dump fails leaving the database as it was.
+    pub fn load<R: Read>(&mut self, mut input: R) -> Result<()> {
+        let mut bytes: Vec<u8> = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        let mut rest = match bytes.strip_prefix(DUMP_MAGIC) {
+            Some(rest) => rest,
+            None => return Err(MonotreeError::corruption("load(): not a dump")),
+        };
+        let next_varint = |rest: &mut &[u8]| -> Result<usize> {
+            let (x, n) = decode_varint(rest)?;
+            *rest = &rest[n..];
+            Ok(x as usize)
+        };
+        let next_bytes = |rest: &mut &[u8], len: usize| -> Result<Vec<u8>> {
+            if rest.len() < len {
+                return Err(MonotreeError::corruption("load(): truncated"));
+            }
+            let bytes = rest[..len].to_vec();
+            *rest = &rest[len..];
+            Ok(bytes)
+        };
+        if next_varint(&mut rest)? != N {
+            return Err(MonotreeError::corruption(
+                "load(): length of hash mismatched",
+            ));
+        }
+        let headroot: Option<Hash<N>> = match next_bytes(&mut rest, 1)?[0] {
+            0x00 => None,
+            0x01 => Some(slice_to_array(&next_bytes(&mut rest, N)?)),
+            _ => return Err(MonotreeError::corruption("load(): invalid headroot")),
+        };
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for _ in 0..next_varint(&mut rest)? {
+            let len = next_varint(&mut rest)?;
+            let key = next_bytes(&mut rest, len)?;
+            let len = next_varint(&mut rest)?;
+            entries.push((key, next_bytes(&mut rest, len)?));
+        }
+        if !rest.is_empty() {
+            return Err(MonotreeError::corruption("load(): trailing bytes"));
+        }
+
+        self.in_batch(|tree| {
+            for (key, value) in entries {
+                tree.db_put(&key, value)?;
+            }
+            tree.set_headroot(headroot.as_ref())
+        })
+    }
+
+    /// This method is intended to use the `insert()` method in batch mode.
+    ///
+    /// Among entries of the same key, the last one in the order given wins.
+    pub fn inserts<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        leaves: &[Hash<N>],
+    ) -> Result<Option<Hash<N>>> {
+        let indices = get_sorted_indices(&as_slices(keys), false);
+        self.inserts_in_order(root, keys, leaves, indices)
+    }
+
+    /// Insert entries of keys sorted already in batch mode, skipping the sort of `inserts()`,
+    /// e.g., for pipelines keeping their keys sorted. Results in the same root as `inserts()`.
+    pub fn inserts_sorted<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        sorted_keys: &[K],
+        leaves: &[Hash<N>],
+    ) -> Result<Option<Hash<N>>> {
+        debug_assert!(is_sorted(sorted_keys), "inserts_sorted(): keys not sorted");
+        self.inserts_in_order(root, sorted_keys, leaves, 0..sorted_keys.len())
+    }
+
+    /// Insert entries in batch mode, in the order of the given indices of them.
+    fn inserts_in_order<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        leaves: &[Hash<N>],
+        indices: impl IntoIterator<Item = usize>,
+    ) -> Result<Option<Hash<N>>> {
+        self.writes.clear();
+        self.in_batch(|tree| {
+            let mut root = root.cloned();
+            let mut indices = indices.into_iter().peekable();
+            while let Some(i) = indices.next() {
+                // entries of the same key are adjacent in the order given, so only the last is put
+                if let Some(&j) = indices.peek() {
+                    if keys[j].as_ref() == keys[i].as_ref() {
+                        continue;
+                    }
+                }
+                root = tree.insert_entry(root.as_ref(), keys[i].as_ref(), &leaves[i])?;
+            }
+            if tree.collision_check {
+                tree.check_collision(keys)?;
+            }
+            tree.update_head(root.as_ref())?;
+            Ok(root)
+        })
+    }
+
+    /// Fail if any of the given keys equals a node hash written by the last operation.
+    /// Called in batch mode, so that none of the writes takes effect on failure.
+    fn check_collision<K: AsRef<[u8]>>(&self, keys: &[K]) -> Result<()> {
+        match keys.iter().find(|key| self.writes.contains(key.as_ref())) {
+            Some(key) => Err(Errors::new(&format!(
+                "node hash collides with key: {}",
+                hex!(key.as_ref())
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Insert entries in batch mode like `inserts()`, building subtrees in parallel.
+    ///
+    /// Keys are sorted and split by their leading bits into disjoint subtrees,
+    /// each of which is built on its own thread into a buffer of nodes.
+    /// The subtrees are then stitched into the root, and all the nodes are written at once.
+    /// Results in exactly the same root as `inserts()` for the same input.
+    /// Only building from an empty tree runs in parallel. Given a root, it falls back to `inserts()`.
+    #[cfg(feature = "rayon")]
+    pub fn inserts_parallel<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        leaves: &[Hash<N>],
+    ) -> Result<Option<Hash<N>>>
+    where
+        H: Sync,
+    {
+        if root.is_some() {
+            return self.inserts(root, keys, leaves);
+        }
+        let mut entries: Vec<(Hash<N>, Hash<N>)> = Vec::with_capacity(keys.len());
+        for (key, leaf) in keys.iter().zip(leaves.iter()) {
+            entries.push((try_slice_to_array(key.as_ref())?, *leaf));
+        }
+        // the last one wins among entries of the same key, as in sequential insertions
+        entries.reverse();
+        entries.sort_by_key(|entry| entry.0);
+        entries.dedup_by(|a, b| a.0 == b.0);
+        self.writes.clear();
+        if entries.is_empty() {
+            return self.in_batch(|tree| tree.update_head(None).map(|_| None));
+        }
+        let builder = Builder {
+            hasher: &self.hasher,
+            compact: self.compact,
+            domain: self.domain,
+        };
+        let (hash, nodes) = builder.build(&entries, 0, 0)?;
+        self.in_batch(|tree| {
+            for (hash, bytes) in nodes {
+                tree.db_put(&hash, bytes)?;
+                tree.writes.insert(hash);
+            }
+            if tree.collision_check {
+                tree.check_collision(keys)?;
+            }
+            tree.update_head(Some(&hash))?;
+            Ok(Some(hash))
+        })
+    }
+
+    /// Insert entries in batch mode, building the subtrees on each side of the first bit
+    /// of keys on their own threads, then merging them into the root.
+    /// An alias of `inserts_parallel()`, which splits them further by the bits next to it.
+    #[cfg(feature = "rayon")]
+    pub fn par_inserts<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        leaves: &[Hash<N>],
+    ) -> Result<Option<Hash<N>>>
+    where
+        H: Sync,
+    {
+        self.inserts_parallel(root, keys, leaves)
+    }
+
+    /// Get a map of the keys found to their leaves, looking up each distinct key only once.
+    ///
+    /// Keys are sorted and deduplicated before walking down the tree by `gets()`,
+    /// so that neighboring keys sharing nodes are looked up in turn, as `inserts()` does.
+    pub fn get_map<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+    ) -> Result<HashMap<Hash<N>, Hash<N>>> {
+        let mut keys: Vec<Hash<N>> = keys
+            .iter()
+            .map(|key| try_slice_to_array(key.as_ref()))
+            .collect::<Result<_>>()?;
+        keys.sort_unstable();
+        keys.dedup();
+        let leaves = self.gets(root, &keys)?;
+        Ok(keys
+            .into_iter()
+            .zip(leaves)
+            .filter_map(|(key, leaf)| Some((key, leaf?)))
+            .collect())
+    }
+
+    /// This method is intended to use the `get()` method in batch mode.
+    ///
+    /// All keys walk down the tree together level by level, so that the nodes needed
+    /// at each level are read at once by `Database::multi_get()`, each node only once.
+    pub fn gets<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+    ) -> Result<Vec<Option<Hash<N>>>> {
+        let mut leaves: Vec<Option<Hash<N>>> = vec![None; keys.len()];
+        let root = match root {
+            None => return Ok(leaves),
+            Some(root) => root,
+        };
+        let keys: Vec<Hash<N>> = keys
+            .iter()
+            .map(|key| try_slice_to_array(key.as_ref()))
+            .collect::<Result<_>>()?;
+        let walks: Vec<(usize, Hash<N>, Bits)> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| (i, *root, Bits::new(key)))
+            .collect();
+        self.find_leaves(walks, &mut leaves)?;
+        Ok(leaves)
+    }
+
+    /// Get leaf hashes of the given key across the given roots, e.g., to audit
+    /// how the leaf of a key evolved over versions of the tree.
+    ///
+    /// Like `gets()`, the walks from all roots go down together level by level,
+    /// so that a node shared by several versions is read only once.
+    pub fn gets_across_roots(
+        &mut self,
+        roots: &[Option<Hash<N>>],
+        key: &[u8],
+    ) -> Result<Vec<Option<Hash<N>>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        let mut leaves: Vec<Option<Hash<N>>> = vec![None; roots.len()];
+        let walks: Vec<(usize, Hash<N>, Bits)> = roots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, root)| Some((i, (*root)?, Bits::new(&key))))
+            .collect();
+        self.find_leaves(walks, &mut leaves)?;
+        Ok(leaves)
+    }
+
+    /// Walk down the tree level by level for each `(index, node hash, remaining bits)`,
+    /// filling `leaves` at the index with the leaf found if any.
+    fn find_leaves(
+        &mut self,
+        mut walks: Vec<(usize, Hash<N>, Bits)>,
+        leaves: &mut [Option<Hash<N>>],
+    ) -> Result<()> {
+        while !walks.is_empty() {
+            let mut hashes: Vec<Hash<N>> = walks.iter().map(|(_, hash, _)| *hash).collect();
+            hashes.sort_unstable();
+            hashes.dedup();
+            let ns_keys: Vec<Cow<[u8]>> = hashes.iter().map(|hash| self.ns_key(hash)).collect();
+            let ns_keys: Vec<&[u8]> = ns_keys.iter().map(|key| key.as_ref()).collect();
+            let nodes = self.db.multi_get(&ns_keys)?;
+            let mut next = Vec::new();
+            for (i, hash, bits) in walks.into_iter() {
+                let bytes = match hashes.binary_search(&hash).map(|j| &nodes[j]) {
+                    Ok(Some(bytes)) => bytes,
+                    _ => return Err(MonotreeError::NodeNotFound(hash.to_vec())),
+                };
+                self.check_node(&hash, bytes)?;
+                let (cell, _) = Node::<N>::cells_from_bytes(bytes, bits.first())?;
+                let unit = cell
+                    .as_ref()
+                    .ok_or_else(|| MonotreeError::corruption("find_leaves(): left-unit"))?;
+                let n = Bits::len_common_bits(&unit.bits, &bits);
+                match n {
+                    n if n == bits.len() => leaves[i] = Some(slice_to_array(unit.hash)),
+                    n if n == unit.bits.len() => {
+                        next.push((i, slice_to_array(unit.hash), bits.shift(n, false)))
+                    }
+                    _ => continue,
+                }
+            }
+            walks = next;
+        }
+        Ok(())
+    }
+
+    /// This method is intended to use the `remove()` method in batch mode.
+    pub fn removes<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+    ) -> Result<Option<Hash<N>>> {
+        let indices = get_sorted_indices(&as_slices(keys), false);
+        self.removes_in_order(root, keys, indices)
+    }
+
+    /// Remove keys sorted already in batch mode, skipping the sort of `removes()`.
+    pub fn removes_sorted<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        sorted_keys: &[K],
+    ) -> Result<Option<Hash<N>>> {
+        debug_assert!(is_sorted(sorted_keys), "removes_sorted(): keys not sorted");
+        self.removes_in_order(root, sorted_keys, 0..sorted_keys.len())
+    }
+
+    /// Remove keys in batch mode, in the order of the given indices of them.
+    fn removes_in_order<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        indices: impl IntoIterator<Item = usize>,
+    ) -> Result<Option<Hash<N>>> {
+        self.writes.clear();
+        self.in_batch(|tree| {
+            let mut root = root.cloned();
+            for i in indices {
+                root = tree.remove_entry(root.as_ref(), keys[i].as_ref())?;
+            }
+            tree.update_head(root.as_ref())?;
+            Ok(root)
+        })
+    }
+
+    /// Apply mixed operations of insertion and removal in batch mode. Returns a new root hash.
+    ///
+    /// The operations are applied in order of their keys,
+    /// while the ones on the same key are applied in the order given.
+    /// Thus, the resulting root does not depend on the order of operations on distinct keys.
+    pub fn apply_batch(
+        &mut self,
+        root: Option<&Hash<N>>,
+        ops: &[Op<N>],
+    ) -> Result<Option<Hash<N>>> {
+        let mut indices: Vec<usize> = (0..ops.len()).collect();
+        indices.sort_by(|&a, &b| ops[a].key().cmp(ops[b].key()));
+        self.writes.clear();
+        self.in_batch(|tree| {
+            let mut root = root.cloned();
+            for i in indices.iter() {
+                root = match &ops[*i] {
+                    Op::Insert(key, leaf) => tree.insert_entry(root.as_ref(), key, leaf)?,
+                    Op::Remove(key) => tree.remove_entry(root.as_ref(), key)?,
+                };
+            }
+            tree.update_head(root.as_ref())?;
+            Ok(root)
+        })
+    }
+
+    /// Apply mixed operations of insertion and removal atomically. Same as `apply_batch()`.
+    pub fn apply(&mut self, root: Option<&Hash<N>>, ops: &[Op<N>]) -> Result<Option<Hash<N>>> {
+        self.apply_batch(root, ops)
+    }
+
+    /// Insert entries in batch mode, then generate Merkle proofs for all of them
+    /// against the resulting root. The proofs are aligned to the order of the given keys.
+    ///
+    /// Proofs are cut from a multiproof of the keys, so that the nodes shared by their paths
+    /// are read only once. Refer to `get_merkle_multiproof()`.
+    pub fn inserts_with_proofs<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        leaves: &[Hash<N>],
+    ) -> Result<(Option<Hash<N>>, Vec<Proof>)> {
+        let root = self.inserts(root, keys, leaves)?;
+        let multiproof = self.get_merkle_multiproof(root.as_ref(), keys)?;
+        let mut proofs: Vec<Proof> = Vec::with_capacity(keys.len());
+        for (key, path) in keys.iter().zip(multiproof.paths.iter()) {
+            let key: Hash<N> = try_slice_to_array(key.as_ref())?;
+            let mut bits = Bits::new(&key);
+            let mut proof: Proof = Vec::with_capacity(path.len());
+            for &i in path.iter() {
+                let bytes = &multiproof.nodes[i];
+                let (cell, _) = Node::<N>::cells_from_bytes(bytes, bits.first())?;
+                let unit = cell
+                    .as_ref()
+                    .ok_or_else(|| MonotreeError::corruption("inserts_with_proofs(): left-unit"))?;
+                proof.push(self.encode_proof(bytes, bits.first())?);
+                bits = bits.shift(unit.bits.len(), false);
+            }
+            proofs.push(proof);
+        }
+        Ok((root, proofs))
+    }
+
+    /// Generate a Merkle proof for the given root and key.
+    pub fn get_merkle_proof(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<Option<Proof>> {
+        Ok(self.get_with_proof(root, key)?.map(|(_, proof)| proof))
+    }
+
+    /// Get a Merkle proof of the given key carrying the key and its leaf,
+    /// to be verified on its own by `verify_keyed_proof()`.
+    pub fn get_keyed_proof(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<Option<KeyedProof<N>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        Ok(self
+            .get_with_proof(root, &key)?
+            .map(|(leaf, proof)| KeyedProof { key, leaf, proof }))
+    }
+
+    /// Get the leaf of the given key along with its Merkle proof, in a single traversal.
+    pub fn get_with_proof(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<Option<(Hash<N>, Proof)>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        let mut proof: Proof = Vec::new();
+        match root {
+            None => Ok(None),
+            Some(root) => Ok(self
+                .gen_proof(root, Bits::new(&key), &mut proof)?
+                .map(|leaf| (leaf, proof))),
+        }
+    }
+
+    /// Get a Merkle proof of the given key in compact form, omitting the bits of path
+    /// implied by the key. Refer to `CompactProof` and `verify_compact_proof()`.
+    pub fn get_compact_proof(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<Option<CompactProof<N>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        let mut steps: Vec<CompactStep<N>> = Vec::new();
+        let mut hash = match root {
+            None => return Ok(None),
+            Some(root) => *root,
+        };
+        let mut bits = Bits::new(&key);
+        loop {
+            let bytes = self.get_node(&hash)?;
+            let (cell, other) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+            let unit = cell
+                .as_ref()
+                .ok_or_else(|| MonotreeError::corruption("get_compact_proof(): left-unit"))?;
+            let n = Bits::len_common_bits(&unit.bits, &bits);
+            if n != unit.bits.len() {
+                return Ok(None);
+            }
+            steps.push(CompactStep {
+                len: n,
+                sibling: other
+                    .as_ref()
+                    .map(|unit| (slice_to_array(unit.hash), unit.bits.to_bits())),
+                compact: matches!(bytes.last(), Some(0x02) | Some(0x03)),
+            });
+            if n == bits.len() {
+                return Ok(Some(CompactProof { steps }));
+            }
+            hash = slice_to_array(unit.hash);
+            bits = bits.shift(n, false);
+        }
+    }
+
+    /// This method is intended to use the `get_merkle_proof()` method in batch mode.
+    pub fn get_merkle_proofs<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+    ) -> Result<Vec<Option<Proof>>> {
+        let mut proofs: Vec<Option<Proof>> = Vec::new();
+        for key in keys.iter() {
+            proofs.push(self.get_merkle_proof(root, key.as_ref())?);
+        }
+        Ok(proofs)
+    }
+
+    /// Generate a single proof for several keys against the given root.
+    ///
+    /// Nodes shared by the paths of the keys are included only once.
+    /// Fails if any of the keys does not exist in the tree.
+    pub fn get_merkle_multiproof<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+    ) -> Result<MultiProof> {
+        let mut multiproof = MultiProof::default();
+        let mut indices: HashMap<Hash<N>, usize> = HashMap::new();
+        for key in keys.iter() {
+            let key: Hash<N> = try_slice_to_array(key.as_ref())?;
+            let mut bits = Bits::new(&key);
+            let mut hash = *root.ok_or_else(|| Errors::new("get_merkle_multiproof(): root"))?;
+            let mut path: Vec<usize> = Vec::new();
+            loop {
+                let bytes = self.get_node(&hash)?;
+                let (cell, _) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+                let unit = cell.as_ref().ok_or_else(|| {
+                    MonotreeError::corruption("get_merkle_multiproof(): left-unit")
+                })?;
+                let n = Bits::len_common_bits(&unit.bits, &bits);
+                let nodes = &mut multiproof.nodes;
+                path.push(*indices.entry(hash).or_insert_with(|| {
+                    nodes.push(bytes.to_vec());
+                    nodes.len() - 1
+                }));
+                match n {
+                    n if n == bits.len() => break,
+                    n if n == unit.bits.len() => {
+                        hash = slice_to_array(unit.hash);
+                        bits = bits.shift(n, false);
+                    }
+                    _ => return Err(Errors::new("get_merkle_multiproof(): key not found")),
+                }
+            }
+            multiproof.paths.push(path);
+        }
+        Ok(multiproof)
+    }
+
+    /// Export a bundle of entries of the given keys with a multiproof of them,
+    /// to be verified on its own by `verify_proof_bundle()`.
+    ///
+    /// The bundle is self-describing: it embeds the root and the length of hash, as well as
+    /// the digest of empty bytes as a fingerprint of the hasher. Refer to `bundle_bytes()`.
+    /// Fails if any of the keys does not exist, or if domain separation is on.
+    pub fn export_proof_bundle<K: AsRef<[u8]>>(
+        &mut self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        w: &mut impl Write,
+    ) -> Result<()> {
+        if self.domain {
+            return Err(Errors::new(
+                "export_proof_bundle(): domain separation unsupported",
+            ));
+        }
+        let root = root.ok_or_else(|| Errors::new("export_proof_bundle(): root"))?;
+        let multiproof = self.get_merkle_multiproof(Some(root), keys)?;
+        let mut entries: Vec<(Hash<N>, Hash<N>)> = Vec::with_capacity(keys.len());
+        for key in keys.iter() {
+            let leaf = self
+                .get(Some(root), key.as_ref())?
+                .ok_or_else(|| Errors::new("export_proof_bundle(): leaf"))?;
+            entries.push((try_slice_to_array(key.as_ref())?, leaf));
+        }
+        let fingerprint = self.hasher.digest(&[]);
+        w.write_all(&bundle_bytes(&fingerprint, root, &entries, &multiproof))?;
+        Ok(())
+    }
+
+    /// Generate a proof that the given key does not exist in the tree with the given root.
+    ///
+    /// Fails if the key exists, as its non-inclusion is unprovable.
+    pub fn get_non_inclusion_proof(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+    ) -> Result<NonInclusionProof> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        let mut proof = NonInclusionProof::default();
+        if let Some(root) = root {
+            self.gen_non_inclusion_proof(root, Bits::new(&key), &mut proof)?;
+        }
+        Ok(proof)
+    }
+
+    fn gen_non_inclusion_proof(
+        &mut self,
+        root: &[u8],
+        bits: Bits,
+        proof: &mut NonInclusionProof,
+    ) -> Result<()> {
+        let bytes = self.get_node(root)?;
+        let (cell, _) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        let unit = cell
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("gen_non_inclusion_proof(): left-unit"))?;
+        let n = Bits::len_common_bits(&unit.bits, &bits);
+        proof.nodes.push(bytes.to_vec());
+        match n {
+            n if n == bits.len() => Err(Errors::new("get_non_inclusion_proof(): key exists")),
+            n if n == unit.bits.len() => {
+                self.gen_non_inclusion_proof(unit.hash, bits.shift(n, false), proof)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Collect the Merkle proof of the given key into `proof`. Returns the leaf if found.
+    fn gen_proof(&mut self, root: &[u8], bits: Bits, proof: &mut Proof) -> Result<Option<Hash<N>>> {
+        let bytes = self.get_node(root)?;
+        let (cell, _) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+        let unit = cell
+            .as_ref()
+            .ok_or_else(|| MonotreeError::corruption("gen_proof(): left-unit"))?;
+        let n = Bits::len_common_bits(&unit.bits, &bits);
+        match n {
+            n if n == bits.len() => {
+                proof.push(self.encode_proof(&bytes, bits.first())?);
+                Ok(Some(slice_to_array(unit.hash)))
+            }
+            n if n == unit.bits.len() => {
+                proof.push(self.encode_proof(&bytes, bits.first())?);
+                self.gen_proof(unit.hash, bits.shift(n, false), proof)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn encode_proof(&self, bytes: &[u8], right: bool) -> Result<(bool, Vec<u8>)> {
+        match Node::<N>::from_bytes(bytes)? {
+            Node::Soft(_) => Ok((false, bytes[N..].to_vec())),
+            Node::Hard(_, _) => {
+                if right {
+                    let l = bytes.len();
+                    Ok((true, [&bytes[..l - N - 1], &bytes[l - 1..]].concat()))
+                } else {
+                    Ok((false, bytes[N..].to_vec()))
+                }
+            }
+        }
+    }
+}
+
+/// Serialize the node and digest it, with `NODE_PREFIX` if domain separation is on.
+fn digest_node<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    node: &Node<N>,
+    compact: bool,
+    domain: bool,
+) -> Result<(Hash<N>, Vec<u8>)> {
+    let bytes = if compact {
+        node.to_compact_bytes()?
+    } else {
+        node.to_bytes()?
+    };
+    Ok((digest_node_bytes(hasher, &bytes, domain), bytes))
+}
+
+/// Digest the serialized bytes of a node, with `NODE_PREFIX` if domain separation is on.
+fn digest_node_bytes<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    bytes: &[u8],
+    domain: bool,
+) -> Hash<N> {
+    if domain {
+        hasher.digest(&[&[NODE_PREFIX], bytes].concat())
+    } else {
+        hasher.digest(bytes)
+    }
+}
+
+/// Get the first 8 hex digits of the given hash, for display only.
+fn short_hex(hash: &[u8]) -> String {
+    hex!(&hash[..hash.len().min(4)])
+}
+
+/// Get the database key of the raw value digested into the given leaf.
+fn value_key(leaf: &[u8]) -> Vec<u8> {
+    [VALUE_PREFIX, leaf].concat()
+}
+
+/// Nodes built apart from the database, as pairs of hash and serialized bytes.
+#[cfg(feature = "rayon")]
+type Nodes<const N: usize> = Vec<(Hash<N>, Vec<u8>)>;
+
+/// A builder of subtrees from sorted entries, apart from the database. Refer to
+/// `Monotree::inserts_parallel()`.
+#[cfg(feature = "rayon")]
+struct Builder<'a, H, const N: usize> {
+    hasher: &'a H,
+    compact: bool,
+    domain: bool,
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, H: Hasher<N> + Sync, const N: usize> Builder<'a, H, N> {
+    /// Splits of the subtrees up to this level are built on their own threads.
+    const PARALLEL_LEVEL: usize = 8;
+
+    /// Build the node of the given entries, which are sorted by key without duplicates
+    /// and share the first `depth` bits of keys. Returns the hash of the node
+    /// along with all the nodes built, i.e., the node itself and the ones under it.
+    ///
+    /// The node has a cell for each side of the bit next to `depth` that any key lies on.
+    /// A cell of a single entry goes straight to its leaf, otherwise it covers
+    /// the longest common prefix of the entries and links to the node where they diverge.
+    /// This is the very shape the tree takes when built by insertions.
+    fn build(
+        &self,
+        entries: &[(Hash<N>, Hash<N>)],
+        depth: usize,
+        level: usize,
+    ) -> Result<(Hash<N>, Nodes<N>)> {
+        let i = entries.partition_point(|(key, _)| !bit(&key[depth / 8..], depth % 8));
+        let (left, right) = entries.split_at(i);
+        let (lc, rc) = if level < Self::PARALLEL_LEVEL {
+            rayon::join(
+                || self.build_cell(left, level),
+                || self.build_cell(right, level),
+            )
+        } else {
+            (self.build_cell(left, level), self.build_cell(right, level))
+        };
+        let ((lc, mut nodes), (rc, rnodes)) = (lc?, rc?);
+        nodes.extend(rnodes);
+        let node = Node::new(
+            Self::to_cell(left, &lc, depth),
+            Self::to_cell(right, &rc, depth),
+        );
+        let (hash, bytes) = digest_node(self.hasher, &node, self.compact, self.domain)?;
+        nodes.push((hash, bytes));
+        Ok((hash, nodes))
+    }
+
+    /// Build the cell of the given entries lying on the same side, if any.
+    /// Returns the hash the cell links to and the end of its bits, along with the nodes built.
+    fn build_cell(
+        &self,
+        entries: &[(Hash<N>, Hash<N>)],
+        level: usize,
+    ) -> Result<(Option<(Hash<N>, usize)>, Nodes<N>)> {
+        match entries {
+            [] => Ok((None, Vec::new())),
+            [(_, leaf)] => Ok((Some((*leaf, N * 8)), Vec::new())),
+            [(first, _), .., (last, _)] => {
+                let end = Bits::len_common_bits(&Bits::new(first), &Bits::new(last)) as usize;
+                let (hash, nodes) = self.build(entries, end, level + 1)?;
+                Ok((Some((hash, end)), nodes))
+            }
+        }
+    }
+
+    /// Get the cell built by `build_cell()`, of which bits are taken from the key of any entry.
+    fn to_cell<'b>(
+        entries: &'b [(Hash<N>, Hash<N>)],
+        cell: &'b Option<(Hash<N>, usize)>,
+        depth: usize,
+    ) -> Cell<'b> {
+        cell.as_ref().map(|(hash, end)| Unit {
+            hash,
+            bits: Bits::new(&entries[0].0)
+                .shift(depth as BitsLen, false)
+                .shift((end - depth) as BitsLen, true),
+        })
+    }
+}
+
+/// A `monotree` on a database of asynchronous I/O, e.g., for services running on tokio.
+///
+/// Nodes on the path of a key are read asynchronously into a staging `MemoryDB` first,
+/// on which the very same algorithm as `Monotree` runs. Then only the nodes it wrote
+/// are put to the database in batch mode, so roots are the same as those of `Monotree`.
+#[cfg(feature = "async")]
+pub struct AsyncMonotree<D, H = DefaultHasher, const N: usize = HASH_LEN> {
+    db: D,
+    stage: Monotree<MemoryDB, H, N>,
+}
+
+#[cfg(feature = "async")]
+impl<D, H, const N: usize> AsyncMonotree<D, H, N>
+where
+    D: AsyncDatabase,
+    H: Hasher<N>,
+{
+    /// Construct an `AsyncMonotree` on top of the given database instance.
+    pub fn with_db(db: D) -> Self {
+        AsyncMonotree {
+            db,
+            stage: Monotree::with_db(MemoryDB::new("")),
+        }
+    }
+
+    /// Use the given hasher instance rather than the one from `Hasher::new()`.
+    pub fn with_hasher(mut self, hasher: H) -> Self {
+        self.stage = self.stage.with_hasher(hasher);
+        self
+    }
+
+    /// Insert key-leaf entry into the `monotree`. Returns a new root hash.
+    pub async fn insert(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<Option<Hash<N>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        self.walk(root, &key, true).await?;
+        let root = self.stage.insert(root, &key, leaf)?;
+        self.commit().await?;
+        Ok(root)
+    }
+
+    /// Get a leaf hash for the given root and key.
+    pub async fn get(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        self.walk(root, &key, false).await
+    }
+
+    /// Remove the given key and its corresponding leaf from the tree. Returns a new root hash.
+    pub async fn remove(&mut self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        let key: Hash<N> = try_slice_to_array(key)?;
+        self.walk(root, &key, true).await?;
+        let root = self.stage.remove(root, &key)?;
+        self.commit().await?;
+        Ok(root)
+    }
+
+    /// Walk down the nodes on the path of the key, returning the leaf if the key exists.
+    /// The nodes visited are kept in the staging database if `stage` is on.
+    async fn walk(
+        &mut self,
+        root: Option<&Hash<N>>,
+        key: &Hash<N>,
+        stage: bool,
+    ) -> Result<Option<Hash<N>>> {
+        self.stage.db = MemoryDB::new("");
+        let mut hash = match root {
+            None => return Ok(None),
+            Some(root) => *root,
+        };
+        let mut bits = Bits::new(key);
+        loop {
+            let bytes = match self.db.get(&hash).await? {
+                Some(bytes) => bytes,
+                None => return Err(MonotreeError::NodeNotFound(hash.to_vec())),
+            };
+            let (leaf, next) = {
+                let (cell, _) = Node::<N>::cells_from_bytes(&bytes, bits.first())?;
+                let unit = cell
+                    .as_ref()
+                    .ok_or_else(|| MonotreeError::corruption("walk(): left-unit"))?;
+                match Bits::len_common_bits(&unit.bits, &bits) {
+                    n if n == bits.len() => (Some(slice_to_array(unit.hash)), None),
+                    n if n == unit.bits.len() => (None, Some((slice_to_array(unit.hash), n))),
+                    _ => (None, None),
+                }
+            };
+            if stage {
+                self.stage.db_put(&hash, bytes)?;
+            }
+            match next {
+                Some((child, n)) => {
+                    hash = child;
+                    bits = bits.shift(n, false);
+                }
+                None => return Ok(leaf),
+            }
+        }
+    }
+
+    /// Put the nodes written to the staging database by the last operation to the database.
+    async fn commit(&mut self) -> Result<()> {
+        let writes: Vec<Hash<N>> = self.stage.writes.iter().cloned().collect();
+        self.db.init_batch().await?;
+        match self.put_writes(&writes).await {
+            Ok(()) => self.db.finish_batch().await,
+            Err(err) => {
+                self.db.discard_batch().await?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Put the nodes of the given hashes from the staging database to the database.
+    async fn put_writes(&mut self, writes: &[Hash<N>]) -> Result<()> {
+        for hash in writes.iter() {
+            let bytes = self.stage.get_node(hash)?;
+            self.db.put(hash, bytes).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A `monotree` shared among threads, e.g., by a service serving reads from many threads.
+///
+/// Reads run concurrently, each on a tree of its own over a cursor of the database,
+/// while writes are serialized on the tree given. Cursors share the cached node blobs,
+/// so the nodes read by any of them are served from memory to the others.
+///
+/// Note that this does not hold a single tree behind an `RwLock`: every read of `Monotree`
+/// takes `&mut self` for its cache and memo, so a read lock would never be enough for one,
+/// and reads would be serialized as much as with a `Mutex`. Instead, a read borrows a tree
+/// of its own from a pool, holding a lock only while taking it out and putting it back.
+pub struct SharedMonotree<D, H = DefaultHasher, const N: usize = HASH_LEN> {
+    writer: Mutex<Monotree<D, H, N>>,
+    /// A tree for reads from which others are opened, apart from the writer
+    /// so that opening one never waits for writes.
+    template: Mutex<Monotree<D, H, N>>,
+    /// Trees for reads left idle, kept to be reused rather than opened on every read.
+    readers: Mutex<Vec<Monotree<D, H, N>>>,
+}
+
+impl<D, H, const N: usize> SharedMonotree<D, H, N>
+where
+    D: Cursor,
+    H: Hasher<N> + Clone,
+{
+    /// Share the given tree. Trees for reads are opened on cursors of its database,
+    /// with the same settings as the given one.
+    pub fn new(tree: Monotree<D, H, N>) -> Self {
+        SharedMonotree {
+            template: Mutex::new(Self::open_reader(&tree)),
+            writer: Mutex::new(tree),
+            readers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Get a leaf hash for the given root and key. Refer to `Monotree::get()`.
+    pub fn get(&self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        self.read(|tree| tree.get(root, key))
+    }
+
+    /// Generate a Merkle proof for the given root and key. Refer to `Monotree::get_merkle_proof()`.
+    pub fn get_merkle_proof(&self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Proof>> {
+        self.read(|tree| tree.get_merkle_proof(root, key))
+    }
+
+    /// Insert key-leaf entry into the `monotree`. Returns a new root hash.
+    pub fn insert(
+        &self,
+        root: Option<&Hash<N>>,
+        key: &[u8],
+        leaf: &Hash<N>,
+    ) -> Result<Option<Hash<N>>> {
+        self.write(|tree| tree.insert(root, key, leaf))
+    }
+
+    /// Insert the given key-leaf entries in batch mode. Returns a new root hash.
+    pub fn inserts<K: AsRef<[u8]>>(
+        &self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+        leaves: &[Hash<N>],
+    ) -> Result<Option<Hash<N>>> {
+        self.write(|tree| tree.inserts(root, keys, leaves))
+    }
+
+    /// Remove the given key and its corresponding leaf from the tree. Returns a new root hash.
+    pub fn remove(&self, root: Option<&Hash<N>>, key: &[u8]) -> Result<Option<Hash<N>>> {
+        self.write(|tree| tree.remove(root, key))
+    }
+
+    /// Remove the given keys in batch mode. Returns a new root hash.
+    pub fn removes<K: AsRef<[u8]>>(
+        &self,
+        root: Option<&Hash<N>>,
+        keys: &[K],
+    ) -> Result<Option<Hash<N>>> {
+        self.write(|tree| tree.removes(root, keys))
+    }
+
+    /// Run the given read-only operation on a tree for reads, opening one if none is idle.
+    /// The tree is not locked while the operation runs, so reads never wait for each other,
+    /// nor for writes in progress.
+    pub fn read<T, F>(&self, op: F) -> Result<T>
+    where
+        F: FnOnce(&mut Monotree<D, H, N>) -> Result<T>,
+    {
+        let idle = self.readers.lock().expect("read(): readers").pop();
+        let mut tree = match idle {
+            Some(tree) => tree,
+            None => Self::open_reader(&self.template.lock().expect("read(): template")),
+        };
+        let res = op(&mut tree);
+        self.readers.lock().expect("read(): readers").push(tree);
+        res
+    }
+
+    /// Run the given operation on the tree given by `new()`, one at a time.
+    pub fn write<T, F>(&self, op: F) -> Result<T>
+    where
+        F: FnOnce(&mut Monotree<D, H, N>) -> Result<T>,
+    {
+        op(&mut self.writer.lock().expect("write(): writer"))
+    }
+
+    /// Unwrap the tree given by `new()`.
+    pub fn into_inner(self) -> Monotree<D, H, N> {
+        self.writer.into_inner().expect("into_inner(): writer")
+    }
+
+    /// Open a tree on a cursor of the database of the given one, with the same settings.
+    fn open_reader(tree: &Monotree<D, H, N>) -> Monotree<D, H, N> {
+        Monotree {
+            db: tree.db.cursor(),
+            hasher: tree.hasher.clone(),
+            compact: tree.compact,
+            domain: tree.domain,
+            collision_check: tree.collision_check,
+            auto_head: false,
+            memoize: tree.memoize,
+            memo: None,
+            published_len: tree.published_len,
+            prefetch: tree.prefetch,
+            verify_on_read: tree.verify_on_read,
+            history_len: tree.history_len,
+            ns: tree.ns.clone(),
+            writes: HashSet::new(),
+            batch_open: false,
+            cleared: tree.cleared,
+        }
+    }
+}
+
+/// A stateful traversal over the subtree under a prefix. Refer to `Monotree::iter_under()`.
+struct IterUnder<'a, D, H, const N: usize> {
+    tree: &'a mut Monotree<D, H, N>,
+    prefix: Vec<bool>,
+    /// Nodes or leaves pending to be visited, along with the bits of key leading to them.
+    stack: Vec<(Hash<N>, Vec<bool>)>,
+}
+
+impl<'a, D, H, const N: usize> IterUnder<'a, D, H, N>
+where
+    D: Database,
+    H: Hasher<N>,
+{
+    /// Visit the node on top of the stack, then push its cells under the prefix.
+    /// Returns an entry if the visited one is a leaf rather than a node.
+    fn visit(&mut self) -> Result<Option<(Hash<N>, Hash<N>)>> {
+        let (hash, path) = match self.stack.pop() {
+            Some(pending) => pending,
+            None => return Ok(None),
+        };
+        if path.len() == N * 8 {
+            let key = slice_to_array(&slicebit_to_bytes(&path, 0));
+            return Ok(Some((key, hash)));
+        }
+        let bytes = self.tree.get_node(&hash)?;
+        let (lc, rc) = Node::<N>::cells_from_bytes(&bytes, false)?;
+        for unit in [rc, lc].iter().flatten() {
+            let bits = [&path[..], &unit.bits.to_bits()].concat();
+            let n = bits.len().min(self.prefix.len());
+            if bits[..n] == self.prefix[..n] {
+                self.stack.push((slice_to_array(unit.hash), bits));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'a, D, H, const N: usize> Iterator for IterUnder<'a, D, H, N>
+where
+    D: Database,
+    H: Hasher<N>,
+{
+    type Item = Result<(Hash<N>, Hash<N>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.stack.is_empty() {
+            match self.visit() {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Statistics of the nodes stored for a tree, generated by `Monotree::storage_stats()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageStats {
+    pub node_count: usize,
+    pub soft_nodes: usize,
+    pub hard_nodes: usize,
+    pub total_bytes: usize,
+}
+
+/// A Merkle proof for several keys at once, generated by `Monotree::get_merkle_multiproof()`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MultiProof {
+    /// Serialized nodes on the paths of the keys, without duplicates.
+    pub nodes: Vec<Vec<u8>>,
+    /// For each key, indices of `nodes` on its path from the root down to its leaf.
+    pub paths: Vec<Vec<usize>>,
+}
+
+/// A proof that a key does not exist in a tree, generated by `Monotree::get_non_inclusion_proof()`.
+///
+/// It consists of the serialized nodes on the path of the key, from the root down to
+/// the terminal node where the path of the key diverges from the tree.
+/// The key may diverge in the middle of a cell, fall off a soft node having no cell
+/// on its side, or reach a cell of a leaf of another key. No nodes stand for an empty tree.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NonInclusionProof {
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// Nodes changed from an old root to a new one, generated by `Monotree::update_proof()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateProof<const N: usize = HASH_LEN> {
+    pub root: Option<Hash<N>>,
+    pub nodes: Vec<Vec<u8>>,
+}
+
+/// A Merkle proof carrying both the root and the leaf it proves, to be passed around as a whole.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfContainedProof<const N: usize = HASH_LEN> {
+    pub root: Hash<N>,
+    pub leaf: Hash<N>,
+    pub steps: Proof,
+}
+
+impl<const N: usize> SelfContainedProof<N> {
+    /// Construct a self-contained proof from a plain `Proof`.
+    pub fn new(root: Hash<N>, leaf: Hash<N>, proof: Proof) -> Self {
+        SelfContainedProof {
+            root,
+            leaf,
+            steps: proof,
+        }
+    }
+}
+
+impl<const N: usize> From<SelfContainedProof<N>> for Proof {
+    fn from(proof: SelfContainedProof<N>) -> Self {
+        proof.steps
+    }
+}
+
+/// A Merkle proof bound to the key it proves, along with the leaf of the key.
+/// Refer to `Monotree::get_keyed_proof()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyedProof<const N: usize = HASH_LEN> {
+    pub key: Hash<N>,
+    pub leaf: Hash<N>,
+    pub proof: Proof,
+}
+
+/// A Merkle proof generated by `Monotree::get_compact_proof()`, which omits the bits of path
+/// of the cells on the path of the key. Those are rebuilt from the key on verification.
+///
+/// Unlike a `Proof` holding the serialized node for each step, only the number of bits
+/// the path consumes is kept for each node, along with the hash and bits of the other cell
+/// of a hard node. The bits of the other cell cannot be omitted, as they are not of the key.
+/// Encoded by `encode_compact_proof_bytes()`, proofs of a tree of 1M entries take about
+/// 774 bytes on average, 21% less than about 983 bytes by `encode_proof_bytes()`,
+/// as measured by `cargo run --release --example proof_size`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactProof<const N: usize = HASH_LEN> {
+    /// Steps for the nodes on the path, from the root down to the leaf.
+    pub steps: Vec<CompactStep<N>>,
+}
+
+/// A step of `CompactProof` for a node on the path of the key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactStep<const N: usize = HASH_LEN> {
+    /// The number of bits of the key consumed by the cell on the path.
+    pub len: BitsLen,
+    /// The hash and the bits of path of the other cell, if the node is a hard node.
+    pub sibling: Option<(Hash<N>, Vec<bool>)>,
+    /// Whether the node is serialized in compact form. Refer to `Node::to_compact_bytes()`.
+    pub compact: bool,
+}
+
+/// Serialize a `Proof` into bytes.
+///
+/// The number of steps comes first as a varint, so that truncated bytes never decode.
+/// Each step of the proof is encoded as a byte of its direction (`0x00` or `0x01`),
+/// followed by the length of its `cut` as a varint and then the `cut` itself.
+pub fn encode_proof_bytes(proof: &Proof) -> Vec<u8> {
+    let steps = proof.iter().flat_map(|(right, cut)| {
+        [
+            &[*right as u8][..],
+            &encode_varint(cut.len() as u64),
+            &cut[..],
+        ]
+        .concat()
+    });
+    encode_varint(proof.len() as u64)
+        .into_iter()
+        .chain(steps)
+        .collect()
+}
+
+/// Deserialize bytes into a `Proof`. Fails on malformed, truncated or trailing bytes.
+pub fn decode_proof_bytes(bytes: &[u8]) -> Result<Proof> {
+    let (steps, mut i) = decode_varint(bytes).map_err(|_| MonotreeError::MalformedProof)?;
+    let mut proof: Proof = Vec::new();
+    for _ in 0..steps {
+        let right = match bytes.get(i) {
+            Some(0x00) => false,
+            Some(0x01) => true,
+            _ => return Err(MonotreeError::MalformedProof),
+        };
+        let (len, n) = decode_varint(&bytes[i + 1..]).map_err(|_| MonotreeError::MalformedProof)?;
+        let start = i + 1 + n;
+        let end = match start.checked_add(len as usize) {
+            Some(end) if end <= bytes.len() => end,
+            _ => return Err(MonotreeError::MalformedProof),
+        };
+        proof.push((right, bytes[start..end].to_vec()));
+        i = end;
+    }
+    if i != bytes.len() {
+        return Err(MonotreeError::MalformedProof);
+    }
+    Ok(proof)
+}
+
+/// Serialize a `CompactProof` into bytes.
+///
+/// The number of steps comes first as a varint. Each step is encoded as a byte of flags,
+/// `0x01` for a hard node and `0x02` for a node in compact form, followed by the number of
+/// bits consumed as a varint. A hard node is then followed by the hash of the other cell,
+/// and the number of its bits as a varint along with the bits packed from the very first bit.
+pub fn encode_compact_proof_bytes<const N: usize>(proof: &CompactProof<N>) -> Vec<u8> {
+    let mut bytes = encode_varint(proof.steps.len() as u64);
+    for step in proof.steps.iter() {
+        let flags = step.sibling.is_some() as u8 | (step.compact as u8) << 1;
+        bytes.push(flags);
+        bytes.extend(encode_varint(step.len as u64));
+        if let Some((hash, bits)) = step.sibling.as_ref() {
+            bytes.extend(&hash[..]);
+            bytes.extend(encode_varint(bits.len() as u64));
+            bytes.extend(slicebit_to_bytes(bits, 0));
+        }
+    }
+    bytes
+}
+
+/// Deserialize bytes into a `CompactProof`. Fails on malformed, truncated or trailing bytes.
+pub fn decode_compact_proof_bytes<const N: usize>(bytes: &[u8]) -> Result<CompactProof<N>> {
+    let varint = |i: usize| match bytes.get(i..).map(decode_varint) {
+        Some(Ok((number, n))) if number <= BitsLen::MAX as u64 => Ok((number as BitsLen, i + n)),
+        _ => Err(MonotreeError::MalformedProof),
+    };
+    let (count, mut i) = decode_varint(bytes).map_err(|_| MonotreeError::MalformedProof)?;
+    let mut steps: Vec<CompactStep<N>> = Vec::new();
+    for _ in 0..count {
+        let flags = match bytes.get(i) {
+            Some(&flags) if flags <= 0x03 => flags,
+            _ => return Err(MonotreeError::MalformedProof),
+        };
+        let (len, j) = varint(i + 1)?;
+        i = j;
+        let sibling = if flags & 0x01 == 0x01 {
+            let hash = bytes.get(i..i + N).ok_or(MonotreeError::MalformedProof)?;
+            let (nbits, j) = varint(i + N)?;
+            let end = j + nbytes_across(0, nbits as usize);
+            let packed = bytes.get(j..end).ok_or(MonotreeError::MalformedProof)?;
+            i = end;
+            Some((slice_to_array(hash), bytes_to_slicebit(packed, &(0..nbits))))
+        } else {
+            None
+        };
+        steps.push(CompactStep {
+            len,
+            sibling,
+            compact: flags & 0x02 == 0x02,
+        });
+    }
+    if i != bytes.len() {
+        return Err(MonotreeError::MalformedProof);
+    }
+    Ok(CompactProof { steps })
+}
+
+/// Serialize a `MultiProof` into bytes.
+///
+/// The number of nodes comes first as a varint, followed by each node prefixed with
+/// its length as a varint. Then the number of paths, followed by each path as
+/// the number of indices and the indices themselves, all as varints.
+pub fn encode_multiproof_bytes(multiproof: &MultiProof) -> Vec<u8> {
+    let mut bytes = encode_varint(multiproof.nodes.len() as u64);
+    for node in multiproof.nodes.iter() {
+        bytes.extend(encode_varint(node.len() as u64));
+        bytes.extend(node);
+    }
+    bytes.extend(encode_varint(multiproof.paths.len() as u64));
+    for path in multiproof.paths.iter() {
+        bytes.extend(encode_varint(path.len() as u64));
+        for index in path.iter() {
+            bytes.extend(encode_varint(*index as u64));
+        }
+    }
+    bytes
+}
+
+/// Deserialize bytes into a `MultiProof`. Fails on malformed, truncated or trailing bytes.
+pub fn decode_multiproof_bytes(bytes: &[u8]) -> Result<MultiProof> {
+    let mut i = 0usize;
+    let next_varint = |i: &mut usize| -> Result<usize> {
+        let (x, n) = decode_varint(bytes.get(*i..).unwrap_or(&[]))
+            .map_err(|_| MonotreeError::MalformedProof)?;
+        *i += n;
+        Ok(x as usize)
+    };
+    let mut multiproof = MultiProof::default();
+    for _ in 0..next_varint(&mut i)? {
+        let len = next_varint(&mut i)?;
+        let end = match i.checked_add(len) {
+            Some(end) if end <= bytes.len() => end,
+            _ => return Err(MonotreeError::MalformedProof),
+        };
+        multiproof.nodes.push(bytes[i..end].to_vec());
+        i = end;
+    }
+    for _ in 0..next_varint(&mut i)? {
+        let path = (0..next_varint(&mut i)?)
+            .map(|_| next_varint(&mut i))
+            .collect::<Result<Vec<_>>>()?;
+        multiproof.paths.push(path);
+    }
+    if i != bytes.len() {
+        return Err(MonotreeError::MalformedProof);
+    }
+    Ok(multiproof)
+}
+
+/// A header put at the very beginning of proof bundles, including the version of the format.
+const BUNDLE_MAGIC: &[u8] = b"monotree::bundle\x01";
+
+/// A header put at the very beginning of blobs of subtrees, including the version of the format.
+const SUBTREE_MAGIC: &[u8] = b"monotree::subtree\x01";
+
+/// A header put at the very beginning of dumps of databases, including the version of the format.
+const DUMP_MAGIC: &[u8] = b"monotree::dump\x01";
+
+/// Serialize a proof bundle. Refer to `Monotree::export_proof_bundle()`.
+///
+/// The bundle consists of `BUNDLE_MAGIC`, the length of hash as a varint,
+/// the fingerprint of the hasher, the root, the number of entries as a varint,
+/// each entry of key and leaf, and then the multiproof by `encode_multiproof_bytes()`.
+fn bundle_bytes<const N: usize>(
+    fingerprint: &Hash<N>,
+    root: &Hash<N>,
+    entries: &[(Hash<N>, Hash<N>)],
+    multiproof: &MultiProof,
+) -> Vec<u8> {
+    let mut bytes = BUNDLE_MAGIC.to_vec();
+    bytes.extend(encode_varint(N as u64));
+    bytes.extend(fingerprint);
+    bytes.extend(root);
+    bytes.extend(encode_varint(entries.len() as u64));
+    for (key, leaf) in entries.iter() {
+        bytes.extend(key);
+        bytes.extend(leaf);
+    }
+    bytes.extend(encode_multiproof_bytes(multiproof));
+    bytes
+}
+
+/// Verify a proof bundle exported by `Monotree::export_proof_bundle()` with the given hasher.
+///
+/// Fails on malformed bytes, or on a bundle of another length of hash or of another hasher.
+/// Otherwise returns whether the multiproof in the bundle is valid for its entries and root.
+pub fn verify_proof_bundle<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    r: &mut impl Read,
+) -> Result<bool> {
+    let mut bytes: Vec<u8> = Vec::new();
+    r.read_to_end(&mut bytes)?;
+    let mut rest = match bytes.strip_prefix(BUNDLE_MAGIC) {
+        Some(rest) => rest,
+        None => return Err(MonotreeError::MalformedProof),
+    };
+    let next_varint = |rest: &mut &[u8]| -> Result<u64> {
+        let (x, n) = decode_varint(rest).map_err(|_| MonotreeError::MalformedProof)?;
+        *rest = &rest[n..];
+        Ok(x)
+    };
+    let next_hash = |rest: &mut &[u8]| -> Result<Hash<N>> {
+        if rest.len() < N {
+            return Err(MonotreeError::MalformedProof);
+        }
+        let hash = slice_to_array(&rest[..N]);
+        *rest = &rest[N..];
+        Ok(hash)
+    };
+    if next_varint(&mut rest)? != N as u64 {
+        return Err(MonotreeError::MalformedProof);
+    }
+    if next_hash(&mut rest)? != hasher.digest(&[]) {
+        return Err(MonotreeError::MalformedProof);
+    }
+    let root = next_hash(&mut rest)?;
+    let mut entries: Vec<(Hash<N>, Hash<N>)> = Vec::new();
+    for _ in 0..next_varint(&mut rest)? {
+        entries.push((next_hash(&mut rest)?, next_hash(&mut rest)?));
+    }
+    let multiproof = decode_multiproof_bytes(rest)?;
+    Ok(verify_multiproof(
+        hasher,
+        Some(&root),
+        &entries,
+        &multiproof,
+    ))
+}
+
+/// Apply an update from `Monotree::update_proof()` to the partial view of a light client,
+/// i.e., a database holding nodes of the old root. Returns the new root.
+///
+/// Nodes are stored under their digests, so that no forged node can take place of another.
+/// Fails on malformed nodes, or if the new root is found in neither the update nor the view.
+/// Note that nodes of trees with domain separation on are not supported.
+pub fn apply_update<D: Database, H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    partial: &mut D,
+    update: &UpdateProof<N>,
+) -> Result<Option<Hash<N>>> {
+    for bytes in update.nodes.iter() {
+        Node::<N>::from_bytes(bytes)?;
+    }
+    partial.init_batch()?;
+    let put = update
+        .nodes
+        .iter()
+        .try_for_each(|bytes| partial.put(&hasher.digest(bytes), bytes.to_vec()));
+    match put {
+        Ok(()) => partial.finish_batch()?,
+        Err(err) => {
+            partial.discard_batch()?;
+            return Err(err);
+        }
+    }
+    if let Some(root) = update.root.as_ref() {
+        if partial.get(root)?.is_none() {
+            return Err(Errors::new("apply_update(): root not found"));
+        }
+    }
+    Ok(update.root)
+}
+
+/// Verify a Merkle proof with the given root, leaf and hasher if the proof is valid or not.
+///
+/// Be aware of that it fails if not provided a suitable hasher used in the tree
+/// This generic fn must be independantly called upon request, not a member of Monotree.
+pub fn verify_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    leaf: &Hash<N>,
+    proof: Option<&Proof>,
+) -> bool {
+    verify_proof_with_prefix(hasher, root, leaf, proof, &[])
+}
+
+/// Verify a Merkle proof the same as `verify_proof()`, with the hasher given by its type,
+/// e.g., `verify_proof_with::<Blake3>(root, leaf, proof)`.
+///
+/// The hasher is constructed by `Hasher::new()`, i.e., with the default config.
+/// Hashers of a state such as a key, or of hashes other than `HASH_LEN` bytes,
+/// must be passed to `verify_proof()` instead.
+pub fn verify_proof_with<H: Hasher>(
+    root: Option<&Hash>,
+    leaf: &Hash,
+    proof: Option<&Proof>,
+) -> bool {
+    verify_proof(&H::new(), root, leaf, proof)
+}
+
+/// Verify Merkle proofs of many leaves against the same root at once.
+///
+/// Each item is a pair of a leaf and its proof. The results are the same as calling
+/// `verify_proof()` for each item, but a single buffer is reused for all nodes rebuilt
+/// instead of allocating one per step, and each item stops at its first malformed step.
+pub fn verify_proofs<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    items: &[(Hash<N>, &Proof)],
+) -> Vec<bool> {
+    let root = match root {
+        Some(root) => root,
+        None => return vec![false; items.len()],
+    };
+    let mut buf: Vec<u8> = Vec::new();
+    items
+        .iter()
+        .map(|(leaf, proof)| {
+            if proof.is_empty() {
+                return false;
+            }
+            let mut hash = *leaf;
+            for (right, cut) in proof.iter().rev() {
+                if cut.is_empty() {
+                    return false;
+                }
+                buf.clear();
+                if *right {
+                    let l = cut.len();
+                    buf.extend_from_slice(&cut[..l - 1]);
+                    buf.extend_from_slice(&hash);
+                    buf.extend_from_slice(&cut[l - 1..]);
+                } else {
+                    buf.extend_from_slice(&hash);
+                    buf.extend_from_slice(cut);
+                }
+                hash = hasher.digest(&buf);
+            }
+            hash == *root
+        })
+        .collect()
+}
+
+/// Verify a Merkle proof of a tree built with domain separation on.
+/// Refer to `Monotree::with_domain_separation()`.
+pub fn verify_domain_separated_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    leaf: &Hash<N>,
+    proof: Option<&Proof>,
+) -> bool {
+    verify_proof_with_prefix(hasher, root, leaf, proof, &[NODE_PREFIX])
+}
+
+fn verify_proof_with_prefix<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    leaf: &Hash<N>,
+    proof: Option<&Proof>,
+    prefix: &[u8],
+) -> bool {
+    verify_proof_detailed_with_prefix(hasher, root, leaf, proof, prefix).is_ok()
+}
+
+/// Verify a Merkle proof like `verify_proof()`, but fail with the reason why it is invalid:
+/// `"empty proof"`, `"root mismatch: expected .. got .."`
+/// or `"malformed proof segment at index i"`.
+pub fn verify_proof_detailed<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    leaf: &Hash<N>,
+    proof: Option<&Proof>,
+) -> Result<()> {
+    verify_proof_detailed_with_prefix(hasher, root, leaf, proof, &[])
+}
+
+fn verify_proof_detailed_with_prefix<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    leaf: &Hash<N>,
+    proof: Option<&Proof>,
+    prefix: &[u8],
+) -> Result<()> {
+    let proof = match proof {
+        Some(proof) if !proof.is_empty() => proof,
+        _ => return Err(Errors::new("empty proof")),
+    };
+    let hash = compute_root_with_prefix(hasher, leaf, proof, prefix)?;
+    match root {
+        Some(root) if *root == hash => Ok(()),
+        _ => Err(Errors::new(&format!(
+            "root mismatch: expected {} got {}",
+            root.map_or("none".to_string(), |root| hex!(root)),
+            hex!(hash)
+        ))),
+    }
+}
+
+/// Reconstruct the root from the given leaf and its Merkle proof.
+///
+/// Unlike `verify_proof()`, this hands over the reconstructed root to callers.
+/// Fails on a malformed proof such as the one with an empty `cut`.
+pub fn compute_root_from_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    leaf: &Hash<N>,
+    proof: &Proof,
+) -> Result<Hash<N>> {
+    compute_root_with_prefix(hasher, leaf, proof, &[])
+}
+
+fn compute_root_with_prefix<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    leaf: &Hash<N>,
+    proof: &Proof,
+    prefix: &[u8],
+) -> Result<Hash<N>> {
+    let mut hash = leaf.to_owned();
+    for (i, (right, cut)) in proof.iter().enumerate().rev() {
+        if cut.is_empty() {
+            return Err(Errors::new(&format!(
+                "malformed proof segment at index {}",
+                i
+            )));
+        }
+        if *right {
+            let l = cut.len();
+            let o = [prefix, &cut[..l - 1], &hash[..], &cut[l - 1..]].concat();
+            hash = hasher.digest(&o);
+        } else {
+            let o = [prefix, &hash[..], &cut[..]].concat();
+            hash = hasher.digest(&o);
+        }
+    }
+    Ok(hash)
+}
+
+/// Verify a Merkle proof of a value inserted by `Monotree::insert_value()`.
+pub fn verify_value_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    value: &[u8],
+    proof: Option<&Proof>,
+) -> bool {
+    verify_proof(hasher, root, &hasher.digest(value), proof)
+}
+
+/// Verify a Merkle proof of a value inserted by `Monotree::insert_value()`
+/// into a tree built with domain separation on.
+pub fn verify_domain_separated_value_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    value: &[u8],
+    proof: Option<&Proof>,
+) -> bool {
+    let leaf = hasher.digest(&[&[LEAF_PREFIX], value].concat());
+    verify_domain_separated_proof(hasher, root, &leaf, proof)
+}
+
+/// Verify a `MultiProof` with the given root, entries of key and leaf, and hasher.
+///
+/// The entries must be given in the same order as the keys the multiproof was generated for.
+pub fn verify_multiproof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    entries: &[(Hash<N>, Hash<N>)],
+    multiproof: &MultiProof,
+) -> bool {
+    let root = match root {
+        Some(root) => root,
+        None => return false,
+    };
+    if entries.len() != multiproof.paths.len() {
+        return false;
+    }
+    let hashes: Vec<Hash<N>> = multiproof.nodes.iter().map(|x| hasher.digest(x)).collect();
+    entries
+        .iter()
+        .zip(multiproof.paths.iter())
+        .all(|((key, leaf), path)| {
+            let mut bits = Bits::new(key);
+            let mut hash: &[u8] = root;
+            for (i, &index) in path.iter().enumerate() {
+                let bytes = match multiproof.nodes.get(index) {
+                    Some(bytes) if hashes[index][..] == hash[..] => bytes,
+                    _ => return false,
+                };
+                let unit = match Node::<N>::cells_from_bytes(bytes, bits.first()) {
+                    Ok((Some(unit), _)) => unit,
+                    _ => return false,
+                };
+                let n = Bits::len_common_bits(&unit.bits, &bits);
+                if i == path.len() - 1 {
+                    return n == bits.len() && unit.hash == leaf;
+                }
+                if n != unit.bits.len() {
+                    return false;
+                }
+                hash = unit.hash;
+                bits = bits.shift(n, false);
+            }
+            false
+        })
+}
+
+/// Compute a single commitment over roots of several trees, building a binary Merkle tree
+/// of which leaves are the given roots in order. Returns zeros if no roots are given.
+///
+/// Leaves are digested with `LEAF_PREFIX` and nodes with `NODE_PREFIX`, where an empty tree
+/// (`None`) is digested apart from any root. A node left without its pair on a level is
+/// digested alone, and the number of roots is digested into the top.
+pub fn commit_roots<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    roots: &[Option<Hash<N>>],
+) -> Hash<N> {
+    match aggregate_roots(hasher, roots).last() {
+        Some(top) => commit_top(hasher, &top[0], roots.len()),
+        None => [0x00; N],
+    }
+}
+
+/// Generate a proof that the root at the given index is committed by `commit_roots()`.
+/// Returns `None` if the index is out of range.
+pub fn proof_of_root<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    roots: &[Option<Hash<N>>],
+    index: usize,
+) -> Option<Proof> {
+    if index >= roots.len() {
+        return None;
+    }
+    let levels = aggregate_roots(hasher, roots);
+    let mut proof: Proof = Vec::new();
+    let mut i = index;
+    for level in levels[..levels.len() - 1].iter() {
+        if let Some(sibling) = level.get(i ^ 1) {
+            proof.push((i & 1 == 1, sibling.to_vec()));
+        }
+        i >>= 1;
+    }
+    Some(proof)
+}
+
+/// Verify a proof from `proof_of_root()` if the given root is committed by the commitment
+/// at the given index among `len` roots.
+///
+/// The steps of the proof must be exactly the ones the index and the number of roots lead to.
+pub fn verify_proof_of_root<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    commitment: &Hash<N>,
+    root: Option<&Hash<N>>,
+    index: usize,
+    len: usize,
+    proof: &Proof,
+) -> bool {
+    if index >= len {
+        return false;
+    }
+    let mut hash = digest_root(hasher, root);
+    let mut steps = proof.iter();
+    let (mut i, mut width) = (index, len);
+    while width > 1 {
+        hash = if i ^ 1 < width {
+            match steps.next() {
+                Some((right, sibling)) if *right == (i & 1 == 1) && sibling.len() == N => {
+                    if *right {
+                        hasher.digest(&[&[NODE_PREFIX], &sibling[..], &hash[..]].concat())
+                    } else {
+                        hasher.digest(&[&[NODE_PREFIX], &hash[..], &sibling[..]].concat())
+                    }
+                }
+                _ => return false,
+            }
+        } else {
+            hasher.digest(&[&[NODE_PREFIX], &hash[..]].concat())
+        };
+        i >>= 1;
+        width = width.div_ceil(2);
+    }
+    steps.next().is_none() && commitment == &commit_top(hasher, &hash, len)
+}
+
+/// Get all levels of the Merkle tree over roots, from the digested roots up to the top.
+fn aggregate_roots<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    roots: &[Option<Hash<N>>],
+) -> Vec<Vec<Hash<N>>> {
+    if roots.is_empty() {
+        return Vec::new();
+    }
+    let mut levels = vec![roots
+        .iter()
+        .map(|root| digest_root(hasher, root.as_ref()))
+        .collect::<Vec<_>>()];
+    while levels[levels.len() - 1].len() > 1 {
+        let level = levels[levels.len() - 1]
+            .chunks(2)
+            .map(|pair| match pair {
+                [l, r] => hasher.digest(&[&[NODE_PREFIX], &l[..], &r[..]].concat()),
+                _ => hasher.digest(&[&[NODE_PREFIX], &pair[0][..]].concat()),
+            })
+            .collect();
+        levels.push(level);
+    }
+    levels
+}
+
+/// Digest a root into a leaf of the tree over roots, where `None` is digested with no bytes.
+fn digest_root<H: Hasher<N>, const N: usize>(hasher: &H, root: Option<&Hash<N>>) -> Hash<N> {
+    match root {
+        Some(root) => hasher.digest(&[&[LEAF_PREFIX], &root[..]].concat()),
+        None => hasher.digest(&[LEAF_PREFIX]),
+    }
+}
+
+/// Digest the top of the tree over roots together with the number of roots.
+fn commit_top<H: Hasher<N>, const N: usize>(hasher: &H, top: &Hash<N>, len: usize) -> Hash<N> {
+    hasher.digest(&[&[NODE_PREFIX], &top[..], &(len as u64).to_be_bytes()[..]].concat())
+}
+
+/// Verify a `SelfContainedProof` if its steps reproduce the embedded root from the embedded leaf.
+///
+/// Note that this does not tell if the embedded root is trustworthy.
+/// It is up to callers to check the root against the one they trust.
+pub fn verify_self_contained<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    proof: &SelfContainedProof<N>,
+) -> bool {
+    verify_proof(hasher, Some(&proof.root), &proof.leaf, Some(&proof.steps))
+}
+
+/// Verify a `CompactProof` of the given key and leaf against the given root.
+///
+/// The nodes on the path are rebuilt from the leaf up to the root, where the cells
+/// on the path take the bits of the key, so the proof verifies only for the key it is of.
+/// Proofs of a tree built with domain separation on are not supported.
+pub fn verify_compact_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    key: &[u8],
+    leaf: &Hash<N>,
+    proof: &CompactProof<N>,
+) -> bool {
+    let (root, key) = match (root, try_slice_to_array::<N>(key)) {
+        (Some(root), Ok(key)) => (root, key),
+        _ => return false,
+    };
+    // the offsets of the nodes in bits of the key, which must be consumed exactly
+    let mut offsets: Vec<usize> = Vec::with_capacity(proof.steps.len());
+    let mut offset = 0usize;
+    for step in proof.steps.iter() {
+        if step.len == 0 {
+            return false;
+        }
+        offsets.push(offset);
+        offset += step.len as usize;
+    }
+    if proof.steps.is_empty() || offset != N * 8 {
+        return false;
+    }
+    let mut hash = *leaf;
+    for (step, &offset) in proof.steps.iter().zip(offsets.iter()).rev() {
+        let bits = Bits::new(&key).shift(offset as BitsLen, false);
+        let start = bits.range.start as usize;
+        let bits = bits.shift(step.len, true);
+        let packed;
+        let other = match step.sibling.as_ref() {
+            None => None,
+            Some((_, sibling)) if sibling.is_empty() || sibling[0] == bits.first() => {
+                return false;
+            }
+            Some((hash, sibling)) => {
+                packed = slicebit_to_bytes(sibling, start);
+                let range = start as BitsLen..(start + sibling.len()) as BitsLen;
+                Some(Unit {
+                    hash,
+                    bits: Bits {
+                        path: &packed,
+                        range,
+                    },
+                })
+            }
+        };
+        let node = Node::<N>::new(Some(Unit { hash: &hash, bits }), other);
+        let bytes = match if step.compact {
+            node.to_compact_bytes()
+        } else {
+            node.to_bytes()
+        } {
+            Ok(bytes) => bytes,
+            Err(_) => return false,
+        };
+        hash = hasher.digest(&bytes);
+    }
+    hash == *root
+}
+
+/// Verify a `KeyedProof` if its leaf is the one of its key in the tree with the given root.
+///
+/// Unlike `verify_proof()`, the nodes are rebuilt from the leaf up to the root, and then
+/// the path of the key is checked to go down along them and to end at the leaf.
+/// Thus, a proof of a key never verifies as the one of another key, even of the same leaf.
+pub fn verify_keyed_proof<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    keyed: &KeyedProof<N>,
+) -> bool {
+    let root = match root {
+        None => return false,
+        Some(root) => root,
+    };
+    // nodes along with the side and the hash of their child on the path, from the bottom up
+    let mut nodes: Vec<(Vec<u8>, bool, Hash<N>)> = Vec::with_capacity(keyed.proof.len());
+    let mut hash = keyed.leaf;
+    for (right, cut) in keyed.proof.iter().rev() {
+        if cut.is_empty() {
+            return false;
+        }
+        let bytes = if *right {
+            let l = cut.len();
+            [&cut[..l - 1], &hash[..], &cut[l - 1..]].concat()
+        } else {
+            [&hash[..], &cut[..]].concat()
+        };
+        let child = std::mem::replace(&mut hash, hasher.digest(&bytes));
+        nodes.push((bytes, *right, child));
+    }
+    if nodes.is_empty() || hash != *root {
+        return false;
+    }
+    let mut bits = Bits::new(&keyed.key);
+    for (i, (bytes, right, child)) in nodes.iter().rev().enumerate() {
+        // the child must be on the side the key goes, as cells of the same hash may be both
+        let unit = match Node::<N>::cells_from_bytes(bytes, bits.first()) {
+            Ok((Some(unit), None)) if !right => unit,
+            Ok((Some(unit), Some(_))) if *right == bits.first() => unit,
+            _ => return false,
+        };
+        let n = Bits::len_common_bits(&unit.bits, &bits);
+        if n != unit.bits.len() || unit.hash != &child[..] {
+            return false;
+        }
+        if i == nodes.len() - 1 {
+            return n == bits.len();
+        }
+        bits = bits.shift(n, false);
+    }
+    false
+}
+
+/// Verify a `NonInclusionProof` if the given key does not exist in the tree with the given root.
+///
+/// It checks that the nodes in the proof are chained by their hashes from the given root,
+/// that the path of the key goes along them and that it diverges at the last node.
+pub fn verify_non_inclusion<H: Hasher<N>, const N: usize>(
+    hasher: &H,
+    root: Option<&Hash<N>>,
+    key: &[u8],
+    proof: &NonInclusionProof,
+) -> bool {
+    let root = match root {
+        None => return proof.nodes.is_empty(),
+        Some(root) => root,
+    };
+    if key.len() != N {
+        return false;
+    }
+    let mut bits = Bits::new(key);
+    let mut hash: &[u8] = root;
+    for (i, bytes) in proof.nodes.iter().enumerate() {
+        if hasher.digest(bytes)[..] != hash[..] {
+            return false;
+        }
+        let unit = match Node::<N>::cells_from_bytes(bytes, bits.first()) {
+            Ok((Some(unit), _)) => unit,
+            _ => return false,
+        };
+        let n = Bits::len_common_bits(&unit.bits, &bits);
+        if i == proof.nodes.len() - 1 {
+            return n < unit.bits.len() && n < bits.len();
+        }
+        if n != unit.bits.len() {
+            return false;
         }
+        hash = unit.hash;
+        bits = bits.shift(n, false);
     }
+    false
 }
@@ -100,11 +100,37 @@ pub fn random_hashes(n: usize) -> Vec<Hash> {
 
 /// Get a fixed lenght byte-array or `Hash` from slice.
 pub fn slice_to_hash(slice: &[u8]) -> Hash {
-    let mut hash = [0x00; HASH_LEN];
+    slice_to_array(slice)
+}
+
+/// Get a `Hash` from slice, or an error when the slice is not `HASH_LEN` long.
+pub fn try_slice_to_hash(slice: &[u8]) -> Result<Hash> {
+    try_slice_to_array(slice)
+}
+
+/// Get a fixed length byte-array of any length `N` from slice.
+pub fn slice_to_array<const N: usize>(slice: &[u8]) -> Hash<N> {
+    let mut hash = [0x00; N];
     hash.copy_from_slice(slice);
     hash
 }
 
+/// Get a byte-array of length `N` from slice, or an error when the slice is not `N` long.
+pub fn try_slice_to_array<const N: usize>(slice: &[u8]) -> Result<Hash<N>> {
+    if slice.len() != N {
+        return Err(MonotreeError::KeyLength {
+            expected: N,
+            got: slice.len(),
+        });
+    }
+    Ok(slice_to_array(slice))
+}
+
+/// Get a Vec of bytes slices from a slice of bytes-like items.
+pub fn as_slices<T: AsRef<[u8]>>(items: &[T]) -> Vec<&[u8]> {
+    items.iter().map(|x| x.as_ref()).collect()
+}
+
 /// Shuffle a slice using _Fisher-Yates_ algorithm.
 pub fn shuffle<T: Clone>(slice: &mut [T]) {
     let mut rng = rand::thread_rng();
@@ -116,19 +142,25 @@ pub fn shuffle<T: Clone>(slice: &mut [T]) {
 }
 
 /// Get sorted indices from unsorted slice.
+/// The sort is stable, so indices of equal items remain in the order of the slice.
 pub fn get_sorted_indices<T>(slice: &[T], reverse: bool) -> Vec<usize>
 where
     T: Clone + cmp::Ord,
 {
     let mut t: Vec<_> = slice.iter().enumerate().collect();
     if reverse {
-        t.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+        t.sort_by(|(_, a), (_, b)| b.cmp(a));
     } else {
-        t.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
+        t.sort_by_key(|&(_, a)| a);
     }
     t.iter().map(|(i, _)| *i).collect()
 }
 
+/// Check if the given bytes-like items are sorted in ascending order.
+pub fn is_sorted<T: AsRef<[u8]>>(items: &[T]) -> bool {
+    items.windows(2).all(|w| w[0].as_ref() <= w[1].as_ref())
+}
+
 /// Get length of the longest common prefix bits for the given two slices.
 pub fn len_lcp<T>(a: &[u8], m: &Range<T>, b: &[u8], n: &Range<T>) -> T
 where
@@ -143,8 +175,8 @@ where
 
 /// Get `i`-th bit from bytes slice. Index `i` starts from 0.
 pub fn bit<T: PrimInt + NumCast>(bytes: &[u8], i: T) -> bool {
-    let q = i.to_usize().expect("bit(): usize") / 8;
-    let r = i.to_u8().expect("bit(): u8") % 8;
+    let i = i.to_usize().expect("bit(): usize");
+    let (q, r) = (i / 8, i % 8);
     (bytes[q] >> (7 - r)) & 0x01 == 0x01
 }
 
@@ -192,6 +224,36 @@ pub fn int_to_bytes(number: u64) -> Vec<u8> {
     }
 }
 
+/// Encode a `u64` into a variable-length bytes (unsigned LEB128).
+pub fn encode_varint(number: u64) -> Vec<u8> {
+    let mut n = number;
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+/// Decode a variable-length bytes (unsigned LEB128) into a `u64`.
+/// Returns the number and the length of bytes read.
+pub fn decode_varint(bytes: &[u8]) -> Result<(u64, usize)> {
+    let mut number = 0u64;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        number |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((number, i + 1));
+        }
+    }
+    Err(MonotreeError::corruption(
+        "decode_varint(): truncated or overflowed",
+    ))
+}
+
 /// Convert a Vec slice of bit or `bool` into a number as `usize`.
 pub fn bits_to_usize(bits: &[bool]) -> usize {
     let l = bits.len();
@@ -212,6 +274,17 @@ where
     range.clone().map(|x| bit(bytes, x)).collect()
 }
 
+/// Convert bits into bytes, placing them from the `start`-th bit.
+/// This is the inverse of `bytes_to_slicebit()`, filling the bits out of range with zeros.
+pub fn slicebit_to_bytes(bits: &[bool], start: usize) -> Vec<u8> {
+    let mut bytes = vec![0x00; nbytes_across(start, start + bits.len())];
+    bits.iter()
+        .enumerate()
+        .filter(|(_, &bit)| bit)
+        .for_each(|(i, _)| bytes[(start + i) / 8] |= 0x80 >> ((start + i) % 8));
+    bytes
+}
+
 /// Convert bits, Vec slice of `bool` into bytes, `Vec<u8>`.
 pub fn bits_to_bytes(bits: &[bool]) -> Vec<u8> {
     bits.rchunks(8)
@@ -240,6 +313,13 @@ mod tests {
         assert_eq!(nbytes_across(7, 19), 3);
     }
 
+    #[test]
+    fn test_is_sorted() {
+        assert!(is_sorted::<&[u8]>(&[]));
+        assert!(is_sorted(&[[0x01, 0x02], [0x01, 0x02], [0x02, 0x00]]));
+        assert!(!is_sorted(&[[0x01, 0x02], [0x01, 0x01]]));
+    }
+
     #[test]
     fn test_offsets() {
         assert_eq!(offsets(&(0..8), 1, false), (0, 1..8));
@@ -264,6 +344,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_varint() {
+        assert_eq!(encode_varint(0), [0x00]);
+        assert_eq!(encode_varint(127), [0x7f]);
+        assert_eq!(encode_varint(300), [0xac, 0x02]);
+        assert_eq!(decode_varint(&[0xac, 0x02, 0xff]).unwrap(), (300, 2));
+        assert_eq!(decode_varint(&encode_varint(u64::MAX)).unwrap().0, u64::MAX);
+        assert!(decode_varint(&[0xac]).is_err());
+        assert!(decode_varint(&[]).is_err());
+    }
+
     #[test]
     fn test_bytes_to_bits() {
         assert_eq!(
@@ -284,6 +375,15 @@ mod tests {
         assert_eq!(bits_to_bytes(&bits), [0x33, 0x33]);
     }
 
+    #[test]
+    fn test_slicebit_to_bytes() {
+        let bytes = [0x73, 0x6f, 0x66];
+        let bits = bytes_to_slicebit(&bytes, &(3..13));
+        assert_eq!(slicebit_to_bytes(&bits, 3), [0x13, 0x68]);
+        assert_eq!(slicebit_to_bytes(&bits, 0), [0x9b, 0x40]);
+        assert_eq!(slicebit_to_bytes(&[], 0), []);
+    }
+
     #[test]
     fn test_bits_to_usize() {
         assert_eq!(
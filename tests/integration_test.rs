@@ -1,8 +1,21 @@
-use monotree::database::{rocksdb::RocksDB, sled::Sled, MemoryDB};
+#[cfg(feature = "db_lmdb")]
+use monotree::database::lmdb::Lmdb;
+#[cfg(feature = "db_redb")]
+use monotree::database::redb::Redb;
+#[cfg(feature = "db_rocksdb")]
+use monotree::database::rocksdb::RocksDB;
+#[cfg(feature = "db_sled")]
+use monotree::database::sled::Sled;
+use monotree::database::{DynDatabase, MemoryDB};
 use monotree::hasher::*;
 use monotree::utils::*;
 use monotree::*;
+use std::collections::HashMap;
 use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 extern crate paste;
 extern crate scopeguard;
@@ -226,22 +239,3531 @@ macro_rules! impl_test_with_params {
     ($($other:tt)*) => {};
 }
 
-impl_test_with_params!(
-    [
-        insert_keys_then_verify_values,
-        insert_keys_then_gen_and_verify_proof,
-        insert_keys_then_delete_keys_immediately,
-        insert_keys_then_delete_keys_in_order,
-        insert_keys_then_delete_keys_reversely,
-        insert_keys_then_delete_keys_randomly
-    ],
-    [("hashmap", MemoryDB), ("rocksdb", RocksDB), ("sled", Sled)],
-    [
-        ("blake3", Blake3),
-        ("blake2s", Blake2s),
-        ("blake2b", Blake2b),
-        ("sha2", Sha2),
-        ("sha3", Sha3)
-    ],
-    [100, 500, 1000]
-);
+macro_rules! impl_test_with_db {
+    ($($db:tt)+) => {
+        impl_test_with_params!(
+            [
+                insert_keys_then_verify_values,
+                insert_keys_then_gen_and_verify_proof,
+                insert_keys_then_delete_keys_immediately,
+                insert_keys_then_delete_keys_in_order,
+                insert_keys_then_delete_keys_reversely,
+                insert_keys_then_delete_keys_randomly
+            ],
+            [($($db)+)],
+            [
+                ("blake3", Blake3),
+                ("blake2s", Blake2s),
+                ("blake2b", Blake2b),
+                ("sha2", Sha2),
+                ("sha3", Sha3),
+                ("keccak256", Keccak256),
+                ("sha512_256", Sha512_256)
+            ],
+            [100, 500, 1000]
+        );
+    };
+}
+
+impl_test_with_db!("hashmap", MemoryDB);
+#[cfg(feature = "db_rocksdb")]
+impl_test_with_db!("rocksdb", RocksDB);
+#[cfg(feature = "db_sled")]
+impl_test_with_db!("sled", Sled);
+#[cfg(feature = "db_redb")]
+impl_test_with_db!("redb", Redb);
+#[cfg(feature = "db_lmdb")]
+impl_test_with_db!("lmdb", Lmdb);
+
+fn insert_keys_then_read_from_another_cursor<D: Database, H: Hasher>(
+    db: D,
+    cursor: D,
+    keys: &[Hash],
+    leaves: &[Hash],
+) -> Result<()> {
+    let mut writer = Monotree::<D, H>::with_db(db);
+    let mut reader = Monotree::<D, H>::with_db(cursor);
+    let root = writer.inserts(None, keys, leaves)?;
+
+    // the other cursor sees all the nodes written just before
+    for (k, v) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(reader.get(root.as_ref(), k)?, Some(*v));
+    }
+    Ok(())
+}
+
+macro_rules! impl_cursor_test {
+    ($d:expr, $db:ident) => {
+        paste::item_with_macros! {
+            #[test]
+            fn [<test_ $d _cursors_share_db_and_cache>]() -> Result<()> {
+                let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+                let _g = scopeguard::guard((), |_| {
+                    if fs::metadata(&dbname).is_ok() {
+                        fs::remove_dir_all(&dbname).unwrap()
+                    }
+                });
+                let keys = random_hashes(500);
+                let leaves = random_hashes(500);
+                let db = $db::new(&dbname);
+                let cursor = db.cursor();
+                insert_keys_then_read_from_another_cursor::<_, Blake3>(db, cursor, &keys, &leaves)
+            }
+        }
+    };
+}
+
+impl_cursor_test!("hashmap", MemoryDB);
+#[cfg(feature = "db_rocksdb")]
+impl_cursor_test!("rocksdb", RocksDB);
+#[cfg(feature = "db_sled")]
+impl_cursor_test!("sled", Sled);
+#[cfg(feature = "db_redb")]
+impl_cursor_test!("redb", Redb);
+#[cfg(feature = "db_lmdb")]
+impl_cursor_test!("lmdb", Lmdb);
+
+fn insert_keys_then_read_from_threads<D, H>(
+    tree: Monotree<D, H>,
+    keys: &[Hash],
+    leaves: &[Hash],
+) -> Result<()>
+where
+    D: Cursor + Send,
+    H: Hasher + Clone + Send,
+{
+    let shared = SharedMonotree::new(tree);
+    let root = shared.inserts(None, keys, leaves)?;
+    let more = random_hashes(100);
+
+    // readers on the fixed root run along with a writer inserting on top of it
+    thread::scope(|s| -> Result<()> {
+        let readers: Vec<_> = (0..8)
+            .map(|i| {
+                let shared = &shared;
+                s.spawn(move || -> Result<()> {
+                    let hasher = H::new();
+                    for (k, v) in keys.iter().zip(leaves.iter()).skip(i) {
+                        assert_eq!(shared.get(root.as_ref(), k)?, Some(*v));
+                        let proof = shared.get_merkle_proof(root.as_ref(), k)?;
+                        assert!(verify_proof(&hasher, root.as_ref(), v, proof.as_ref()));
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        let writer = s.spawn(|| shared.inserts(root.as_ref(), &more, &more));
+        for reader in readers {
+            reader.join().expect("reader panicked")?;
+        }
+        let grown = writer.join().expect("writer panicked")?;
+        assert_eq!(shared.get(grown.as_ref(), &more[0])?, Some(more[0]));
+        Ok(())
+    })
+}
+
+macro_rules! impl_shared_test {
+    ($d:expr, $db:ident) => {
+        paste::item_with_macros! {
+            #[test]
+            fn [<test_ $d _shared_reads_from_threads>]() -> Result<()> {
+                let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+                let _g = scopeguard::guard((), |_| {
+                    if fs::metadata(&dbname).is_ok() {
+                        fs::remove_dir_all(&dbname).unwrap()
+                    }
+                });
+                let keys = random_hashes(500);
+                let leaves = random_hashes(500);
+                let tree = Monotree::<$db, Blake3>::new(&dbname);
+                insert_keys_then_read_from_threads(tree, &keys, &leaves)
+            }
+        }
+    };
+}
+
+impl_shared_test!("hashmap", MemoryDB);
+#[cfg(feature = "db_rocksdb")]
+impl_shared_test!("rocksdb", RocksDB);
+#[cfg(feature = "db_sled")]
+impl_shared_test!("sled", Sled);
+#[cfg(feature = "db_redb")]
+impl_shared_test!("redb", Redb);
+#[cfg(feature = "db_lmdb")]
+impl_shared_test!("lmdb", Lmdb);
+
+#[test]
+fn test_shared_reads_during_write() -> Result<()> {
+    let keys = random_hashes(100);
+    let shared = SharedMonotree::new(Monotree::<MemoryDB, Blake3>::new("shared"));
+    let root = shared.inserts(None, &keys, &keys)?;
+
+    // a read opening a tree of its own does not wait for the write holding the tree
+    let found = shared.write(|_| {
+        thread::scope(|s| {
+            s.spawn(|| shared.get(root.as_ref(), &keys[0]))
+                .join()
+                .expect("reader panicked")
+        })
+    })?;
+    assert_eq!(found, Some(keys[0]));
+    Ok(())
+}
+
+fn insert_keys_then_flush<D: Database, H: Hasher>(
+    mut tree: Monotree<D, H>,
+    keys: &[Hash],
+    leaves: &[Hash],
+) -> Result<()> {
+    let root = tree.inserts(None, keys, leaves)?;
+    tree.set_headroot(root.as_ref())?;
+    tree.flush()?;
+    assert_eq!(tree.get_headroot()?, root);
+    for (k, v) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(tree.get(root.as_ref(), k)?, Some(*v));
+    }
+    Ok(())
+}
+
+macro_rules! impl_flush_test {
+    ($d:expr, $db:ident) => {
+        paste::item_with_macros! {
+            #[test]
+            fn [<test_ $d _flush>]() -> Result<()> {
+                let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+                let _g = scopeguard::guard((), |_| {
+                    if fs::metadata(&dbname).is_ok() {
+                        fs::remove_dir_all(&dbname).unwrap()
+                    }
+                });
+                let keys = random_hashes(500);
+                let leaves = random_hashes(500);
+                let tree = Monotree::<$db, Blake3>::new(&dbname);
+                insert_keys_then_flush(tree, &keys, &leaves)
+            }
+        }
+    };
+}
+
+impl_flush_test!("hashmap", MemoryDB);
+#[cfg(feature = "db_rocksdb")]
+impl_flush_test!("rocksdb", RocksDB);
+#[cfg(feature = "db_sled")]
+impl_flush_test!("sled", Sled);
+#[cfg(feature = "db_redb")]
+impl_flush_test!("redb", Redb);
+#[cfg(feature = "db_lmdb")]
+impl_flush_test!("lmdb", Lmdb);
+
+fn insert_keys_then_clear<D: Database, H: Hasher>(
+    mut tree: Monotree<D, H>,
+    keys: &[Hash],
+    leaves: &[Hash],
+) -> Result<()> {
+    let root = tree.inserts(None, keys, leaves)?;
+    tree.set_headroot(root.as_ref())?;
+    tree.clear_all()?;
+
+    // the previous root no longer resolves, while the tree is usable from scratch
+    assert_eq!(tree.get_headroot()?, None);
+    assert!(!tree.root_exists(&root.unwrap())?);
+    assert!(matches!(
+        tree.get(root.as_ref(), &keys[0]),
+        Err(MonotreeError::NodeNotFound(_))
+    ));
+    assert_eq!(tree.inserts(None, keys, leaves)?, root);
+    for (k, v) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(tree.get(root.as_ref(), k)?, Some(*v));
+    }
+    Ok(())
+}
+
+macro_rules! impl_clear_test {
+    ($d:expr, $db:ident) => {
+        paste::item_with_macros! {
+            #[test]
+            fn [<test_ $d _clear_all>]() -> Result<()> {
+                let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+                let _g = scopeguard::guard((), |_| {
+                    if fs::metadata(&dbname).is_ok() {
+                        fs::remove_dir_all(&dbname).unwrap()
+                    }
+                });
+                let keys = random_hashes(500);
+                let leaves = random_hashes(500);
+                let tree = Monotree::<$db, Blake3>::new(&dbname);
+                insert_keys_then_clear(tree, &keys, &leaves)
+            }
+        }
+    };
+}
+
+impl_clear_test!("hashmap", MemoryDB);
+#[cfg(feature = "db_rocksdb")]
+impl_clear_test!("rocksdb", RocksDB);
+#[cfg(feature = "db_sled")]
+impl_clear_test!("sled", Sled);
+#[cfg(feature = "db_redb")]
+impl_clear_test!("redb", Redb);
+#[cfg(feature = "db_lmdb")]
+impl_clear_test!("lmdb", Lmdb);
+
+fn fail_in_batch_then_clear<D: Database, H: Hasher>(
+    mut tree: Monotree<D, H>,
+    keys: &[Hash],
+    leaves: &[Hash],
+) -> Result<()> {
+    let root = tree.inserts(None, keys, leaves)?;
+    tree.set_headroot(root.as_ref())?;
+
+    // a key of invalid length fails the batch in the middle, discarding its writes
+    let queries: Vec<&[u8]> = vec![&keys[0][..], &keys[1][..1]];
+    assert!(tree.inserts(root.as_ref(), &queries, &leaves[..2]).is_err());
+    tree.clear()?;
+    assert_eq!(tree.get_headroot()?, None);
+    assert_eq!(tree.get(root.as_ref(), &keys[0])?, None);
+
+    // writes after clearing are not held in the batch left open
+    let single = tree.insert(None, &keys[0], &leaves[0])?;
+    let mut other = Monotree::<MemoryDB, H>::new("other");
+    assert_eq!(single, other.insert(None, &keys[0], &leaves[0])?);
+    assert!(tree.root_exists(&single.unwrap())?);
+    assert_eq!(tree.get(single.as_ref(), &keys[0])?, Some(leaves[0]));
+    assert_eq!(tree.get(root.as_ref(), &keys[1])?, None);
+    Ok(())
+}
+
+macro_rules! impl_clear_in_batch_test {
+    ($d:expr, $db:ident) => {
+        paste::item_with_macros! {
+            #[test]
+            fn [<test_ $d _clear_in_batch>]() -> Result<()> {
+                let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+                let _g = scopeguard::guard((), |_| {
+                    if fs::metadata(&dbname).is_ok() {
+                        fs::remove_dir_all(&dbname).unwrap()
+                    }
+                });
+                let keys = random_hashes(100);
+                let leaves = random_hashes(100);
+                let tree = Monotree::<$db, Blake3>::new(&dbname);
+                fail_in_batch_then_clear(tree, &keys, &leaves)
+            }
+        }
+    };
+}
+
+impl_clear_in_batch_test!("hashmap", MemoryDB);
+#[cfg(feature = "db_rocksdb")]
+impl_clear_in_batch_test!("rocksdb", RocksDB);
+#[cfg(feature = "db_sled")]
+impl_clear_in_batch_test!("sled", Sled);
+#[cfg(feature = "db_redb")]
+impl_clear_in_batch_test!("redb", Redb);
+#[cfg(feature = "db_lmdb")]
+impl_clear_in_batch_test!("lmdb", Lmdb);
+
+#[test]
+fn test_self_contained_proof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let leaves = random_hashes(100);
+    let root = tree.inserts(None, &keys, &leaves)?.expect("root");
+
+    let proof = tree
+        .get_merkle_proof(Some(&root), &keys[7])?
+        .expect("proof");
+    let contained = SelfContainedProof::new(root, leaves[7], proof.clone());
+    assert!(verify_self_contained(&hasher, &contained));
+
+    // a proof embedding another leaf or root never verifies
+    let forged = SelfContainedProof {
+        leaf: leaves[8],
+        ..contained.clone()
+    };
+    assert!(!verify_self_contained(&hasher, &forged));
+    let forged = SelfContainedProof {
+        root: random_hash(),
+        ..contained.clone()
+    };
+    assert!(!verify_self_contained(&hasher, &forged));
+
+    assert_eq!(Proof::from(contained), proof);
+    Ok(())
+}
+
+#[test]
+fn test_verify_proof_with() -> Result<()> {
+    let mut tree = Monotree::<MemoryDB, Blake2b>::new("monotree");
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    for key in keys.iter() {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof_with::<Blake2b>(
+            root.as_ref(),
+            key,
+            proof.as_ref()
+        ));
+        assert_eq!(
+            verify_proof_with::<Blake2b>(root.as_ref(), &keys[0], proof.as_ref()),
+            verify_proof(&Blake2b::new(), root.as_ref(), &keys[0], proof.as_ref())
+        );
+        assert!(!verify_proof_with::<Blake3>(
+            root.as_ref(),
+            key,
+            proof.as_ref()
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify_proofs() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let mut proofs: Vec<Proof> = keys
+        .iter()
+        .map(|key| tree.get_merkle_proof(root.as_ref(), key))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .map(|proof| proof.expect("proof"))
+        .collect();
+    proofs[1][0].1.clear();
+    proofs[2].clear();
+    let mut leaves = keys.clone();
+    leaves[3] = random_hash();
+
+    let items: Vec<(Hash, &Proof)> = leaves.iter().cloned().zip(proofs.iter()).collect();
+    let expected: Vec<bool> = items
+        .iter()
+        .map(|(leaf, proof)| verify_proof(&hasher, root.as_ref(), leaf, Some(proof)))
+        .collect();
+    assert_eq!(verify_proofs(&hasher, root.as_ref(), &items), expected);
+    assert_eq!(expected.iter().filter(|&&ok| !ok).count(), 3);
+    assert!(!expected[1] && !expected[2] && !expected[3]);
+
+    assert_eq!(
+        verify_proofs(&hasher, None, &items),
+        vec![false; items.len()]
+    );
+    assert!(verify_proofs(&hasher, root.as_ref(), &[]).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_compact_proof() -> Result<()> {
+    let hasher = Blake3::new();
+    let mut keys = random_hashes(500);
+    let mut neighbor = keys[0];
+    neighbor[HASH_LEN - 1] ^= 0x01;
+    keys.push(neighbor);
+    let leaf = random_hash();
+    let leaves = vec![leaf; keys.len()];
+
+    for &compact in [false, true].iter() {
+        let mut tree = Monotree::default().with_compact_node_encoding(compact);
+        let root = tree.inserts(None, &keys, &leaves)?;
+        for key in keys.iter() {
+            let proof = tree
+                .get_compact_proof(root.as_ref(), key)?
+                .expect("compact proof");
+            assert!(verify_compact_proof(
+                &hasher,
+                root.as_ref(),
+                key,
+                &leaf,
+                &proof
+            ));
+            assert!(!verify_compact_proof(
+                &hasher,
+                root.as_ref(),
+                key,
+                &random_hash(),
+                &proof
+            ));
+            assert!(!verify_compact_proof(&hasher, None, key, &leaf, &proof));
+
+            let bytes = encode_compact_proof_bytes(&proof);
+            assert_eq!(decode_compact_proof_bytes(&bytes)?, proof);
+            assert!(decode_compact_proof_bytes::<HASH_LEN>(&bytes[..bytes.len() - 1]).is_err());
+            let plain = tree.get_merkle_proof(root.as_ref(), key)?.expect("proof");
+            assert!(bytes.len() < encode_proof_bytes(&plain).len());
+        }
+
+        // a proof of a key never verifies for another key, even of the same leaf
+        let proof = tree
+            .get_compact_proof(root.as_ref(), &keys[0])?
+            .expect("compact proof");
+        assert!(!verify_compact_proof(
+            &hasher,
+            root.as_ref(),
+            &neighbor,
+            &leaf,
+            &proof
+        ));
+        assert!(!verify_compact_proof(
+            &hasher,
+            root.as_ref(),
+            &keys[1],
+            &leaf,
+            &proof
+        ));
+        assert!(!verify_compact_proof(
+            &hasher,
+            root.as_ref(),
+            &keys[0][..1],
+            &leaf,
+            &proof
+        ));
+
+        let mut tampered = proof.clone();
+        tampered.steps[0].len += 1;
+        assert!(!verify_compact_proof(
+            &hasher,
+            root.as_ref(),
+            &keys[0],
+            &leaf,
+            &tampered
+        ));
+        let mut tampered = proof.clone();
+        tampered.steps.pop();
+        assert!(!verify_compact_proof(
+            &hasher,
+            root.as_ref(),
+            &keys[0],
+            &leaf,
+            &tampered
+        ));
+        let mut tampered = proof;
+        tampered.steps[0].compact = !compact;
+        assert!(!verify_compact_proof(
+            &hasher,
+            root.as_ref(),
+            &keys[0],
+            &leaf,
+            &tampered
+        ));
+
+        assert_eq!(tree.get_compact_proof(root.as_ref(), &random_hash())?, None);
+        assert_eq!(tree.get_compact_proof(None, &keys[0])?, None);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compact_proof_size() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(10_000);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let (mut plain, mut compact) = (0, 0);
+    for key in keys.iter().take(1000) {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?.expect("proof");
+        plain += encode_proof_bytes(&proof).len();
+        let proof = tree
+            .get_compact_proof(root.as_ref(), key)?
+            .expect("compact proof");
+        compact += encode_compact_proof_bytes(&proof).len();
+    }
+    // compact proofs are smaller by about 20%, refer to `examples/proof_size.rs` for 1M entries
+    assert!(compact * 100 < plain * 85);
+    Ok(())
+}
+
+#[test]
+fn test_keyed_proof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let mut keys = random_hashes(100);
+    let mut neighbor = keys[0];
+    neighbor[HASH_LEN - 1] ^= 0x01;
+    keys.push(neighbor);
+    let leaf = random_hash();
+    let leaves = vec![leaf; keys.len()];
+    let root = tree.inserts(None, &keys, &leaves)?;
+
+    for key in keys.iter() {
+        let keyed = tree.get_keyed_proof(root.as_ref(), key)?.expect("proof");
+        assert_eq!((keyed.key, keyed.leaf), (*key, leaf));
+        assert!(verify_keyed_proof(&hasher, root.as_ref(), &keyed));
+    }
+    assert_eq!(tree.get_keyed_proof(root.as_ref(), &random_hash())?, None);
+
+    // a proof of key A verifies with the leaf alone, but never as the one of key B
+    let keyed = tree.get_keyed_proof(root.as_ref(), &keys[0])?.unwrap();
+    for other in keys[1..].iter() {
+        let forged = KeyedProof {
+            key: *other,
+            ..keyed.clone()
+        };
+        assert!(verify_proof(
+            &hasher,
+            root.as_ref(),
+            &leaf,
+            Some(&forged.proof)
+        ));
+        assert!(!verify_keyed_proof(&hasher, root.as_ref(), &forged));
+    }
+    let forged = KeyedProof {
+        leaf: random_hash(),
+        ..keyed.clone()
+    };
+    assert!(!verify_keyed_proof(&hasher, root.as_ref(), &forged));
+    assert!(!verify_keyed_proof(&hasher, Some(&random_hash()), &keyed));
+    assert!(!verify_keyed_proof(&hasher, None, &keyed));
+    Ok(())
+}
+
+#[test]
+fn test_inserts_with_proofs() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let (root, proofs) = tree.inserts_with_proofs(None, &keys, &leaves)?;
+    assert_eq!(proofs.len(), keys.len());
+    for (leaf, proof) in leaves.iter().zip(proofs.iter()) {
+        assert!(verify_proof(&hasher, root.as_ref(), leaf, Some(proof)));
+    }
+
+    // the same as the proofs generated one by one
+    for (key, proof) in keys.iter().zip(proofs) {
+        assert_eq!(tree.get_merkle_proof(root.as_ref(), key)?, Some(proof));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_keys_of_wrong_length() -> Result<()> {
+    let mut tree = Monotree::default();
+    let (key, leaf) = (random_hash(), random_hash());
+    let root = tree.insert(None, &key[..], &leaf)?;
+    assert_eq!(tree.get(root.as_ref(), &key[..])?, Some(leaf));
+
+    let short = random_bytes(HASH_LEN - 1);
+    let long = random_bytes(HASH_LEN + 1);
+    assert!(tree.insert(root.as_ref(), &short, &leaf).is_err());
+    assert!(tree.get(root.as_ref(), &long).is_err());
+    assert!(tree.inserts(root.as_ref(), &[&short[..]], &[leaf]).is_err());
+    assert!(tree.gets(root.as_ref(), &[&long[..]]).is_err());
+    assert!(tree.remove(root.as_ref(), &short).is_err());
+    assert!(tree.get_merkle_proof(root.as_ref(), &long).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_apply_batch_of_mixed_ops() -> Result<()> {
+    let mut tree = Monotree::default();
+    let mut keys = random_hashes(500);
+    keys.sort();
+    let leaves = random_hashes(500);
+    let root = tree.inserts(None, &keys[..300], &leaves[..300])?;
+
+    // interleave removals, updates and insertions of new keys
+    let ops: Vec<Op> = keys
+        .iter()
+        .zip(leaves.iter())
+        .enumerate()
+        .map(|(i, (key, leaf))| match i {
+            i if i < 300 && i % 3 == 0 => Op::Remove(*key),
+            i if i < 300 => Op::Insert(*key, random_hash()),
+            _ => Op::Insert(*key, *leaf),
+        })
+        .collect();
+    let batched = tree.apply_batch(root.as_ref(), &ops)?;
+
+    let mut sequential = root;
+    for op in ops.iter() {
+        sequential = match op {
+            Op::Insert(key, leaf) => tree.insert(sequential.as_ref(), key, leaf)?,
+            Op::Remove(key) => tree.remove(sequential.as_ref(), key)?,
+        };
+    }
+    assert_eq!(batched, sequential);
+
+    for op in ops.iter() {
+        match op {
+            Op::Insert(key, leaf) => assert_eq!(tree.get(batched.as_ref(), key)?, Some(*leaf)),
+            Op::Remove(key) => assert_eq!(tree.get(batched.as_ref(), key)?, None),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_compact_node_encoding() -> Result<()> {
+    let hasher = Blake3::new();
+    let mut plain = Monotree::default();
+    let mut compact = Monotree::default().with_compact_node_encoding(true);
+
+    // keys sharing a long common prefix
+    let prefix = random_bytes(16);
+    let keys: Vec<Hash> = (0..500)
+        .map(|_| slice_to_hash(&[&prefix[..], &random_bytes(16)].concat()))
+        .collect();
+    let leaves = random_hashes(500);
+    let root_plain = plain.inserts(None, &keys, &leaves)?;
+    let root_compact = compact.inserts(None, &keys, &leaves)?;
+    assert_ne!(root_plain, root_compact);
+
+    let size = |proof: &Proof| proof.iter().map(|(_, cut)| cut.len()).sum::<usize>();
+    let (mut size_plain, mut size_compact) = (0, 0);
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(compact.get(root_compact.as_ref(), key)?, Some(*leaf));
+        let proof = compact.get_merkle_proof(root_compact.as_ref(), key)?;
+        assert!(verify_proof(
+            &hasher,
+            root_compact.as_ref(),
+            leaf,
+            proof.as_ref()
+        ));
+        size_compact += size(&proof.expect("proof"));
+        size_plain += size(
+            &plain
+                .get_merkle_proof(root_plain.as_ref(), key)?
+                .expect("proof"),
+        );
+    }
+    assert!(size_compact < size_plain);
+
+    // removal works on compact nodes as well
+    let root = compact.removes(root_compact.as_ref(), &keys)?;
+    assert_eq!(root, None);
+    Ok(())
+}
+
+#[test]
+fn test_apply_batch_regardless_of_ops_order() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let root = tree.inserts(None, &keys[..250], &leaves[..250])?;
+
+    let mut ops: Vec<Op> = keys
+        .iter()
+        .zip(leaves.iter())
+        .enumerate()
+        .map(|(i, (key, leaf))| match i {
+            i if i < 250 && i % 2 == 0 => Op::Remove(*key),
+            _ => Op::Insert(*key, *leaf),
+        })
+        .collect();
+    let expected = tree.apply_batch(root.as_ref(), &ops)?;
+    for _ in 0..3 {
+        shuffle(&mut ops);
+        assert_eq!(tree.apply_batch(root.as_ref(), &ops)?, expected);
+        assert_eq!(tree.apply(root.as_ref(), &ops)?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_proof_bytes_roundtrip() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    for key in keys.iter() {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?.expect("proof");
+        let bytes = encode_proof_bytes(&proof);
+        assert_eq!(decode_proof_bytes(&bytes)?, proof);
+
+        // truncated bytes never decode, even at the boundary of steps
+        for i in 0..bytes.len() {
+            assert!(decode_proof_bytes(&bytes[..i]).is_err());
+        }
+        assert!(decode_proof_bytes(&[&bytes[..], &[0x00]].concat()).is_err());
+    }
+    assert!(decode_proof_bytes(&[0x01, 0x02, 0x00]).is_err());
+    assert_eq!(
+        decode_proof_bytes(&encode_proof_bytes(&Vec::new()))?,
+        Vec::new()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_truncated_node_bytes() -> Result<()> {
+    let (lh, rh) = (random_hash(), random_hash());
+    let (lk, rk) = ([0x00; HASH_LEN], [0xff; HASH_LEN]);
+    let lc = Some(Unit {
+        hash: &lh,
+        bits: Bits::new(&lk),
+    });
+    let rc = Some(Unit {
+        hash: &rh,
+        bits: Bits::new(&rk),
+    });
+    let nodes: [Node; 2] = [Node::new(lc.clone(), None), Node::new(lc, rc)];
+    for node in nodes.iter() {
+        for bytes in [node.to_bytes()?, node.to_compact_bytes()?].iter() {
+            assert!(Node::<HASH_LEN>::from_bytes(bytes).is_ok());
+
+            // must not panic whatever truncated
+            for l in 0..bytes.len() {
+                let _ = Node::<HASH_LEN>::from_bytes(&bytes[..l]);
+            }
+
+            // truncated while the indicator remains
+            let l = bytes.len();
+            let truncated = [&bytes[..l - 2], &bytes[l - 1..]].concat();
+            assert!(Node::<HASH_LEN>::from_bytes(&truncated).is_err());
+        }
+    }
+
+    // a hard node whose right cell is shorter than a hash, where both cells are of equal size
+    let bytes = nodes[1].to_bytes()?;
+    let half = (bytes.len() - 1) / 2;
+    for tail in 0..HASH_LEN {
+        let truncated = [&bytes[..half + tail], &[0x01]].concat();
+        assert!(matches!(
+            Node::<HASH_LEN>::from_bytes(&truncated),
+            Err(MonotreeError::Corruption(_))
+        ));
+    }
+    assert!(Node::<HASH_LEN>::from_bytes(&[0x00; 3]).is_err());
+    assert!(Node::<HASH_LEN>::from_bytes(&[0x01]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_root_regardless_of_insertion_order() -> Result<()> {
+    let keys = random_hashes(1000);
+    let leaves = random_hashes(1000);
+
+    let mut tree = Monotree::default();
+    let mut forward = None;
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        forward = tree.insert(forward.as_ref(), key, leaf)?;
+    }
+    let mut tree = Monotree::default();
+    let mut reverse = None;
+    for (key, leaf) in keys.iter().zip(leaves.iter()).rev() {
+        reverse = tree.insert(reverse.as_ref(), key, leaf)?;
+    }
+    assert_eq!(forward, reverse);
+    Ok(())
+}
+
+#[test]
+fn test_root_regardless_of_insertion_order_with_one_bit_divergence() -> Result<()> {
+    let base = random_hash();
+    for i in 0..HASH_LEN * 8 {
+        let mut other = base;
+        other[i / 8] ^= 0x80 >> (i % 8);
+        let mut tree = Monotree::default();
+        let forward = tree.insert(None, &base, &base)?;
+        let forward = tree.insert(forward.as_ref(), &other, &other)?;
+        let reverse = tree.insert(None, &other, &other)?;
+        let reverse = tree.insert(reverse.as_ref(), &base, &base)?;
+        assert_eq!(forward, reverse);
+        assert_eq!(tree.inserts(None, &[base, other], &[base, other])?, forward);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_root_pinned() -> Result<()> {
+    // the root of fixed entries, which changes only with the format of nodes
+    let hasher = Blake3::new();
+    let keys: Vec<Hash> = (0..100u32)
+        .map(|i| hasher.digest(&i.to_be_bytes()))
+        .collect();
+    let leaves: Vec<Hash> = keys.iter().map(|key| hasher.digest(key)).collect();
+    let mut tree = Monotree::default();
+    let root = tree.inserts(None, &keys, &leaves)?;
+    #[cfg(not(feature = "bitslen_u32"))]
+    let pinned = "a14a590633e380b7e93d3f04c5985bf3ab3f42a56357048f616ea1c812247b7a";
+    #[cfg(feature = "bitslen_u32")]
+    let pinned = "5f29cb505852a4a0d43acb4f43e483d7f7081f2f1ab6ca5acc48f4b83ab3c046";
+    assert_eq!(hex!(root.unwrap()), pinned);
+    Ok(())
+}
+
+#[test]
+fn test_root_after_removal_as_if_never_inserted() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(1000);
+    let leaves = random_hashes(1000);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    let root = tree.removes(root.as_ref(), &keys[..400])?;
+    assert_eq!(root, tree.inserts(None, &keys[400..], &leaves[400..])?);
+    Ok(())
+}
+
+#[test]
+fn test_dangling_root() -> Result<()> {
+    let mut tree = Monotree::default();
+    let (key, leaf) = (random_hash(), random_hash());
+    let dangling = random_hash();
+    assert!(tree.get(Some(&dangling), &key).is_err());
+    assert!(tree.insert(Some(&dangling), &key, &leaf).is_err());
+    assert!(tree.remove(Some(&dangling), &key).is_err());
+    assert!(tree.get_merkle_proof(Some(&dangling), &key).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_keys_of_hash_or_slice() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(10);
+    let root = tree.inserts(None, &keys, &keys)?;
+
+    // a `&Hash` is passed as it is, the same as to `insert()` and `get()`
+    assert!(tree.get_merkle_proof(root.as_ref(), &keys[0])?.is_some());
+    let absent = random_hash();
+    tree.get_non_inclusion_proof(root.as_ref(), &absent)?;
+    let removed = tree.remove(root.as_ref(), &keys[0])?;
+    assert_eq!(tree.removes(root.as_ref(), &keys[..1])?, removed);
+
+    // while a slice of a wrong length fails rather than indexing out of bounds
+    let short = &keys[1][..HASH_LEN - 1];
+    let long = [&keys[1][..], &[0x00]].concat();
+    for key in [short, &long[..]].iter() {
+        let invalid = MonotreeError::KeyLength {
+            expected: HASH_LEN,
+            got: key.len(),
+        };
+        assert_eq!(tree.remove(root.as_ref(), key), Err(invalid.clone()));
+        assert_eq!(tree.removes(root.as_ref(), &[key]), Err(invalid.clone()));
+        assert_eq!(
+            tree.get_merkle_proof(root.as_ref(), key),
+            Err(invalid.clone())
+        );
+        assert_eq!(
+            tree.get_non_inclusion_proof(root.as_ref(), key),
+            Err(invalid)
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_error_kinds() -> Result<()> {
+    let mut tree = Monotree::default();
+    let (key, leaf) = (random_hash(), random_hash());
+    let dangling = random_hash();
+    let not_found = MonotreeError::NodeNotFound(dangling.to_vec());
+    assert_eq!(tree.get(Some(&dangling), &key), Err(not_found.clone()));
+    assert_eq!(
+        tree.insert(Some(&dangling), &key, &leaf),
+        Err(not_found.clone())
+    );
+    assert_eq!(tree.remove(Some(&dangling), &key), Err(not_found.clone()));
+    assert!(not_found
+        .to_string()
+        .starts_with("dangling node reference: "));
+
+    // a key of a wrong length is rejected rather than panicking
+    assert_eq!(
+        tree.insert(None, &key[..20], &leaf),
+        Err(MonotreeError::KeyLength {
+            expected: HASH_LEN,
+            got: 20
+        })
+    );
+
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let proof = tree.get_merkle_proof(root.as_ref(), &keys[0])?.unwrap();
+    let bytes = encode_proof_bytes(&proof);
+    for n in 0..bytes.len() {
+        assert_eq!(
+            decode_proof_bytes(&bytes[..n]),
+            Err(MonotreeError::MalformedProof)
+        );
+    }
+    assert!(matches!(
+        Node::<HASH_LEN>::from_bytes(&[0xff]),
+        Err(MonotreeError::Corruption(_))
+    ));
+
+    // errors of messages are still converted from strings
+    let err: MonotreeError = String::from("failed").into();
+    assert_eq!(err, Errors::new("failed"));
+    Ok(())
+}
+
+#[test]
+fn test_clone_tree() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys[..50], &keys[..50])?;
+
+    // a fork answers the same as the original for the same root
+    let mut fork = tree.clone();
+    for key in keys[..50].iter() {
+        assert_eq!(fork.get(root.as_ref(), key)?, tree.get(root.as_ref(), key)?);
+        assert_eq!(
+            fork.get_merkle_proof(root.as_ref(), key)?,
+            tree.get_merkle_proof(root.as_ref(), key)?
+        );
+    }
+
+    // while changes to the fork never reach the original
+    let forked = fork.inserts(root.as_ref(), &keys[50..], &keys[50..])?;
+    assert_eq!(fork.len(forked.as_ref())?, 100);
+    assert!(tree.get(forked.as_ref(), &keys[0]).is_err());
+    let other = tree.inserts(root.as_ref(), &keys[50..], &keys[50..])?;
+    assert_eq!(other, forked);
+    Ok(())
+}
+
+#[test]
+fn test_root_exists() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(10);
+    let root = tree.inserts(None, &keys, &keys)?.unwrap();
+    assert!(tree.root_exists(&root)?);
+    let unknown = random_hash();
+    assert!(!tree.root_exists(&unknown)?);
+    assert!(tree.get(Some(&unknown), &keys[0]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_dump_and_load() -> Result<()> {
+    let mut tree = Monotree::default().with_auto_head(true);
+    let keys = random_hashes(300);
+    let root = tree.inserts(None, &keys[..200], &keys[..200])?;
+    tree.push_root(root.as_ref().unwrap())?;
+    let root = tree.insert_value(root.as_ref(), &keys[200], b"value")?;
+    let mut dump: Vec<u8> = Vec::new();
+    tree.dump(&mut dump)?;
+
+    // a tree loaded from the dump answers the same as the original
+    let mut other = Monotree::default();
+    other.load(&dump[..])?;
+    assert_eq!(other.get_headroot()?, root);
+    assert_eq!(other.roots_history()?, tree.roots_history()?);
+    assert_eq!(
+        other.get_value(root.as_ref(), &keys[200])?,
+        Some(b"value".to_vec())
+    );
+    for key in keys.iter() {
+        assert_eq!(
+            other.get(root.as_ref(), key)?,
+            tree.get(root.as_ref(), key)?
+        );
+        assert_eq!(
+            other.get_merkle_proof(root.as_ref(), key)?,
+            tree.get_merkle_proof(root.as_ref(), key)?
+        );
+    }
+    let mut again: Vec<u8> = Vec::new();
+    other.dump(&mut again)?;
+    assert_eq!(again, dump);
+
+    // a dump truncated or of another version is rejected with nothing written
+    let mut other = Monotree::default();
+    for n in (0..dump.len()).step_by(97).chain(Some(dump.len() - 1)) {
+        assert!(other.load(&dump[..n]).is_err());
+    }
+    let mut version = dump.clone();
+    version[b"monotree::dump".len()] = 0x02;
+    assert!(other.load(&version[..]).is_err());
+    assert_eq!(other.get_headroot()?, None);
+    assert!(!other.root_exists(root.as_ref().unwrap())?);
+    Ok(())
+}
+
+#[test]
+fn test_verify_on_read() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let mut db = MemoryDB::new("copy");
+    apply_update(&hasher, &mut db, &tree.update_proof(None, root.as_ref())?)?;
+
+    let mut tree = Monotree::<MemoryDB, Blake3>::with_db(db.clone()).with_verify_on_read(true);
+    assert_eq!(
+        tree.gets(root.as_ref(), &keys)?,
+        keys.iter().cloned().map(Some).collect::<Vec<_>>()
+    );
+
+    // flip a bit of the root node, still parsed as a node but no longer the one of its hash
+    let root_hash = root.expect("root");
+    let mut bytes = Database::get(&mut db, &root_hash)?.expect("root node");
+    bytes[0] ^= 0x01;
+    Database::put(&mut db, &root_hash, bytes)?;
+
+    let mut tree = Monotree::<MemoryDB, Blake3>::with_db(db.clone()).with_verify_on_read(true);
+    assert!(matches!(
+        tree.get(root.as_ref(), &keys[0]),
+        Err(MonotreeError::Corruption(_))
+    ));
+    assert!(matches!(
+        tree.gets(root.as_ref(), &keys),
+        Err(MonotreeError::Corruption(_))
+    ));
+    let mut tree = Monotree::<MemoryDB, Blake3>::with_db(db);
+    assert!(!matches!(
+        tree.get(root.as_ref(), &keys[0]),
+        Err(MonotreeError::Corruption(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_missing_child_node() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let old = tree.inserts(None, &keys[..99], &keys[..99])?;
+    let new = tree.insert(old.as_ref(), &keys[99], &keys[99])?;
+
+    // a store holding only the nodes on the path of the last key inserted
+    let mut partial = MemoryDB::new("partial");
+    apply_update(
+        &hasher,
+        &mut partial,
+        &tree.update_proof(old.as_ref(), new.as_ref())?,
+    )?;
+    let mut stale = Monotree::<MemoryDB, Blake3>::with_db(partial);
+    assert_eq!(stale.get(new.as_ref(), &keys[99])?, Some(keys[99]));
+
+    // other keys are found on the path, or fail with the missing node rather than panicking
+    let mut missing = 0;
+    for key in keys[..99].iter() {
+        match stale.get(new.as_ref(), key) {
+            Ok(leaf) => assert_eq!(leaf, Some(*key)),
+            Err(MonotreeError::NodeNotFound(_)) => missing += 1,
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+        for result in [
+            stale.get_merkle_proof(new.as_ref(), key).map(|_| ()),
+            stale.remove(new.as_ref(), key).map(|_| ()),
+        ]
+        .iter()
+        {
+            assert!(matches!(
+                result,
+                Ok(()) | Err(MonotreeError::NodeNotFound(_))
+            ));
+        }
+    }
+    assert!(missing > 0);
+    Ok(())
+}
+
+#[test]
+fn test_last_op_writes() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(100);
+    let leaves = random_hashes(100);
+
+    let root = tree.insert(None, &keys[0], &leaves[0])?;
+    assert_eq!(tree.last_op_writes(), 1);
+    let root = tree.remove(root.as_ref(), &keys[0])?;
+    assert_eq!(tree.last_op_writes(), 0);
+
+    // at least every node of the resulting tree was written in the batch
+    let root = tree.inserts(root.as_ref(), &keys, &leaves)?;
+    assert!(tree.last_op_writes() >= keys.len() - 1);
+
+    // only nodes on the path are written
+    let _ = tree.insert(root.as_ref(), &keys[0], &random_hash())?;
+    assert!(tree.last_op_writes() < keys.len());
+
+    // nothing is written when re-inserting the existing leaves
+    assert_eq!(tree.insert(root.as_ref(), &keys[0], &leaves[0])?, root);
+    assert_eq!(tree.last_op_writes(), 0);
+    assert_eq!(tree.inserts(root.as_ref(), &keys, &leaves)?, root);
+    assert_eq!(tree.last_op_writes(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_insert_returning_old() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(100);
+    let leaves = random_hashes(3);
+    let root = tree.inserts(None, &keys[1..], &keys[1..])?;
+
+    let (root, old) = tree.insert_returning_old(root.as_ref(), &keys[0], &leaves[0])?;
+    assert_eq!(old, None);
+    let (root, old) = tree.insert_returning_old(root.as_ref(), &keys[0], &leaves[1])?;
+    assert_eq!(old, Some(leaves[0]));
+    let (root, old) = tree.insert_returning_old(root.as_ref(), &keys[0], &leaves[2])?;
+    assert_eq!(old, Some(leaves[1]));
+    assert_eq!(tree.get(root.as_ref(), &keys[0])?, Some(leaves[2]));
+
+    let (new, old) = tree.insert_returning_old(root.as_ref(), &keys[1], &leaves[0])?;
+    assert_eq!(old, Some(keys[1]));
+    assert_eq!(new, tree.insert(root.as_ref(), &keys[1], &leaves[0])?);
+    assert_eq!(
+        tree.insert_returning_old(None, &keys[0], &leaves[0])?.1,
+        None
+    );
+    Ok(())
+}
+
+#[test]
+fn test_reinsert_unchanged() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(1000);
+    let root = tree.inserts(None, &keys, &keys)?;
+    for key in keys.iter() {
+        assert_eq!(tree.insert(root.as_ref(), key, key)?, root);
+        assert_eq!(tree.last_op_writes(), 0);
+    }
+
+    // the same key with a different leaf is still updated
+    let leaf = random_hash();
+    let updated = tree.insert(root.as_ref(), &keys[0], &leaf)?;
+    assert_ne!(updated, root);
+    assert!(tree.last_op_writes() > 0);
+    assert_eq!(tree.get(updated.as_ref(), &keys[0])?, Some(leaf));
+    assert_eq!(tree.insert(updated.as_ref(), &keys[0], &keys[0])?, root);
+    Ok(())
+}
+
+#[test]
+fn test_contains() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(200);
+    let leaves = random_hashes(200);
+    assert!(!tree.contains(None, &keys[0])?);
+    let root = tree.inserts(None, &keys[..100], &leaves[..100])?;
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(tree.contains(root.as_ref(), key)?, i < 100);
+    }
+
+    // the same as looking up leaves, as keys come and go
+    let root = tree.removes(root.as_ref(), &keys[..30])?;
+    let root = tree.inserts(root.as_ref(), &keys[150..], &leaves[150..])?;
+    for key in keys.iter().chain(random_hashes(100).iter()) {
+        assert_eq!(
+            tree.contains(root.as_ref(), key)?,
+            tree.get(root.as_ref(), key)?.is_some()
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_non_inclusion_proof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let key = [0u8; HASH_LEN];
+    let mut sibling = key;
+    sibling[HASH_LEN - 1] = 0x03;
+
+    // empty tree
+    let proof = tree.get_non_inclusion_proof(None, &key)?;
+    assert!(verify_non_inclusion(&hasher, None, &key, &proof));
+
+    let root = tree.inserts(None, &[key, sibling], &[key, sibling])?;
+    let root = root.as_ref();
+
+    // existing keys have no proof of non-inclusion
+    assert!(tree.get_non_inclusion_proof(root, &key).is_err());
+    assert!(tree.get_non_inclusion_proof(root, &sibling).is_err());
+
+    // falling off the soft node at the root
+    let mut absent = key;
+    absent[0] = 0x80;
+    let proof = tree.get_non_inclusion_proof(root, &absent)?;
+    assert_eq!(proof.nodes.len(), 1);
+    assert!(verify_non_inclusion(&hasher, root, &absent, &proof));
+    assert!(!verify_non_inclusion(&hasher, root, &key, &proof));
+
+    // diverging in the middle of the path
+    let mut absent = key;
+    absent[HASH_LEN / 2] = 0x01;
+    let proof = tree.get_non_inclusion_proof(root, &absent)?;
+    assert!(verify_non_inclusion(&hasher, root, &absent, &proof));
+    assert!(!verify_non_inclusion(&hasher, root, &sibling, &proof));
+    assert!(!verify_non_inclusion(&hasher, None, &absent, &proof));
+
+    // reaching a cell of a leaf of another key
+    let mut absent = key;
+    absent[HASH_LEN - 1] = 0x01;
+    let proof = tree.get_non_inclusion_proof(root, &absent)?;
+    assert_eq!(proof.nodes.len(), 2);
+    assert!(verify_non_inclusion(&hasher, root, &absent, &proof));
+    assert!(!verify_non_inclusion(&hasher, root, &key, &proof));
+
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys[..50], &keys[..50])?;
+    for key in keys[..50].iter() {
+        assert!(tree.get_non_inclusion_proof(root.as_ref(), key).is_err());
+    }
+    for key in keys[50..].iter() {
+        let proof = tree.get_non_inclusion_proof(root.as_ref(), key)?;
+        assert!(verify_non_inclusion(&hasher, root.as_ref(), key, &proof));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_domain_separation() -> Result<()> {
+    let mut plain = Monotree::default();
+    let mut tree = Monotree::<MemoryDB, Blake3>::new("domain").with_domain_separation(true);
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let values = random_hashes(100);
+    let leaves: Vec<Hash> = values.iter().map(|v| tree.hash_leaf(v)).collect();
+    assert_ne!(leaves[0], plain.hash_leaf(&values[0]));
+
+    let root = tree.inserts(None, &keys, &leaves)?;
+    assert_ne!(root, plain.inserts(None, &keys, &leaves)?);
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(tree.get(root.as_ref(), key)?, Some(*leaf));
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_domain_separated_proof(
+            &hasher,
+            root.as_ref(),
+            leaf,
+            proof.as_ref()
+        ));
+        assert!(!verify_proof(&hasher, root.as_ref(), leaf, proof.as_ref()));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_get_with_proof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(200);
+    let leaves = random_hashes(200);
+    assert_eq!(tree.get_with_proof(None, &keys[0])?, None);
+    let root = tree.inserts(None, &keys[..100], &leaves[..100])?;
+    for (i, key) in keys.iter().enumerate() {
+        match tree.get_with_proof(root.as_ref(), key)? {
+            Some((leaf, proof)) => {
+                assert!(i < 100);
+                assert_eq!(leaf, leaves[i]);
+                assert_eq!(
+                    Some(&proof),
+                    tree.get_merkle_proof(root.as_ref(), key)?.as_ref()
+                );
+                assert!(verify_proof(&hasher, root.as_ref(), &leaf, Some(&proof)));
+            }
+            None => assert!(i >= 100),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_insert_and_get_values() -> Result<()> {
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let values: Vec<Vec<u8>> = (0..100).map(|i| random_bytes(i * 7)).collect();
+    for &domain in [false, true].iter() {
+        let mut tree = Monotree::default().with_domain_separation(domain);
+        let mut root = None;
+        for (key, value) in keys.iter().zip(values.iter()) {
+            root = tree.insert_value(root.as_ref(), key, value)?;
+        }
+        for (key, value) in keys.iter().zip(values.iter()) {
+            assert_eq!(tree.get_value(root.as_ref(), key)?.as_ref(), Some(value));
+            let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+            let verify = if domain {
+                verify_domain_separated_value_proof
+            } else {
+                verify_value_proof
+            };
+            assert!(verify(&hasher, root.as_ref(), value, proof.as_ref()));
+            assert!(!verify(&hasher, root.as_ref(), b"forged", proof.as_ref()));
+        }
+        assert_eq!(tree.get_value(root.as_ref(), &random_hash())?, None);
+    }
+    Ok(())
+}
+
+/// A database accepting writes only in batch mode, which are applied on `finish_batch()`.
+struct BatchOnlyDB {
+    db: HashMap<Vec<u8>, Vec<u8>>,
+    batch: Option<HashMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl Database for BatchOnlyDB {
+    fn new(_dbpath: &str) -> Self {
+        BatchOnlyDB {
+            db: HashMap::new(),
+            batch: None,
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.batch.as_ref().and_then(|batch| batch.get(key)) {
+            Some(value) => Ok(value.clone()),
+            None => Ok(self.db.get(key).cloned()),
+        }
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        match self.batch.as_mut() {
+            Some(batch) => {
+                batch.insert(key.to_vec(), Some(value));
+                Ok(())
+            }
+            None => Err(Errors::new("put(): not in batch")),
+        }
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        match self.batch.as_mut() {
+            Some(batch) => {
+                batch.insert(key.to_vec(), None);
+                Ok(())
+            }
+            None => Err(Errors::new("delete(): not in batch")),
+        }
+    }
+
+    fn init_batch(&mut self) -> Result<()> {
+        self.batch = Some(HashMap::new());
+        Ok(())
+    }
+
+    fn finish_batch(&mut self) -> Result<()> {
+        for (key, value) in self.batch.take().into_iter().flatten() {
+            match value {
+                Some(value) => self.db.insert(key, value),
+                None => self.db.remove(&key),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_insert_value_in_batch() -> Result<()> {
+    let keys = random_hashes(100);
+    let mut tree = Monotree::<BatchOnlyDB, Blake3>::new("batch");
+    let mut root = None;
+    for key in keys.iter() {
+        root = tree.insert_value(root.as_ref(), key, &[key.as_ref(), b"value"].concat())?;
+    }
+    assert!(tree.insert(root.as_ref(), &keys[0], &keys[0]).is_err());
+    for key in keys.iter() {
+        assert_eq!(
+            tree.get_value(root.as_ref(), key)?,
+            Some([key.as_ref(), b"value"].concat())
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_iter_under_prefix() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    let mut entries: Vec<(Hash, Hash)> = keys.iter().cloned().zip(leaves.iter().cloned()).collect();
+    entries.sort();
+
+    let all = tree
+        .iter_under(root.as_ref(), &[])
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(all, entries);
+    for prefix in [vec![keys[0][0]], keys[1][..2].to_vec(), keys[2].to_vec()].iter() {
+        let expected: Vec<(Hash, Hash)> = entries
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(tree.leaves_under(root.as_ref(), prefix)?, expected);
+    }
+    assert_eq!(tree.iter_under(root.as_ref(), &[0xff; 4]).count(), 0);
+    assert_eq!(tree.iter_under(None, &[]).count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_multiproof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(300);
+    let leaves = random_hashes(300);
+    let root = tree.inserts(None, &keys, &leaves)?;
+
+    let multiproof = tree.get_merkle_multiproof(root.as_ref(), &keys[..100])?;
+    let entries: Vec<(Hash, Hash)> = keys.iter().cloned().zip(leaves.iter().cloned()).collect();
+    assert!(verify_multiproof(
+        &hasher,
+        root.as_ref(),
+        &entries[..100],
+        &multiproof
+    ));
+
+    // shared nodes are included only once
+    let lens: usize = multiproof.paths.iter().map(|path| path.len()).sum();
+    assert!(multiproof.nodes.len() < lens);
+
+    let mut forged = entries[..100].to_vec();
+    forged[50].1 = random_hash();
+    assert!(!verify_multiproof(
+        &hasher,
+        root.as_ref(),
+        &forged,
+        &multiproof
+    ));
+    assert!(!verify_multiproof(
+        &hasher,
+        root.as_ref(),
+        &entries[100..200],
+        &multiproof
+    ));
+    assert!(!verify_multiproof(
+        &hasher,
+        root.as_ref(),
+        &entries[..99],
+        &multiproof
+    ));
+    assert!(tree
+        .get_merkle_multiproof(root.as_ref(), &[random_hash()])
+        .is_err());
+    Ok(())
+}
+
+/// A hasher of 20-byte digests, truncated from `Blake3`.
+struct Blake3Truncated20;
+impl Hasher<20> for Blake3Truncated20 {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
+        Blake3Truncated20
+    }
+
+    fn digest(&self, bytes: &[u8]) -> Hash<20> {
+        slice_to_array(&Blake3::new().digest(bytes)[..20])
+    }
+}
+
+#[test]
+fn test_tree_of_20_byte_hashes() -> Result<()> {
+    let mut tree = Monotree::<MemoryDB, Blake3Truncated20, 20>::new("hash20");
+    let hasher = Blake3Truncated20::new();
+    let keys: Vec<Hash<20>> = (0..200)
+        .map(|_| slice_to_array(&random_bytes(20)))
+        .collect();
+    let leaves: Vec<Hash<20>> = (0..200)
+        .map(|_| slice_to_array(&random_bytes(20)))
+        .collect();
+
+    let root = tree.inserts(None, &keys, &leaves)?;
+    assert_eq!(
+        tree.gets(root.as_ref(), &keys)?,
+        leaves.iter().cloned().map(Some).collect::<Vec<_>>()
+    );
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof(&hasher, root.as_ref(), leaf, proof.as_ref()));
+    }
+    assert!(tree.get(root.as_ref(), &random_hash()).is_err());
+
+    let root = tree.removes(root.as_ref(), &keys[100..])?;
+    assert_eq!(
+        root,
+        Monotree::<MemoryDB, Blake3Truncated20, 20>::new("hash20").inserts(
+            None,
+            &keys[..100],
+            &leaves[..100]
+        )?
+    );
+    for key in keys[100..].iter() {
+        assert_eq!(tree.get(root.as_ref(), key)?, None);
+    }
+    Ok(())
+}
+
+/// A hasher of N-byte digests, extended from `Blake3` by hashing with a counter.
+struct Blake3Extended<const N: usize>;
+impl<const N: usize> Hasher<N> for Blake3Extended<N> {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
+        Blake3Extended
+    }
+
+    fn digest(&self, bytes: &[u8]) -> Hash<N> {
+        let hasher = Blake3::new();
+        let digest: Vec<u8> = (0..N / HASH_LEN + 1)
+            .flat_map(|i| hasher.digest(&[bytes, &(i as u32).to_be_bytes()].concat()))
+            .take(N)
+            .collect();
+        slice_to_array(&digest)
+    }
+}
+
+fn check_tree_of_long_keys<const N: usize>() -> Result<()> {
+    let mut tree = Monotree::<MemoryDB, Blake3Extended<N>, N>::new("long");
+    let hasher = Blake3Extended::<N>::new();
+    let keys: Vec<Hash<N>> = (0..20).map(|_| slice_to_array(&random_bytes(N))).collect();
+    let leaves: Vec<Hash<N>> = (0..20).map(|_| slice_to_array(&random_bytes(N))).collect();
+
+    let root = tree.insert(None, &keys[0], &leaves[0])?;
+    assert_eq!(tree.get(root.as_ref(), &keys[0])?, Some(leaves[0]));
+    let root = tree.inserts(root.as_ref(), &keys[1..], &leaves[1..])?;
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(tree.get(root.as_ref(), key)?, Some(*leaf));
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof(&hasher, root.as_ref(), leaf, proof.as_ref()));
+    }
+    let root = tree.removes(root.as_ref(), &keys[1..])?;
+    assert_eq!(tree.get(root.as_ref(), &keys[0])?, Some(leaves[0]));
+    Ok(())
+}
+
+#[test]
+fn test_tree_of_2kb_keys() -> Result<()> {
+    check_tree_of_long_keys::<2048>()
+}
+
+#[cfg(feature = "bitslen_u32")]
+#[test]
+fn test_tree_of_keys_beyond_8kb() -> Result<()> {
+    check_tree_of_long_keys::<{ 9 * 1024 }>()
+}
+
+#[test]
+fn test_commit_roots() -> Result<()> {
+    let hasher = Blake3::new();
+    let mut roots: Vec<Option<Hash>> = Vec::new();
+    for i in 0..7 {
+        let mut tree = Monotree::default();
+        let keys = random_hashes(10 * i);
+        roots.push(tree.inserts(None, &keys, &keys)?);
+    }
+    for n in 1..=roots.len() {
+        let commitment = commit_roots(&hasher, &roots[..n]);
+        for (i, root) in roots[..n].iter().enumerate() {
+            let proof = proof_of_root(&hasher, &roots[..n], i).expect("proof");
+            assert!(verify_proof_of_root(
+                &hasher,
+                &commitment,
+                root.as_ref(),
+                i,
+                n,
+                &proof
+            ));
+            assert!(!verify_proof_of_root(
+                &hasher,
+                &commitment,
+                Some(&random_hash()),
+                i,
+                n,
+                &proof
+            ));
+
+            // neither at another index nor among another number of roots
+            for (j, m) in [(i ^ 1, n), (i, n + 1), (i + n, n), (0, 0)].iter() {
+                assert!(!verify_proof_of_root(
+                    &hasher,
+                    &commitment,
+                    root.as_ref(),
+                    *j,
+                    *m,
+                    &proof
+                ));
+            }
+        }
+        assert_eq!(proof_of_root(&hasher, &roots[..n], n), None);
+    }
+    assert_ne!(
+        commit_roots(&hasher, &roots[..2]),
+        commit_roots(&hasher, &[roots[1], roots[0]])
+    );
+
+    // an empty tree is committed apart from a root of zeros
+    assert_eq!(roots[0], None);
+    assert_ne!(
+        commit_roots(&hasher, &roots[..2]),
+        commit_roots(&hasher, &[Some([0x00; HASH_LEN]), roots[1]])
+    );
+
+    // a node left alone is not the same as the one carried up
+    assert_ne!(
+        commit_roots(&hasher, &roots[..3]),
+        commit_roots(&hasher, &[roots[0], roots[1], roots[2], roots[2]])
+    );
+
+    // an interior node does not pass for a root with the rest of a proof
+    let mut interior = [0x00; HASH_LEN];
+    interior.copy_from_slice(&proof_of_root(&hasher, &roots[..4], 2).expect("proof")[1].1);
+    let proof = proof_of_root(&hasher, &roots[..4], 0).expect("proof");
+    let commitment = commit_roots(&hasher, &roots[..4]);
+    let forged = vec![proof[1].clone()];
+    for (i, n) in [(0, 2), (0, 4), (1, 4)].iter() {
+        assert!(!verify_proof_of_root(
+            &hasher,
+            &commitment,
+            Some(&interior),
+            *i,
+            *n,
+            &forged
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_iter() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(1000);
+    let leaves = random_hashes(1000);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    let entries: std::collections::HashSet<(Hash, Hash)> =
+        keys.iter().cloned().zip(leaves.iter().cloned()).collect();
+    let iterated = tree
+        .iter(root.as_ref())
+        .collect::<Result<std::collections::HashSet<_>>>()?;
+    assert_eq!(iterated, entries);
+    assert_eq!(tree.iter(None).count(), 0);
+    Ok(())
+}
+
+#[test]
+fn test_compute_root_from_proof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let leaves = random_hashes(100);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?.expect("proof");
+        assert_eq!(Some(compute_root_from_proof(&hasher, leaf, &proof)?), root);
+    }
+    let malformed: Proof = vec![(true, Vec::new())];
+    assert!(compute_root_from_proof(&hasher, &leaves[0], &malformed).is_err());
+    assert!(!verify_proof(
+        &hasher,
+        root.as_ref(),
+        &leaves[0],
+        Some(&malformed)
+    ));
+    Ok(())
+}
+
+/// A broken hasher digesting everything into zeros.
+struct ZeroHasher;
+impl Hasher for ZeroHasher {
+    type Config = ();
+
+    fn with_config(_: ()) -> Self {
+        ZeroHasher
+    }
+
+    fn digest(&self, _: &[u8]) -> Hash {
+        [0x00; HASH_LEN]
+    }
+}
+
+#[test]
+fn test_collision_check() -> Result<()> {
+    let keys = [[0x00; HASH_LEN], random_hash()];
+    let leaves = random_hashes(2);
+    let mut tree = Monotree::<MemoryDB, ZeroHasher>::new("zero");
+    assert!(tree.inserts(None, &keys, &leaves).is_ok());
+    let mut tree = tree.with_collision_check(true);
+    assert!(tree.inserts(None, &keys, &leaves).is_err());
+    assert!(tree.inserts(None, &keys[1..], &leaves[1..]).is_ok());
+
+    // nothing of the batch failed takes effect, neither the nodes nor the headroot
+    let mut tree = Monotree::<MemoryDB, ZeroHasher>::new("zero")
+        .with_collision_check(true)
+        .with_auto_head(true);
+    assert!(tree.inserts(None, &keys, &leaves).is_err());
+    assert!(!tree.root_exists(&[0x00; HASH_LEN])?);
+    assert_eq!(tree.get_headroot()?, None);
+    let root = tree.inserts(None, &keys[1..], &leaves[1..])?;
+    assert_eq!(tree.get_headroot()?, root);
+
+    let mut tree = Monotree::default().with_collision_check(true);
+    let keys = random_hashes(1000);
+    assert!(tree.inserts(None, &keys, &keys).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_len() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(1000);
+    assert_eq!(tree.len(None)?, 0);
+    let root = tree.inserts(None, &keys, &keys)?;
+    assert_eq!(tree.len(root.as_ref())?, 1000);
+    let root = tree.removes(root.as_ref(), &keys[..400])?;
+    assert_eq!(tree.len(root.as_ref())?, 600);
+    assert_eq!(tree.count(root.as_ref())?, 600);
+    assert_eq!(tree.count(None)?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_get_with_side() -> Result<()> {
+    let mut tree = Monotree::default();
+    let (left, right) = ([0x00; HASH_LEN], [0xff; HASH_LEN]);
+    let root = tree.inserts(None, &[left, right], &[left, right])?;
+    assert_eq!(
+        tree.get_with_side(root.as_ref(), &left)?,
+        Some((left, false))
+    );
+    assert_eq!(
+        tree.get_with_side(root.as_ref(), &right)?,
+        Some((right, true))
+    );
+
+    // the side is decided at the parent, not at the root
+    let mut middle = left;
+    middle[HASH_LEN - 1] = 0x01;
+    let root = tree.insert(root.as_ref(), &middle, &middle)?;
+    assert_eq!(
+        tree.get_with_side(root.as_ref(), &left)?,
+        Some((left, false))
+    );
+    assert_eq!(
+        tree.get_with_side(root.as_ref(), &middle)?,
+        Some((middle, true))
+    );
+    assert_eq!(
+        tree.get_with_side(root.as_ref(), &right)?,
+        Some((right, true))
+    );
+    assert_eq!(tree.get_with_side(root.as_ref(), &random_hash())?, None);
+    Ok(())
+}
+
+#[test]
+fn test_hard_node_of_cells_on_the_same_side() -> Result<()> {
+    let (lh, rh) = (random_hash(), random_hash());
+    let (lk, rk) = ([0x00; HASH_LEN], [0xff; HASH_LEN]);
+    let unit = |hash, key| {
+        Some(Unit {
+            hash,
+            bits: Bits::new(key),
+        })
+    };
+
+    // cells in either order are serialized into the same bytes
+    let node: Node = Node::new(unit(&lh, &lk), unit(&rh, &rk));
+    let swapped: Node = Node::new(unit(&rh, &rk), unit(&lh, &lk));
+    assert_eq!(node.to_bytes()?, swapped.to_bytes()?);
+
+    let node: Node = Node::new(unit(&lh, &lk), unit(&rh, &lk));
+    assert!(node.to_bytes().is_err());
+    assert!(node.to_compact_bytes().is_err());
+
+    // bytes with cells placed in the reverse order
+    let corrupt = [
+        &rh[..],
+        &Bits::new(&rk).to_bytes()?,
+        &Bits::new(&lk).to_bytes()?,
+        &lh[..],
+        &[0x01],
+    ]
+    .concat();
+    assert!(Node::<HASH_LEN>::from_bytes(&corrupt).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "hasher_poseidon")]
+#[test]
+fn test_poseidon_hasher() -> Result<()> {
+    let hasher = Poseidon::new();
+    // a single byte of 0x02 is absorbed as elements of [1, 2], of which digest is
+    // the one of `poseidon([1, 2])` in `circomlib`
+    assert_eq!(
+        hex!(hasher.digest(&[0x02])),
+        "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a"
+    );
+    assert_ne!(hasher.digest(&[]), hasher.digest(&[0x00]));
+    assert_ne!(hasher.digest(&[0x01; 400]), hasher.digest(&[0x01; 401]));
+
+    let mut tree = Monotree::<MemoryDB, Poseidon>::new("poseidon");
+    let keys = random_hashes(100);
+    let leaves = random_hashes(100);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof(&hasher, root.as_ref(), leaf, proof.as_ref()));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_prune() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let old = tree.inserts(None, &keys[..50], &keys[..50])?;
+    let root = tree.inserts(old.as_ref(), &keys[50..], &keys[50..])?;
+    let value = tree.insert_value(root.as_ref(), &keys[0], b"value")?;
+    tree.set_headroot(value.as_ref())?;
+    let latest = tree.remove(value.as_ref(), &keys[99])?;
+    let writes = tree.last_op_writes();
+
+    // the headroot is kept along with the value, even if not given as live
+    let count = tree.prune(&[latest.unwrap()])?;
+    assert!(count > writes);
+    assert_eq!(tree.prune(&[latest.unwrap()])?, 0);
+    assert_eq!(tree.get_headroot()?, value);
+    assert_eq!(
+        tree.get_value(value.as_ref(), &keys[0])?,
+        Some(b"value".to_vec())
+    );
+
+    // nodes only of the old versions are gone
+    assert!(tree.get(old.as_ref(), &keys[0]).is_err());
+    assert!(tree.get(root.as_ref(), &keys[0]).is_err());
+
+    // while the latest root still verifies
+    assert_eq!(tree.len(latest.as_ref())?, 99);
+    for key in keys[1..99].iter() {
+        let proof = tree.get_merkle_proof(latest.as_ref(), key)?;
+        assert!(verify_proof(&hasher, latest.as_ref(), key, proof.as_ref()));
+    }
+
+    // dropping the headroot, nodes only of its version are gone as well
+    tree.set_headroot(None)?;
+    assert!(tree.gc(&[latest.unwrap()])? > 0);
+    assert_eq!(tree.get_headroot()?, None);
+    assert!(tree.get(value.as_ref(), &keys[99]).is_err());
+    assert_eq!(tree.len(latest.as_ref())?, 99);
+    Ok(())
+}
+
+#[test]
+fn test_auto_head() -> Result<()> {
+    let keys = random_hashes(100);
+    let mut tree = Monotree::default();
+    let root = tree.inserts(None, &keys, &keys)?;
+    assert_eq!(tree.get_headroot()?, None);
+
+    let mut tree = Monotree::default().with_auto_head(true);
+    let root_ = tree.inserts(None, &keys, &keys)?;
+    assert_eq!(root_, root);
+    assert_eq!(tree.get_headroot()?, root);
+
+    let root = tree.insert(root.as_ref(), &keys[0], &keys[1])?;
+    assert_eq!(tree.get_headroot()?, root);
+    let root = tree.insert_value(root.as_ref(), &keys[1], b"value")?;
+    assert_eq!(tree.get_headroot()?, root);
+    let root = tree.remove(root.as_ref(), &keys[2])?;
+    assert_eq!(tree.get_headroot()?, root);
+    let root = tree.apply_batch(
+        root.as_ref(),
+        &[Op::Remove(keys[3]), Op::Insert(keys[2], keys[2])],
+    )?;
+    assert_eq!(tree.get_headroot()?, root);
+
+    // emptying the tree clears the headroot
+    let root = tree.removes(root.as_ref(), &keys)?;
+    assert_eq!(root, None);
+    assert_eq!(tree.get_headroot()?, None);
+    Ok(())
+}
+
+#[test]
+fn test_roots_history() -> Result<()> {
+    let mut tree = Monotree::default().with_history_capacity(3);
+    let keys = random_hashes(5);
+    assert!(tree.roots_history()?.is_empty());
+    let mut roots: Vec<Hash> = Vec::new();
+    let mut root = None;
+    for key in keys.iter() {
+        root = tree.insert(root.as_ref(), key, key)?;
+        tree.push_root(root.as_ref().unwrap())?;
+        roots.insert(0, root.unwrap());
+    }
+
+    // only the recent ones are kept, newest first
+    assert_eq!(tree.roots_history()?, roots[..3].to_vec());
+
+    // undo the last commit, even after pruning with no live roots given
+    assert!(tree.prune(&[])? > 0);
+    let previous = tree.roots_history()?[1];
+    assert_eq!(tree.get(Some(&previous), &keys[4])?, None);
+    assert_eq!(tree.get(Some(&previous), &keys[3])?, Some(keys[3]));
+    assert!(tree.get(Some(&roots[3]), &keys[0]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_domain_hasher() -> Result<()> {
+    let keys = random_hashes(100);
+    let (alice, bob) = (
+        DomainHasher::<Blake3>::with_tag(b"alice"),
+        DomainHasher::<Blake3>::with_tag(b"bob"),
+    );
+    let mut tree =
+        Monotree::<MemoryDB, DomainHasher<Blake3>>::new("alice").with_hasher(alice.clone());
+    let root = tree.inserts(None, &keys, &keys)?;
+
+    // the same entries in other domains have different roots
+    let mut other = Monotree::<MemoryDB, DomainHasher<Blake3>>::new("bob").with_hasher(bob.clone());
+    assert_ne!(other.inserts(None, &keys, &keys)?, root);
+    assert_ne!(
+        Monotree::<MemoryDB, Blake3>::new("plain").inserts(None, &keys, &keys)?,
+        root
+    );
+
+    // tags are not confused with the input following them
+    let ali = DomainHasher::<Blake3>::with_tag(b"ali");
+    assert_ne!(ali.digest(b"cedata"), alice.digest(b"data"));
+
+    // proofs verify only with the hasher of the same tag
+    for key in keys.iter() {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof(&alice, root.as_ref(), key, proof.as_ref()));
+        assert!(!verify_proof(&bob, root.as_ref(), key, proof.as_ref()));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_blake3_keyed() -> Result<()> {
+    let keys = random_hashes(100);
+    let (key, other) = (random_hash(), random_hash());
+    let hasher = Blake3Keyed::with_key(&key);
+    let mut tree = Monotree::<MemoryDB, _>::new_with_hasher("keyed", hasher.clone());
+    let root = tree.inserts(None, &keys, &keys)?;
+
+    // roots differ by keys, and from the one of unkeyed hasher
+    let mut roots = vec![root];
+    for hasher in [Blake3Keyed::with_key(&other), Blake3Keyed::new()].iter() {
+        let mut tree = Monotree::<MemoryDB, _>::new_with_hasher("keyed", hasher.clone());
+        roots.push(tree.inserts(None, &keys, &keys)?);
+    }
+    roots.push(Monotree::<MemoryDB, Blake3>::new("plain").inserts(None, &keys, &keys)?);
+    assert!((1..roots.len()).all(|i| !roots[..i].contains(&roots[i])));
+    assert_eq!(
+        Blake3Keyed::new().digest(b"data"),
+        Blake3Keyed::with_config([0x00; 32]).digest(b"data")
+    );
+
+    // proofs verify only with the hasher of the same key
+    for key in keys.iter() {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof(&hasher, root.as_ref(), key, proof.as_ref()));
+        assert!(!verify_proof(
+            &Blake3Keyed::with_key(&other),
+            root.as_ref(),
+            key,
+            proof.as_ref()
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_keccak256() -> Result<()> {
+    // well-known digests of the empty input differ by padding only
+    assert_eq!(
+        hex!(Keccak256::new().digest(b"")),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    assert_eq!(
+        hex!(Sha3::new().digest(b"")),
+        "a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_sha512_256() -> Result<()> {
+    // well-known digests of the empty input differ from those of SHA-256
+    assert_eq!(
+        hex!(Sha512_256::new().digest(b"")),
+        "c672b8d1ef56ed28ab87c3622c5114069bdd3ad7b8f9737498d0c01ecef0967a"
+    );
+    assert_eq!(
+        hex!(Sha2::new().digest(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_multiproof_bytes() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let mut keys = random_hashes(300);
+    // keys sharing a common prefix
+    for key in keys.iter_mut().take(50) {
+        key[..2].copy_from_slice(&[0xab, 0xcd]);
+    }
+    let root = tree.inserts(None, &keys, &keys)?;
+
+    let multiproof = tree.get_merkle_multiproof(root.as_ref(), &keys[..50])?;
+    let bytes = encode_multiproof_bytes(&multiproof);
+    assert_eq!(decode_multiproof_bytes(&bytes)?, multiproof);
+    let entries: Vec<(Hash, Hash)> = keys[..50].iter().map(|key| (*key, *key)).collect();
+    assert!(verify_multiproof(
+        &hasher,
+        root.as_ref(),
+        &entries,
+        &decode_multiproof_bytes(&bytes)?
+    ));
+
+    // meaningfully smaller than the individual proofs altogether
+    let mut len = 0;
+    for key in keys[..50].iter() {
+        let proof = tree.get_merkle_proof(root.as_ref(), key)?.unwrap();
+        len += encode_proof_bytes(&proof).len();
+    }
+    assert!(bytes.len() * 2 < len);
+
+    assert!(decode_multiproof_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(decode_multiproof_bytes(&[&bytes[..], &[0x00]].concat()).is_err());
+    assert_eq!(
+        decode_multiproof_bytes(&encode_multiproof_bytes(&MultiProof::default()))?,
+        MultiProof::default()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_resolve_root_prefix() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?.unwrap();
+    tree.set_headroot(Some(&root))?;
+    let value = tree.insert_value(Some(&root), &keys[0], b"value")?.unwrap();
+
+    assert_eq!(tree.resolve_root_prefix(&root[..8])?, vec![root]);
+    assert_eq!(tree.resolve_root_prefix(&root)?, vec![root]);
+    assert_eq!(tree.resolve_root_prefix(&value[..4])?, vec![value]);
+
+    // neither the headroot key nor stored values are taken for nodes
+    let leaf = tree.hash_leaf(b"value");
+    assert!(tree.resolve_root_prefix(&leaf)?.is_empty());
+    assert!(tree.resolve_root_prefix(&ROOT_KEY[..4])?.is_empty());
+
+    let hashes = tree.resolve_root_prefix(&[])?;
+    assert!(hashes.contains(&root) && hashes.contains(&value));
+    assert!(hashes.windows(2).all(|x| x[0] < x[1]));
+    assert!(hashes
+        .iter()
+        .all(|hash| tree.resolve_root_prefix(&hash[..1]).unwrap().contains(hash)));
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async_monotree() -> Result<()> {
+    use std::future::Future;
+    use std::task::{Context, Poll, Waker};
+
+    // futures of `MemoryDB` are ready at once, so polling them in a loop is enough
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+    fn assert_send<T: Send>(_: &T) {}
+
+    let keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let mut tree = Monotree::default();
+    let mut atree: AsyncMonotree<MemoryDB> = AsyncMonotree::with_db(MemoryDB::new(""));
+    let mut root = None;
+    let mut aroot = None;
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        root = tree.insert(root.as_ref(), key, leaf)?;
+        let future = atree.insert(aroot.as_ref(), key, leaf);
+        assert_send(&future);
+        aroot = block_on(future)?;
+        assert_eq!(aroot, root);
+    }
+    for (key, leaf) in keys.iter().zip(leaves.iter()) {
+        assert_eq!(block_on(atree.get(aroot.as_ref(), key))?, Some(*leaf));
+    }
+    assert_eq!(block_on(atree.get(aroot.as_ref(), &random_hash()))?, None);
+
+    for key in keys.iter().take(250) {
+        root = tree.remove(root.as_ref(), key)?;
+        aroot = block_on(atree.remove(aroot.as_ref(), key))?;
+        assert_eq!(aroot, root);
+        assert_eq!(block_on(atree.get(aroot.as_ref(), key))?, None);
+    }
+    for key in keys.iter().skip(250) {
+        aroot = block_on(atree.remove(aroot.as_ref(), key))?;
+    }
+    assert_eq!(aroot, None);
+
+    let dangling = random_hash();
+    assert_eq!(
+        block_on(atree.get(Some(&dangling), &keys[0])),
+        Err(MonotreeError::NodeNotFound(dangling.to_vec()))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_inserts_parallel() -> Result<()> {
+    for &n in [0, 1, 2, 3, 100, 10_000].iter() {
+        let keys = random_hashes(n);
+        let leaves = random_hashes(n);
+        let mut tree = Monotree::default();
+        let root = tree.inserts(None, &keys, &leaves)?;
+        let mut other = Monotree::default();
+        assert_eq!(other.inserts_parallel(None, &keys, &leaves)?, root);
+        for (key, leaf) in keys.iter().zip(leaves.iter()) {
+            assert_eq!(other.get(root.as_ref(), key)?, Some(*leaf));
+        }
+        let mut other = Monotree::default();
+        assert_eq!(other.par_inserts(None, &keys, &leaves)?, root);
+    }
+
+    // nodes in any form result in the same root as well
+    let keys = random_hashes(1000);
+    let mut tree = Monotree::default()
+        .with_compact_node_encoding(true)
+        .with_domain_separation(true);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let mut other = Monotree::default()
+        .with_compact_node_encoding(true)
+        .with_domain_separation(true);
+    assert_eq!(other.inserts_parallel(None, &keys, &keys)?, root);
+    assert_eq!(other.last_op_writes(), 999);
+
+    // the last one wins among entries of the same key
+    let leaves = random_hashes(1000);
+    let mut duplicated = keys.clone();
+    duplicated.extend(keys.iter());
+    let overwritten = [&keys[..], &leaves[..]].concat();
+    let root = tree.inserts(None, &keys, &leaves)?;
+    assert_eq!(
+        other.inserts_parallel(None, &duplicated, &overwritten)?,
+        root
+    );
+
+    // given a root, the same as inserts()
+    let more = random_hashes(100);
+    let expected = tree.inserts(root.as_ref(), &more, &more)?;
+    assert_eq!(
+        other.inserts_parallel(root.as_ref(), &more, &more)?,
+        expected
+    );
+    Ok(())
+}
+
+#[test]
+fn test_insert_returning_root_node() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let mut root = None;
+    for key in keys.iter() {
+        let (hash, bytes) = tree
+            .insert_returning_root_node(root.as_ref(), key, key)?
+            .unwrap();
+        assert_eq!(hasher.digest(&bytes), hash);
+        let node: Node = Node::from_bytes(&bytes)?;
+        match node {
+            Node::Soft(cell) => assert!(cell.is_some()),
+            Node::Hard(lc, rc) => assert!(lc.is_some() && rc.is_some()),
+        }
+        root = Some(hash);
+    }
+    assert_eq!(tree.len(root.as_ref())?, 100);
+    Ok(())
+}
+
+#[test]
+fn test_memo() -> Result<()> {
+    let mut tree = Monotree::default().with_memo(true);
+    let keys = random_hashes(100);
+    let (key, leaf) = (random_hash(), random_hash());
+    let mut root = tree.inserts(None, &keys, &keys)?;
+
+    // polling a key until it appears
+    for _ in 0..3 {
+        assert_eq!(tree.get(root.as_ref(), &key)?, None);
+    }
+    root = tree.insert(root.as_ref(), &key, &leaf)?;
+    for _ in 0..3 {
+        assert_eq!(tree.get(root.as_ref(), &key)?, Some(leaf));
+        assert_eq!(tree.get(root.as_ref(), &keys[0])?, Some(keys[0]));
+    }
+
+    // the memo never outlives nodes pruned
+    let old = tree.remove(root.as_ref(), &key)?;
+    assert_eq!(tree.get(old.as_ref(), &keys[1])?, Some(keys[1]));
+    tree.prune(&[root.unwrap()])?;
+    assert!(tree.get(old.as_ref(), &keys[1]).is_err());
+    assert_eq!(tree.get(root.as_ref(), &key)?, Some(leaf));
+    Ok(())
+}
+
+#[test]
+fn test_remove_checked() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    assert_eq!(tree.remove_checked(None, &keys[0])?, (None, false));
+
+    // removing absent keys changes nothing
+    for key in random_hashes(100).iter() {
+        assert_eq!(tree.remove_checked(root.as_ref(), key)?, (root, false));
+        assert_eq!(tree.remove(root.as_ref(), key)?, root);
+    }
+    let (left, right) = ([0x00; HASH_LEN], [0xff; HASH_LEN]);
+    let single = tree.insert(None, &left, &left)?;
+    assert_eq!(
+        tree.remove_checked(single.as_ref(), &right)?,
+        (single, false)
+    );
+
+    let mut root = root;
+    for (i, key) in keys.iter().enumerate() {
+        let (next, removed) = tree.remove_checked(root.as_ref(), key)?;
+        assert!(removed);
+        assert_eq!(tree.len(next.as_ref())?, 99 - i);
+        assert_eq!(tree.remove_checked(next.as_ref(), key)?, (next, false));
+        root = next;
+    }
+    assert_eq!(root, None);
+    Ok(())
+}
+
+#[test]
+fn test_proof_bundle() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(300);
+    let leaves = random_hashes(300);
+    let root = tree.inserts(None, &keys, &leaves)?;
+
+    let mut bundle: Vec<u8> = Vec::new();
+    tree.export_proof_bundle(root.as_ref(), &keys[..50], &mut bundle)?;
+    assert!(verify_proof_bundle(&hasher, &mut &bundle[..])?);
+
+    // a tampered leaf fails to verify
+    let i = bundle
+        .windows(HASH_LEN)
+        .position(|x| x == leaves[7])
+        .unwrap();
+    let mut tampered = bundle.clone();
+    tampered[i] ^= 0x01;
+    assert!(!verify_proof_bundle(&hasher, &mut &tampered[..])?);
+
+    // neither bundles malformed nor of another hasher are verified
+    assert!(verify_proof_bundle(&Sha3::new(), &mut &bundle[..]).is_err());
+    assert!(verify_proof_bundle(&hasher, &mut &bundle[..bundle.len() - 1]).is_err());
+    assert!(verify_proof_bundle(&hasher, &mut &bundle[1..]).is_err());
+
+    let mut bundle: Vec<u8> = Vec::new();
+    assert!(tree
+        .export_proof_bundle(root.as_ref(), &[random_hash()], &mut bundle)
+        .is_err());
+    assert!(tree
+        .export_proof_bundle(None, &keys[..1], &mut bundle)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_published_root() -> Result<()> {
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let mut tree = Monotree::default();
+    let root = tree.inserts(None, &keys, &keys)?.unwrap();
+    assert_eq!(tree.published_root(&root), root.to_vec());
+
+    let mut tree = Monotree::default().with_published_root_len(16);
+    assert_eq!(tree.inserts(None, &keys, &keys)?, Some(root));
+    let published = tree.published_root(&root);
+    assert_eq!(published, root[..16].to_vec());
+
+    let proof = tree.get_merkle_proof(Some(&root), &keys[0])?.unwrap();
+    let computed = compute_root_from_proof(&hasher, &keys[0], &proof)?;
+    assert_eq!(computed[..16], published[..]);
+
+    // lengths out of range are clamped to the length of hash
+    let tree = Monotree::default().with_published_root_len(0);
+    assert_eq!(tree.published_root(&root), root[..1].to_vec());
+    let tree = Monotree::default().with_published_root_len(HASH_LEN + 1);
+    assert_eq!(tree.published_root(&root), root.to_vec());
+    Ok(())
+}
+
+/// A database of simulated latency on reads, which runs prefetches on their own threads.
+struct LatencyDB {
+    db: Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>,
+    pending: HashMap<Vec<u8>, JoinHandle<Option<Vec<u8>>>>,
+    /// The number of reads not prefetched, which wait for the full latency.
+    cold: Arc<AtomicUsize>,
+}
+
+impl LatencyDB {
+    const LATENCY: Duration = Duration::from_micros(100);
+}
+
+impl Database for LatencyDB {
+    fn new(_dbpath: &str) -> Self {
+        LatencyDB {
+            db: Default::default(),
+            pending: HashMap::new(),
+            cold: Default::default(),
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.pending.remove(key) {
+            Some(pending) => Ok(pending.join().unwrap()),
+            None => {
+                self.cold.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Self::LATENCY);
+                Ok(self.db.lock().unwrap().get(key).cloned())
+            }
+        }
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.pending.remove(key);
+        self.db.lock().unwrap().insert(key.to_vec(), value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.pending.remove(key);
+        self.db.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn init_batch(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish_batch(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn prefetch(&mut self, keys: &[&[u8]]) -> Result<()> {
+        for key in keys.iter() {
+            if self.pending.contains_key(*key) {
+                continue;
+            }
+            let (db, owned) = (Arc::clone(&self.db), key.to_vec());
+            let pending = thread::spawn(move || {
+                thread::sleep(Self::LATENCY);
+                db.lock().unwrap().get(&owned).cloned()
+            });
+            self.pending.insert(key.to_vec(), pending);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_prefetch() -> Result<()> {
+    let mut keys = random_hashes(500);
+    keys.sort();
+    let leaves = random_hashes(500);
+    let mut cold: Vec<usize> = Vec::new();
+    for &on in [false, true].iter() {
+        let db = LatencyDB::new("latency");
+        let counter = Arc::clone(&db.cold);
+        let mut tree = Monotree::<_, Blake3>::with_db(db).with_prefetch(on);
+        let root = tree.inserts(None, &keys, &leaves)?;
+
+        // walks of keys in order read nodes just prefetched by the former walks
+        counter.store(0, Ordering::SeqCst);
+        for (key, leaf) in keys.iter().zip(leaves.iter()) {
+            assert_eq!(tree.get(root.as_ref(), key)?, Some(*leaf));
+        }
+        cold.push(counter.load(Ordering::SeqCst));
+    }
+    assert!(cold[1] < cold[0]);
+
+    #[cfg(feature = "db_rocksdb")]
+    {
+        let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+        let _g = scopeguard::guard((), |_| {
+            if fs::metadata(&dbname).is_ok() {
+                fs::remove_dir_all(&dbname).unwrap()
+            }
+        });
+        let root = Monotree::default().inserts(None, &keys, &leaves)?;
+        let mut tree = Monotree::<RocksDB, Blake3>::new(&dbname).with_prefetch(true);
+        assert_eq!(tree.inserts(None, &keys, &leaves)?, root);
+        let mut reader = Monotree::<RocksDB, Blake3>::new(&dbname).with_prefetch(true);
+        for (key, leaf) in keys.iter().zip(leaves.iter()) {
+            assert_eq!(reader.get(root.as_ref(), key)?, Some(*leaf));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_inserts_and_removes_sorted() -> Result<()> {
+    let mut tree = Monotree::default();
+    let mut keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let root = tree.inserts(None, &keys, &leaves)?;
+    let removed = tree.removes(root.as_ref(), &keys[..250])?;
+
+    // pairs of key and leaf sorted by keys give the same roots without sorting again
+    let mut entries: Vec<(Hash, Hash)> = keys.iter().cloned().zip(leaves.iter().cloned()).collect();
+    entries.sort_unstable();
+    let (sorted, leaves): (Vec<Hash>, Vec<Hash>) = entries.into_iter().unzip();
+    let mut other = Monotree::default();
+    assert_eq!(other.inserts_sorted(None, &sorted, &leaves)?, root);
+    keys.truncate(250);
+    keys.sort_unstable();
+    assert_eq!(other.removes_sorted(root.as_ref(), &keys)?, removed);
+    Ok(())
+}
+
+#[test]
+fn test_cas_insert() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(3);
+    let head = tree.cas_insert(None, &keys[0], &keys[0])?;
+    assert_eq!(tree.get_headroot()?, head);
+    let head = tree.cas_insert(head.as_ref(), &keys[1], &keys[1])?;
+    assert_eq!(tree.get_headroot()?, head);
+
+    // a writer holding a stale head is rejected, with neither nodes nor headroot written
+    let stale = tree.insert(None, &keys[0], &keys[0])?;
+    assert_eq!(
+        tree.cas_insert(stale.as_ref(), &keys[2], &keys[2]),
+        Err(MonotreeError::CasFailed)
+    );
+    assert_eq!(tree.last_op_writes(), 0);
+    assert_eq!(tree.get_headroot()?, head);
+    assert_eq!(
+        tree.cas_insert(None, &keys[2], &keys[2]),
+        Err(MonotreeError::CasFailed)
+    );
+
+    let new = tree.cas_insert(head.as_ref(), &keys[2], &keys[2])?;
+    assert_eq!(tree.get_headroot()?, new);
+    assert_eq!(tree.len(new.as_ref())?, 3);
+    Ok(())
+}
+
+/// Tell that no batch is left open by the tree of auto head off,
+/// as the entry it inserts then is visible to the reader on the same database.
+fn assert_batch_closed<D: Database>(
+    tree: &mut Monotree<D, Blake3>,
+    reader: &mut Monotree<MemoryDB, Blake3>,
+) -> Result<()> {
+    let key = random_hash();
+    let root = tree.insert(None, &key, &key)?;
+    assert_eq!(reader.get(root.as_ref(), &key)?, Some(key));
+    Ok(())
+}
+
+/// A `MemoryDB` of which reads, writes, deletions and discarding batches fail
+/// while the respective flags are set.
+struct FaultyDB {
+    db: MemoryDB,
+    faulty_gets: Arc<AtomicBool>,
+    faulty_puts: Arc<AtomicBool>,
+    faulty_deletes: Arc<AtomicBool>,
+    faulty_discards: Arc<AtomicBool>,
+}
+
+impl Database for FaultyDB {
+    fn new(dbpath: &str) -> Self {
+        FaultyDB {
+            db: MemoryDB::new(dbpath),
+            faulty_gets: Default::default(),
+            faulty_puts: Default::default(),
+            faulty_deletes: Default::default(),
+            faulty_discards: Default::default(),
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if self.faulty_gets.load(Ordering::SeqCst) {
+            return Err(MonotreeError::Db("faulty: get".to_string()));
+        }
+        Database::get(&mut self.db, key)
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        if self.faulty_puts.load(Ordering::SeqCst) {
+            return Err(MonotreeError::Db("faulty: put".to_string()));
+        }
+        Database::put(&mut self.db, key, value)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        if self.faulty_deletes.load(Ordering::SeqCst) {
+            return Err(MonotreeError::Db("faulty: delete".to_string()));
+        }
+        Database::delete(&mut self.db, key)
+    }
+
+    fn init_batch(&mut self) -> Result<()> {
+        Database::init_batch(&mut self.db)
+    }
+
+    fn finish_batch(&mut self) -> Result<()> {
+        Database::finish_batch(&mut self.db)
+    }
+
+    fn discard_batch(&mut self) -> Result<()> {
+        if self.faulty_discards.load(Ordering::SeqCst) {
+            return Err(MonotreeError::Db("faulty: discard_batch".to_string()));
+        }
+        Database::discard_batch(&mut self.db)
+    }
+
+    fn scan(&mut self, prefix: &[u8]) -> Result<Vec<Vec<u8>>> {
+        self.db.scan(prefix)
+    }
+}
+
+#[test]
+fn test_clear_with_batch_open() -> Result<()> {
+    let db = FaultyDB::new("monotree");
+    let faulty = Arc::clone(&db.faulty_discards);
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let keys = random_hashes(10);
+    let root = tree.inserts(None, &keys, &keys)?;
+    tree.set_headroot(root.as_ref())?;
+
+    // a batch failed to be discarded is left open, which is never cleared silently
+    faulty.store(true, Ordering::SeqCst);
+    let queries: Vec<&[u8]> = vec![&keys[0][..], &keys[1][..1]];
+    assert!(tree.inserts(root.as_ref(), &queries, &keys[..2]).is_err());
+    assert!(tree.clear().is_err());
+    assert_eq!(tree.get_headroot()?, root);
+
+    // clearing works again once a batch is finished
+    faulty.store(false, Ordering::SeqCst);
+    let other = tree.inserts(None, &keys[..1], &keys[..1])?;
+    tree.clear()?;
+    assert_eq!(tree.get_headroot()?, None);
+    for root in [root, other].iter() {
+        assert_eq!(tree.get(root.as_ref(), &keys[0])?, None);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_writes_kept_after_failed_inserts() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(3);
+    let r0 = tree.inserts(None, &keys[..1], &keys[..1])?;
+    let (k2, k3): (Hash, &[u8]) = ([2; HASH_LEN], &[3; 5]);
+    assert!(matches!(
+        tree.inserts(r0.as_ref(), &[&k2[..], k3], &keys[..2]),
+        Err(MonotreeError::KeyLength { .. })
+    ));
+
+    // the nodes written after the failure are never lost by the batches that follow
+    let r1 = tree.insert(r0.as_ref(), &k2, &keys[1])?;
+    assert_eq!(tree.get(r1.as_ref(), &k2)?, Some(keys[1]));
+    let r2 = tree.inserts(r1.as_ref(), &keys[2..], &keys[2..])?;
+    assert_eq!(tree.get(r1.as_ref(), &k2)?, Some(keys[1]));
+    assert_eq!(tree.get(r2.as_ref(), &k2)?, Some(keys[1]));
+    Ok(())
+}
+
+#[test]
+fn test_load_import_failed_midway() -> Result<()> {
+    let mut source = Monotree::<_, Blake3>::default();
+    let keys = random_hashes(10);
+    let root = source.inserts(None, &keys, &keys)?.unwrap();
+    let blob = source.export_subtree(&root)?;
+    let mut dump: Vec<u8> = Vec::new();
+    source.dump(&mut dump)?;
+
+    let db = FaultyDB::new("monotree");
+    let faulty = Arc::clone(&db.faulty_puts);
+    let mut reader = Monotree::<_, Blake3>::with_db(db.db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    faulty.store(true, Ordering::SeqCst);
+    assert!(tree.import_subtree(&blob).is_err());
+    assert!(tree.load(&dump[..]).is_err());
+    faulty.store(false, Ordering::SeqCst);
+    assert_batch_closed(&mut tree, &mut reader)
+}
+
+#[test]
+fn test_inserts_failed_midway() -> Result<()> {
+    let db = MemoryDB::new("monotree");
+    let mut reader = Monotree::<_, Blake3>::with_db(db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let keys = random_hashes(2);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let bad: [&[u8]; 2] = [&keys[0], &keys[1][..5]];
+    assert!(tree.inserts(root.as_ref(), &bad, &keys).is_err());
+    assert_batch_closed(&mut tree, &mut reader)?;
+    assert!(tree.removes(root.as_ref(), &bad).is_err());
+    assert_batch_closed(&mut tree, &mut reader)?;
+    Ok(())
+}
+
+#[test]
+fn test_auto_head_failed_midway() -> Result<()> {
+    let db = MemoryDB::new("monotree");
+    let mut reader = Monotree::<_, Blake3>::with_db(db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db).with_auto_head(true);
+    let key = random_hash();
+    let root = tree.insert(None, &key, &key)?;
+    assert!(tree.insert(root.as_ref(), &key[..5], &key).is_err());
+    assert!(tree.remove(root.as_ref(), &key[..5]).is_err());
+    assert_eq!(reader.get_headroot()?, root);
+
+    let mut tree = tree.with_auto_head(false);
+    assert_batch_closed(&mut tree, &mut reader)
+}
+
+#[test]
+fn test_insert_value_failed_midway() -> Result<()> {
+    let db = MemoryDB::new("monotree");
+    let mut reader = Monotree::<_, Blake3>::with_db(db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let key = random_hash();
+    assert!(tree.insert_value(None, &key[..5], b"value").is_err());
+    assert_batch_closed(&mut tree, &mut reader)
+}
+
+#[test]
+fn test_prune_failed_midway() -> Result<()> {
+    let db = FaultyDB::new("monotree");
+    let faulty = Arc::clone(&db.faulty_deletes);
+    let mut reader = Monotree::<_, Blake3>::with_db(db.db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let keys = random_hashes(10);
+    let root = tree.inserts(None, &keys, &keys)?;
+    tree.inserts(None, &keys[..5], &keys[..5])?;
+
+    faulty.store(true, Ordering::SeqCst);
+    assert!(tree.prune(&[root.unwrap()]).is_err());
+    faulty.store(false, Ordering::SeqCst);
+    assert_batch_closed(&mut tree, &mut reader)
+}
+
+#[test]
+fn test_apply_batch_failed_midway() -> Result<()> {
+    let db = MemoryDB::new("monotree");
+    let mut reader = Monotree::<_, Blake3>::with_db(db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let keys = random_hashes(3);
+    let root = tree.inserts(None, &keys[..2], &keys[..2])?;
+
+    // an op on a dangling root fails in the middle of the batch
+    let ops = vec![Op::Insert(keys[2], keys[2]), Op::Remove(keys[0])];
+    assert!(tree.apply_batch(Some(&keys[2]), &ops).is_err());
+    assert_batch_closed(&mut tree, &mut reader)?;
+    assert!(tree.apply(Some(&keys[2]), &ops).is_err());
+    assert_batch_closed(&mut tree, &mut reader)?;
+    assert_eq!(
+        tree.apply(root.as_ref(), &ops)?,
+        tree.inserts(None, &keys[1..], &keys[1..])?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cas_insert_failed_midway() -> Result<()> {
+    let db = MemoryDB::new("monotree");
+    let mut reader = Monotree::<_, Blake3>::with_db(db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let keys = random_hashes(2);
+    let head = tree.cas_insert(None, &keys[0], &keys[0])?;
+    assert!(matches!(
+        tree.cas_insert(head.as_ref(), &keys[1][..8], &keys[1]),
+        Err(MonotreeError::KeyLength { .. })
+    ));
+
+    // the batch is not left open, so the writes that follow are visible to others
+    let root = tree.insert(head.as_ref(), &keys[1], &keys[1])?;
+    assert_eq!(reader.get(root.as_ref(), &keys[1])?, Some(keys[1]));
+    assert_eq!(reader.get_headroot()?, head);
+
+    // so is it on failing to read the headroot
+    let db = FaultyDB::new("monotree");
+    let faulty = Arc::clone(&db.faulty_gets);
+    let mut reader = Monotree::<_, Blake3>::with_db(db.db.cursor());
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    faulty.store(true, Ordering::SeqCst);
+    assert!(tree.cas_insert(None, &keys[0], &keys[0]).is_err());
+    faulty.store(false, Ordering::SeqCst);
+    assert_batch_closed(&mut tree, &mut reader)
+}
+
+#[test]
+fn test_inserts_duplicate_keys() -> Result<()> {
+    let mut tree = Monotree::default();
+    let (key, a, b) = (random_hash(), random_hash(), random_hash());
+    let root = tree.inserts(None, &[key, key], &[a, b])?;
+    assert_eq!(tree.get(root.as_ref(), &key)?, Some(b));
+    assert_eq!(root, tree.insert(None, &key, &b)?);
+
+    // the last one in the order given wins regardless of the other keys sorted around
+    let mut keys = random_hashes(100);
+    let mut leaves = random_hashes(100);
+    let expected = tree.inserts(None, &keys, &leaves)?;
+    let last = leaves.clone();
+    keys.extend(keys.clone());
+    leaves = random_hashes(100).into_iter().chain(last).collect();
+    assert_eq!(tree.inserts(None, &keys, &leaves)?, expected);
+    let ops: Vec<Op> = keys
+        .iter()
+        .zip(leaves.iter())
+        .map(|(key, leaf)| Op::Insert(*key, *leaf))
+        .collect();
+    assert_eq!(tree.apply_batch(None, &ops)?, expected);
+    Ok(())
+}
+
+#[test]
+fn test_get_map() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(200);
+    let leaves = random_hashes(200);
+    let root = tree.inserts(None, &keys[..100], &leaves[..100])?;
+    assert!(tree.get_map(None, &keys)?.is_empty());
+
+    // repeated keys appear once, while keys not found are left out
+    let queries: Vec<Hash> = keys.iter().chain(keys[..50].iter()).cloned().collect();
+    let map = tree.get_map(root.as_ref(), &queries)?;
+    assert_eq!(map.len(), 100);
+    for (key, leaf) in keys[..100].iter().zip(leaves[..100].iter()) {
+        assert_eq!(map.get(key), Some(leaf));
+    }
+    assert!(keys[100..].iter().all(|key| !map.contains_key(key)));
+    assert!(tree.get_map(root.as_ref(), &[&keys[0][..1]]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_gets_across_roots() -> Result<()> {
+    let mut tree = Monotree::default();
+    let key = random_hash();
+    let others = random_hashes(100);
+    let values = random_hashes(3);
+
+    let mut roots = vec![None];
+    let root = tree.inserts(None, &others[..50], &others[..50])?;
+    roots.push(root);
+    let root = tree.insert(root.as_ref(), &key, &values[0])?;
+    roots.push(root);
+    let root = tree.inserts(root.as_ref(), &[key, others[50]], &[values[1], others[50]])?;
+    roots.push(root);
+    let root = tree.inserts(root.as_ref(), &others[51..], &others[51..])?;
+    roots.push(root);
+    let root = tree.remove(root.as_ref(), &key)?;
+    roots.push(root);
+    let root = tree.insert(root.as_ref(), &key, &values[2])?;
+    roots.push(root);
+
+    let history = tree.gets_across_roots(&roots, &key)?;
+    assert_eq!(
+        history,
+        vec![
+            None,
+            None,
+            Some(values[0]),
+            Some(values[1]),
+            Some(values[1]),
+            None,
+            Some(values[2])
+        ]
+    );
+    for (root, leaf) in roots.iter().zip(history.iter()) {
+        assert_eq!(tree.get(root.as_ref(), &key)?, *leaf);
+    }
+    assert!(tree.gets_across_roots(&[], &key)?.is_empty());
+    assert!(tree.gets_across_roots(&roots, &key[..1]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_gets_reading_nodes_at_once() -> Result<()> {
+    let keys = random_hashes(500);
+    let missing = random_hashes(100);
+    let queries: Vec<Hash> = [&keys[..], &missing[..], &keys[..10]].concat();
+    let db = LatencyDB::new("latency");
+    let counter = Arc::clone(&db.cold);
+    let mut tree = Monotree::<_, Blake3>::with_db(db);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let expected: Vec<Option<Hash>> = queries
+        .iter()
+        .map(|key| tree.get(root.as_ref(), key))
+        .collect::<Result<_>>()?;
+    let reads = counter.swap(0, Ordering::SeqCst);
+
+    // each node is read only once, however many keys walk through it
+    assert_eq!(tree.gets(root.as_ref(), &queries)?, expected);
+    assert!(counter.load(Ordering::SeqCst) * 2 < reads);
+    assert_eq!(tree.gets(None, &queries)?, vec![None; queries.len()]);
+
+    #[cfg(feature = "db_rocksdb")]
+    {
+        let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+        let _g = scopeguard::guard((), |_| {
+            if fs::metadata(&dbname).is_ok() {
+                fs::remove_dir_all(&dbname).unwrap()
+            }
+        });
+        let mut tree = Monotree::<RocksDB, Blake3>::new(&dbname);
+        assert_eq!(tree.inserts(None, &keys, &keys)?, root);
+        let mut reader = Monotree::<RocksDB, Blake3>::new(&dbname);
+        assert_eq!(reader.gets(root.as_ref(), &queries)?, expected);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_depth() -> Result<()> {
+    let mut tree = Monotree::default();
+    assert_eq!(tree.depth(None)?, 0);
+    let prefix = random_hash();
+    let key = |last: u8| -> Hash {
+        let mut key = prefix;
+        key[HASH_LEN - 1] = last;
+        key
+    };
+    let root = tree.insert(None, &key(0x00), &prefix)?;
+    assert_eq!(tree.depth(root.as_ref())?, 1);
+    let root = tree.insert(root.as_ref(), &key(0x01), &prefix)?;
+    assert_eq!(tree.depth(root.as_ref())?, 2);
+
+    // keys of a long common prefix are compressed into a single hop
+    let keys: Vec<Hash> = (0..16).map(key).collect();
+    let root = tree.inserts(None, &keys, &keys)?;
+    assert_eq!(tree.depth(root.as_ref())?, 5);
+
+    // whereas keys diverging all the way make a deeper tree
+    let keys = random_hashes(1000);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let depth = tree.depth(root.as_ref())?;
+    assert!(depth >= 10);
+    let mut steps: Vec<usize> = Vec::new();
+    for key in keys.iter() {
+        steps.push(tree.get_merkle_proof(root.as_ref(), key)?.unwrap().len());
+    }
+    assert_eq!(steps.into_iter().max(), Some(depth));
+    Ok(())
+}
+
+#[test]
+fn test_storage_stats() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(100);
+    assert_eq!(tree.storage_stats(None)?, StorageStats::default());
+
+    let root = tree.insert(None, &keys[0], &keys[0])?;
+    let stats = tree.storage_stats(root.as_ref())?;
+    assert_eq!(
+        (stats.node_count, stats.soft_nodes, stats.hard_nodes),
+        (1, 1, 0)
+    );
+
+    let root = tree.inserts(root.as_ref(), &keys, &keys)?;
+    let stats = tree.storage_stats(root.as_ref())?;
+    let nodes = tree.update_proof(None, root.as_ref())?.nodes;
+    let distinct: std::collections::HashSet<&Vec<u8>> = nodes.iter().collect();
+    assert_eq!(stats.node_count, distinct.len());
+    assert_eq!(stats.soft_nodes + stats.hard_nodes, stats.node_count);
+    assert_eq!(stats.hard_nodes, keys.len() - 1);
+    assert_eq!(
+        stats.total_bytes,
+        nodes.iter().map(|node| node.len()).sum::<usize>()
+    );
+
+    let mut compact = Monotree::default().with_compact_node_encoding(true);
+    let root = compact.inserts(None, &keys, &keys)?;
+    let compact_stats = compact.storage_stats(root.as_ref())?;
+    assert_eq!(compact_stats.node_count, stats.node_count);
+    assert!(compact_stats.total_bytes < stats.total_bytes);
+    Ok(())
+}
+
+#[test]
+fn test_debug_tree() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(50);
+    assert_eq!(tree.debug_tree(None)?, "(empty)\n");
+
+    let root = tree.inserts(None, &keys, &keys)?;
+    let rendered = tree.debug_tree(root.as_ref())?;
+    let stats = tree.storage_stats(root.as_ref())?;
+    let lines: Vec<&str> = rendered.lines().collect();
+    let count = |kind: &str| {
+        lines
+            .iter()
+            .filter(|line| line.trim_start_matches([' ', '|']).starts_with(kind))
+            .count()
+    };
+    assert!(rendered.starts_with(&format!("Hard {}", &hex!(root.unwrap())[..8])));
+    assert_eq!(count("Hard "), stats.hard_nodes);
+    assert_eq!(count("Soft "), stats.soft_nodes);
+    assert_eq!(
+        lines.iter().filter(|line| line.ends_with("(leaf)")).count(),
+        keys.len()
+    );
+    for key in keys.iter() {
+        assert!(rendered.contains(&format!("-> {} (leaf)", &hex!(key)[..8])));
+    }
+
+    // a node referring to itself is rendered once rather than looping forever
+    let looped = random_hash();
+    let bits = Bits::new(&looped).shift(1, true);
+    let node: Node = Node::new(
+        Some(Unit {
+            hash: &looped,
+            bits,
+        }),
+        None,
+    );
+    let mut db = MemoryDB::new("looped");
+    Database::put(&mut db, &looped, node.to_bytes()?)?;
+    let mut tree = Monotree::<MemoryDB, Blake3>::with_db(db);
+    let rendered = tree.debug_tree(Some(&looped))?;
+    assert_eq!(rendered.lines().count(), 2);
+    assert!(rendered.ends_with("(cycle)\n"));
+    Ok(())
+}
+
+#[test]
+fn test_subtree_root() -> Result<()> {
+    // keys of a shard beginning with the bits of `0b1010`, and the ones not even of `0b101`
+    let shard: Vec<Hash> = random_hashes(50)
+        .into_iter()
+        .map(|mut key| {
+            key[0] = 0xa0 | (key[0] & 0x0f);
+            key
+        })
+        .collect();
+    let others: Vec<Hash> = random_hashes(50)
+        .into_iter()
+        .filter(|key| key[0] >> 5 != 0x05)
+        .collect();
+    let byte = [0xa0];
+    let prefix = Bits::new(&byte).shift(4, true);
+
+    let mut tree = Monotree::default();
+    let root = tree.inserts(None, &shard, &shard)?;
+    let expected = tree.subtree_root(root.as_ref(), &prefix)?;
+    assert!(expected.is_some());
+
+    // the same whatever else the tree has, even with paths compressed differently
+    let full = tree.inserts(root.as_ref(), &others, &others)?;
+    assert_eq!(tree.subtree_root(full.as_ref(), &prefix)?, expected);
+    let entries = [&shard[..], &others[..1]].concat();
+    let few = tree.inserts(None, &entries, &entries)?;
+    assert_eq!(tree.subtree_root(few.as_ref(), &prefix)?, expected);
+
+    // a prefix ending in the middle of the path of a cell, rather than where the tree branches
+    let short = Bits::new(&byte).shift(3, true);
+    let mid = tree.subtree_root(root.as_ref(), &short)?;
+    assert!(mid.is_some() && mid != expected);
+    assert_eq!(tree.subtree_root(full.as_ref(), &short)?, mid);
+    assert_eq!(tree.subtree_root(few.as_ref(), &short)?, mid);
+
+    // any change in the shard changes the hash, while the ones out of it do not
+    let changed = tree.insert(full.as_ref(), &shard[0], &random_hash())?;
+    assert_ne!(tree.subtree_root(changed.as_ref(), &prefix)?, expected);
+    let changed = tree.remove(full.as_ref(), &others[0])?;
+    assert_eq!(tree.subtree_root(changed.as_ref(), &prefix)?, expected);
+
+    // prefixes consuming the whole key or nothing, or out of the tree
+    let key = Bits::new(&shard[0]);
+    assert_eq!(tree.subtree_root(full.as_ref(), &key)?, Some(shard[0]));
+    assert_eq!(tree.subtree_root(full.as_ref(), &Bits::new(&[]))?, full);
+    assert_eq!(
+        tree.subtree_root(root.as_ref(), &Bits::new(&others[0][..1]))?,
+        None
+    );
+    assert_eq!(tree.subtree_root(None, &prefix)?, None);
+    Ok(())
+}
+
+#[test]
+fn test_scan_prefix() -> Result<()> {
+    let mut tree = Monotree::default();
+    let mut keys = random_hashes(300);
+    let root = tree.inserts(None, &keys, &keys)?;
+    keys.sort();
+    let all: Vec<(Hash, Hash)> = keys.iter().map(|key| (*key, *key)).collect();
+    assert_eq!(tree.scan_prefix(root.as_ref(), &[], 0)?, all);
+    assert_eq!(tree.scan_prefix(root.as_ref(), &[0xff], 0)?, all);
+
+    // prefixes ending in the middle of a byte, in whole bytes, and of whole keys
+    for &(prefix, prefix_bits) in [(0x00u8, 1), (0xa0, 3), (0xa0, 4), (0x5c, 7), (0x5c, 8)].iter() {
+        let expected: Vec<(Hash, Hash)> = all
+            .iter()
+            .filter(|(key, _)| (key[0] ^ prefix) >> (8 - prefix_bits) == 0)
+            .cloned()
+            .collect();
+        assert_eq!(
+            tree.scan_prefix(root.as_ref(), &[prefix], prefix_bits)?,
+            expected
+        );
+    }
+    assert_eq!(
+        tree.scan_prefix(root.as_ref(), &keys[0], 256)?,
+        vec![(keys[0], keys[0])]
+    );
+    assert_eq!(
+        tree.scan_prefix(root.as_ref(), &[0x5c], 8)?,
+        tree.leaves_under(root.as_ref(), &[0x5c])?
+    );
+    assert!(tree.scan_prefix(root.as_ref(), &[0x5c], 9).is_err());
+    assert!(tree.scan_prefix(None, &[], 0)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_diff() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(500);
+    let leaves = random_hashes(500);
+    let old = tree.inserts(None, &keys[..400], &leaves[..400])?;
+    assert!(tree.diff(old.as_ref(), old.as_ref())?.is_empty());
+
+    // add 100 keys, remove 50 keys and change 50 leaves
+    let changed = random_hashes(50);
+    let new = tree.inserts(old.as_ref(), &keys[400..], &leaves[400..])?;
+    let new = tree.removes(new.as_ref(), &keys[..50])?;
+    let new = tree.inserts(new.as_ref(), &keys[50..100], &changed)?;
+
+    let mut expected: Vec<(Hash, DiffKind)> = Vec::new();
+    expected.extend(keys[..50].iter().map(|k| (*k, DiffKind::Removed)));
+    expected.extend(
+        keys[50..100]
+            .iter()
+            .zip(changed.iter())
+            .map(|(k, l)| (*k, DiffKind::Changed(*l))),
+    );
+    expected.extend(
+        keys[400..]
+            .iter()
+            .zip(leaves[400..].iter())
+            .map(|(k, l)| (*k, DiffKind::Added(*l))),
+    );
+    expected.sort_by_key(|entry| entry.0);
+    assert_eq!(tree.diff(old.as_ref(), new.as_ref())?, expected);
+
+    // diff from or to an empty tree lists every entry
+    let all = tree.diff(None, new.as_ref())?;
+    assert_eq!(all.len(), 450);
+    assert!(all
+        .iter()
+        .all(|(_, kind)| matches!(kind, DiffKind::Added(_))));
+    let all = tree.diff(new.as_ref(), None)?;
+    assert!(all.iter().all(|(_, kind)| *kind == DiffKind::Removed));
+    assert!(tree.diff(None, None)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_proof_of_empty_right_segment() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let mut keyed = tree
+        .get_keyed_proof(root.as_ref(), &keys[0])?
+        .expect("keyed proof");
+
+    // a crafted proof of a single right-side step with nothing to cut
+    let crafted: Proof = vec![(true, vec![])];
+    let mut tampered = keyed.proof.clone();
+    let last = tampered.len() - 1;
+    tampered[last] = (true, vec![]);
+    for proof in [crafted, tampered].iter() {
+        assert!(!verify_proof(&hasher, root.as_ref(), &keys[0], Some(proof)));
+        assert!(verify_proof_detailed(&hasher, root.as_ref(), &keys[0], Some(proof)).is_err());
+        assert!(!verify_domain_separated_proof(
+            &hasher,
+            root.as_ref(),
+            &keys[0],
+            Some(proof)
+        ));
+        assert!(compute_root_from_proof(&hasher, &keys[0], proof).is_err());
+        assert_eq!(
+            verify_proofs(&hasher, root.as_ref(), &[(keys[0], proof)]),
+            vec![false]
+        );
+        keyed.proof = proof.clone();
+        assert!(!verify_keyed_proof(&hasher, root.as_ref(), &keyed));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify_proof_detailed() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(100);
+    let root = tree.inserts(None, &keys, &keys)?;
+    let proof = tree.get_merkle_proof(root.as_ref(), &keys[0])?;
+    verify_proof_detailed(&hasher, root.as_ref(), &keys[0], proof.as_ref())?;
+
+    let reason = |result: Result<()>| result.unwrap_err().to_string();
+    assert_eq!(
+        reason(verify_proof_detailed(
+            &hasher,
+            root.as_ref(),
+            &keys[0],
+            None
+        )),
+        "empty proof"
+    );
+    assert_eq!(
+        reason(verify_proof_detailed(
+            &hasher,
+            Some(&keys[0]),
+            &keys[0],
+            Some(&Vec::new())
+        )),
+        "empty proof"
+    );
+    assert!(reason(verify_proof_detailed(
+        &hasher,
+        root.as_ref(),
+        &keys[1],
+        proof.as_ref()
+    ))
+    .starts_with(&format!(
+        "root mismatch: expected {} got ",
+        hex!(root.unwrap())
+    )));
+    assert!(reason(verify_proof_detailed(
+        &hasher,
+        None,
+        &keys[0],
+        proof.as_ref()
+    ))
+    .starts_with("root mismatch: expected none got "));
+
+    let mut malformed = proof.unwrap();
+    malformed[1].1.clear();
+    assert_eq!(
+        reason(verify_proof_detailed(
+            &hasher,
+            root.as_ref(),
+            &keys[0],
+            Some(&malformed)
+        )),
+        "malformed proof segment at index 1"
+    );
+    assert!(!verify_proof(
+        &hasher,
+        root.as_ref(),
+        &keys[0],
+        Some(&malformed)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_update_proof() -> Result<()> {
+    let mut tree = Monotree::default();
+    let hasher = Blake3::new();
+    let keys = random_hashes(1000);
+    let old = tree.inserts(None, &keys[..900], &keys[..900])?;
+    let new = tree.inserts(old.as_ref(), &keys[900..], &keys[900..])?;
+    let new = tree.removes(new.as_ref(), &keys[..10])?;
+
+    // a light client syncs the old root from scratch, then catches up the new one
+    let mut partial = MemoryDB::new("partial");
+    let full = tree.update_proof(None, old.as_ref())?;
+    assert_eq!(apply_update(&hasher, &mut partial, &full)?, old);
+    let update = tree.update_proof(old.as_ref(), new.as_ref())?;
+    assert!(update.nodes.len() * 2 < full.nodes.len());
+    assert_eq!(apply_update(&hasher, &mut partial, &update)?, new);
+
+    let mut client = Monotree::<MemoryDB, Blake3>::with_db(partial);
+    assert_eq!(client.len(new.as_ref())?, 990);
+    for key in keys[10..].iter() {
+        assert_eq!(client.get(new.as_ref(), key)?, Some(*key));
+    }
+
+    // nothing to update without changes, while no root is found with nodes missing
+    assert!(tree
+        .update_proof(new.as_ref(), new.as_ref())?
+        .nodes
+        .is_empty());
+    let mut empty = MemoryDB::new("empty");
+    assert!(apply_update(&hasher, &mut empty, &update).is_ok());
+    let mut empty = MemoryDB::new("empty");
+    let stale = UpdateProof {
+        root: update.root,
+        nodes: Vec::new(),
+    };
+    assert!(apply_update(&hasher, &mut empty, &stale).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_export_import_subtree() -> Result<()> {
+    let keys = random_hashes(100);
+    for &domain in [false, true].iter() {
+        let mut tree = Monotree::default().with_domain_separation(domain);
+        let root = tree.inserts(None, &keys, &keys)?.unwrap();
+        let blob = tree.export_subtree(&root)?;
+
+        // any corruption of the blob is rejected with nothing written
+        let mut other = Monotree::default().with_domain_separation(domain);
+        for i in 0..blob.len() {
+            let mut corrupted = blob.clone();
+            corrupted[i] ^= 0x01;
+            assert!(other.import_subtree(&corrupted).is_err());
+        }
+        assert!(other.import_subtree(&blob[..blob.len() - 1]).is_err());
+        assert!(other
+            .import_subtree(&[&blob[..], &[0x00]].concat())
+            .is_err());
+        assert!(other.get(Some(&root), &keys[0]).is_err());
+
+        // so is a blob of a tree in the other form
+        let mut wrong = Monotree::default().with_domain_separation(!domain);
+        assert!(wrong.import_subtree(&blob).is_err());
+
+        assert_eq!(other.import_subtree(&blob)?, root);
+        assert_eq!(other.len(Some(&root))?, 100);
+        for key in keys.iter() {
+            assert_eq!(other.get(Some(&root), key)?, Some(*key));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "db_rocksdb")]
+#[test]
+fn test_rocksdb_small_cache() -> Result<()> {
+    let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+    let _g = scopeguard::guard((), |_| {
+        if fs::metadata(&dbname).is_ok() {
+            fs::remove_dir_all(&dbname).unwrap()
+        }
+    });
+    let keys = random_hashes(1000);
+    let mut db = RocksDB::new(&dbname);
+    db.set_cache_capacity(16);
+    let mut tree = Monotree::<RocksDB, Blake3>::with_db(db);
+
+    // nodes written in a batch are read back regardless of the capacity
+    let root = tree.inserts(None, &keys, &keys)?;
+    for key in keys.iter() {
+        assert_eq!(tree.get(root.as_ref(), key)?, Some(*key));
+    }
+    let root = tree.removes(root.as_ref(), &keys[..500])?;
+    assert_eq!(tree.len(root.as_ref())?, 500);
+    Ok(())
+}
+
+#[cfg(feature = "db_sled")]
+#[test]
+fn test_sled_no_cache() -> Result<()> {
+    let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+    let _g = scopeguard::guard((), |_| {
+        if fs::metadata(&dbname).is_ok() {
+            fs::remove_dir_all(&dbname).unwrap()
+        }
+    });
+    let keys = random_hashes(1000);
+    let db = Sled::with_cache_capacity(&dbname, 0);
+    let mut tree = Monotree::<Sled, Blake3>::with_db(db);
+
+    // every read goes to the database, with the same results
+    let root = tree.inserts(None, &keys, &keys)?;
+    for key in keys.iter() {
+        assert_eq!(tree.get(root.as_ref(), key)?, Some(*key));
+    }
+    let root = tree.removes(root.as_ref(), &keys[..500])?;
+    assert_eq!(tree.len(root.as_ref())?, 500);
+    for key in keys[500..].iter() {
+        assert_eq!(tree.get(root.as_ref(), key)?, Some(*key));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dyn_database() -> Result<()> {
+    let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+    let _g = scopeguard::guard((), |_| {
+        if fs::metadata(&dbname).is_ok() {
+            fs::remove_dir_all(&dbname).unwrap()
+        }
+    });
+    let keys = random_hashes(100);
+
+    // trees of backends chosen at runtime are held together
+    let kinds = std::iter::once("memory")
+        .chain(cfg!(feature = "db_rocksdb").then_some("rocksdb"))
+        .chain(cfg!(feature = "db_sled").then_some("sled"))
+        .chain(cfg!(feature = "db_redb").then_some("redb"))
+        .chain(cfg!(feature = "db_lmdb").then_some("lmdb"));
+    let mut trees: Vec<Monotree<DynDatabase>> = Vec::new();
+    for kind in kinds {
+        trees.push(Monotree::open(kind, &format!("{}/{}", dbname, kind))?);
+    }
+    trees.push(Monotree::with_db(DynDatabase::with_db(MemoryDB::new(
+        "memory",
+    ))));
+    let roots: Vec<Option<Hash>> = trees
+        .iter_mut()
+        .map(|tree| tree.inserts(None, &keys, &keys))
+        .collect::<Result<_>>()?;
+    assert!(roots.windows(2).all(|x| x[0] == x[1]));
+    for (tree, root) in trees.iter_mut().zip(roots.iter()) {
+        for key in keys.iter() {
+            assert_eq!(tree.get(root.as_ref(), key)?, Some(*key));
+        }
+    }
+    assert!(Monotree::<DynDatabase>::open("unknown", &dbname).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "db_rocksdb")]
+#[test]
+fn test_rocksdb_checkpoint_readonly() -> Result<()> {
+    let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+    let checkpoint = format!("{}-checkpoint", dbname);
+    let _g = scopeguard::guard((), |_| {
+        for path in [&dbname, &checkpoint].iter() {
+            if fs::metadata(path).is_ok() {
+                fs::remove_dir_all(path).unwrap()
+            }
+        }
+    });
+    let keys = random_hashes(200);
+    let db = RocksDB::new(&dbname);
+    let cursor = db.cursor();
+    let mut tree = Monotree::<RocksDB, Blake3>::with_db(db);
+    let root = tree.inserts(None, &keys[..100], &keys[..100])?;
+    cursor.checkpoint(&checkpoint)?;
+
+    // writes continue on the live tree
+    let new = tree.inserts(root.as_ref(), &keys[100..], &keys[100..])?;
+    assert_eq!(tree.len(new.as_ref())?, 200);
+
+    // while the checkpoint serves the frozen state
+    let hasher = Blake3::new();
+    let mut frozen = Monotree::<RocksDB, Blake3>::open_readonly(&checkpoint)?;
+    for key in keys[..100].iter() {
+        let proof = frozen.get_merkle_proof(root.as_ref(), key)?;
+        assert!(verify_proof(&hasher, root.as_ref(), key, proof.as_ref()));
+    }
+    assert!(frozen.get(new.as_ref(), &keys[0]).is_err());
+    assert!(frozen
+        .insert(root.as_ref(), &keys[100], &keys[100])
+        .is_err());
+    assert!(frozen.remove(root.as_ref(), &keys[0]).is_err());
+    assert!(frozen.set_headroot(root.as_ref()).is_err());
+    assert!(Monotree::<MemoryDB, Blake3>::open_readonly("memory").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_headroot_ns() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(200);
+    let data = tree.inserts(None, &keys[..100], &keys[..100])?;
+    let proofs = tree.inserts(None, &keys[100..], &keys[100..])?;
+    assert_eq!(tree.get_headroot_ns("data")?, None);
+
+    tree.set_headroot_ns("data", data.as_ref())?;
+    tree.set_headroot_ns("proofs", proofs.as_ref())?;
+    tree.set_headroot(data.as_ref())?;
+    assert_eq!(tree.get_headroot_ns("data")?, data);
+    assert_eq!(tree.get_headroot_ns("proofs")?, proofs);
+
+    tree.set_headroot_ns("proofs", None)?;
+    assert_eq!(tree.get_headroot_ns("proofs")?, None);
+    assert_eq!(tree.get_headroot_ns("data")?, data);
+    assert_eq!(tree.get_headroot()?, data);
+    Ok(())
+}
+
+#[test]
+fn test_prune_keeps_headroots_ns() -> Result<()> {
+    let mut tree = Monotree::default();
+    let keys = random_hashes(300);
+    let data = tree.inserts(None, &keys[..100], &keys[..100])?;
+    let proofs = tree.inserts(None, &keys[100..200], &keys[100..200])?;
+    let stale = tree.inserts(None, &keys[200..], &keys[200..])?;
+    tree.set_headroot_ns("data", data.as_ref())?;
+    tree.set_headroot_ns("proofs", proofs.as_ref())?;
+    assert_eq!(tree.namespaces()?, vec!["data", "proofs"]);
+
+    // trees of both heads are kept, while the one of no head is gone
+    assert!(tree.prune(&[])? > 0);
+    assert_eq!(tree.prune(&[])?, 0);
+    assert_eq!(tree.get_headroot_ns("data")?, data);
+    assert_eq!(tree.get_headroot_ns("proofs")?, proofs);
+    for key in keys[..100].iter() {
+        assert_eq!(tree.get(data.as_ref(), key)?, Some(*key));
+    }
+    for key in keys[100..200].iter() {
+        assert_eq!(tree.get(proofs.as_ref(), key)?, Some(*key));
+    }
+    assert!(tree.get(stale.as_ref(), &keys[200]).is_err());
+
+    // dropping a head, its tree is gone as well
+    tree.set_headroot_ns("proofs", None)?;
+    assert!(tree.prune(&[])? > 0);
+    assert!(tree.get(proofs.as_ref(), &keys[100]).is_err());
+    assert_eq!(tree.len(data.as_ref())?, 100);
+    Ok(())
+}
+
+#[cfg(feature = "db_rocksdb")]
+#[test]
+fn test_namespace() -> Result<()> {
+    let dbname = format!(".tmp/{}", hex!(random_bytes(4)));
+    let _g = scopeguard::guard((), |_| {
+        if fs::metadata(&dbname).is_ok() {
+            fs::remove_dir_all(&dbname).unwrap()
+        }
+    });
+    let keys = random_hashes(100);
+    let root = {
+        let mut tree = Monotree::<RocksDB, Blake3>::with_namespace(&dbname, b"data::");
+        let root = tree.inserts(None, &keys, &keys)?;
+        tree.set_headroot(root.as_ref())?;
+        root
+    };
+
+    // trees in other namespaces never see the nodes nor the headroot
+    {
+        let mut tree = Monotree::<RocksDB, Blake3>::with_namespace(&dbname, b"proofs::");
+        assert_eq!(tree.get_headroot()?, None);
+        assert!(tree.get(root.as_ref(), &keys[0]).is_err());
+
+        // the same entries are stored on their own, which are pruned independently
+        assert_eq!(tree.inserts(None, &keys, &keys)?, root);
+        assert!(tree.prune(&[])? > 0);
+        assert!(tree.get(root.as_ref(), &keys[0]).is_err());
+    }
+    {
+        let mut tree = Monotree::<RocksDB, Blake3>::new(&dbname);
+        assert_eq!(tree.get_headroot()?, None);
+        assert!(tree.get(root.as_ref(), &keys[0]).is_err());
+    }
+
+    let mut tree = Monotree::<RocksDB, Blake3>::with_namespace(&dbname, b"data::");
+    assert_eq!(tree.get_headroot()?, root);
+    for key in keys.iter() {
+        assert_eq!(tree.get(root.as_ref(), key)?, Some(*key));
+    }
+
+    // clearing a namespace leaves the others as they are
+    {
+        let mut tree = Monotree::<RocksDB, Blake3>::with_namespace(&dbname, b"proofs::");
+        tree.inserts(None, &keys, &keys)?;
+        tree.clear_all()?;
+        assert!(tree.get(root.as_ref(), &keys[0]).is_err());
+    }
+    assert_eq!(tree.get_headroot()?, root);
+    assert_eq!(tree.get(root.as_ref(), &keys[0])?, Some(keys[0]));
+    Ok(())
+}